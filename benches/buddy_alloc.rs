@@ -8,7 +8,11 @@ use {
     std::alloc::{Allocator, Layout},
 };
 
-use buddy_alloc::buddy_alloc::{BuddyAlloc, BuddyAllocParam};
+use buddy_alloc::{
+    buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    fill::{FillAlloc, FillPatterns},
+    seal::SealAlloc,
+};
 
 const HEAP_SIZE: usize = 64 * 1024 * 1024; // 64 MB
 const ALLOC_SIZE: usize = 32 * 1024 * 1024;
@@ -31,7 +35,7 @@ fn bench_alloc(allocator: &mut BuddyAlloc, alloc_size: usize) {
     }
 }
 
-fn bench_alloc_then_free(allocator: &mut BuddyAlloc, alloc_size: usize) {
+fn bench_alloc_then_free<A: Allocator>(allocator: &A, alloc_size: usize) {
     let count = ALLOC_SIZE / alloc_size;
     let layout = Layout::from_size_align(alloc_size, 1).unwrap();
     let mut ptrs = Vec::with_capacity(count);
@@ -59,10 +63,35 @@ fn bench(c: &mut Criterion) {
         let count = ALLOC_SIZE / size;
         group.throughput(Throughput::Elements(count as u64));
         group.bench_with_input(format!("{} Bytes", size), &size, |b, &size| {
-            with_allocator(|mut allocator| b.iter(|| bench_alloc_then_free(&mut allocator, size)));
+            with_allocator(|allocator| b.iter(|| bench_alloc_then_free(&allocator, size)));
         });
     }
     group.finish();
+
+    // wrappers with their instrumentation turned off should cost nothing
+    let size = 64;
+    let mut group = c.benchmark_group("instrumentation off");
+    group.throughput(Throughput::Elements((ALLOC_SIZE / size) as u64));
+    group.bench_function("bare", |b| {
+        with_allocator(|allocator| b.iter(|| bench_alloc_then_free(&allocator, size)));
+    });
+    group.bench_function("fill", |b| {
+        let patterns = FillPatterns {
+            on_alloc: None,
+            on_free: None,
+        };
+        with_allocator(|allocator| {
+            let allocator = FillAlloc::new(allocator, patterns);
+            b.iter(|| bench_alloc_then_free(&allocator, size))
+        });
+    });
+    group.bench_function("seal", |b| {
+        with_allocator(|allocator| {
+            let allocator = SealAlloc::new(allocator);
+            b.iter(|| bench_alloc_then_free(&allocator, size))
+        });
+    });
+    group.finish();
 }
 
 criterion_group!(
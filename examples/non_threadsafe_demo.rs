@@ -9,14 +9,18 @@ pub static mut HEAP: [u8; BUDDY_HEAP_SIZE] = [0u8; BUDDY_HEAP_SIZE];
 
 // This allocator can't work in tests since it's non-threadsafe.
 #[cfg_attr(not(test), global_allocator)]
-static ALLOC: NonThreadsafeAlloc = unsafe {
-    let freelist_param = FreelistAllocParam::new(FREELIST_HEAP.as_ptr(), FREELIST_HEAP_SIZE);
-    let buddy_param = BuddyAllocParam::new(HEAP.as_ptr(), BUDDY_HEAP_SIZE, LEAF_SIZE);
+static ALLOC: NonThreadsafeAlloc = {
+    let freelist_param = FreelistAllocParam::new(
+        core::ptr::addr_of!(FREELIST_HEAP).cast(),
+        FREELIST_HEAP_SIZE,
+    );
+    let buddy_param =
+        BuddyAllocParam::new(core::ptr::addr_of!(HEAP).cast(), BUDDY_HEAP_SIZE, LEAF_SIZE);
     NonThreadsafeAlloc::new(freelist_param, buddy_param)
 };
 
 fn main() {
-    let v = vec![0u8; 42];
+    let v = vec![0u8; 42].into_boxed_slice();
     let msg = "alloc success".to_string();
     println!("{} {:?}", msg, v.len());
 }
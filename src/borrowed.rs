@@ -0,0 +1,117 @@
+//! Borrowed heap
+//! A heap borrowing its buffer, so the buffer outlives the allocator.
+//!
+//! BuddyAlloc and FreelistAlloc take raw addresses and nothing ties them
+//! to the memory they hand out. BorrowedHeap holds the `&'a mut [u8]` the
+//! heap was built over, so the buffer can't be dropped, moved or touched
+//! while the heap is alive, and neither can collections allocating from it.
+
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        freelist_alloc::{FreelistAlloc, FreelistAllocParam},
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        marker::PhantomData,
+        ops::Range,
+        ptr::NonNull,
+    },
+};
+
+/// BorrowedHeap
+/// a heap over a buffer borrowed for `'a`
+pub struct BorrowedHeap<'a, A> {
+    inner: A,
+    _buf: PhantomData<&'a mut [u8]>,
+}
+
+/// a BuddyAlloc borrowing its buffer
+pub type BorrowedBuddyAlloc<'a> = BorrowedHeap<'a, BuddyAlloc>;
+/// a FreelistAlloc borrowing its buffer
+pub type BorrowedFreelistAlloc<'a> = BorrowedHeap<'a, FreelistAlloc>;
+
+impl<'a> BorrowedHeap<'a, BuddyAlloc> {
+    /// see BuddyAllocParam::new for `leaf_size`,
+    /// panics like BuddyAlloc::new if `buf` can't hold the heap
+    pub fn new(buf: &'a mut [u8], leaf_size: usize) -> Self {
+        let param = BuddyAllocParam::new(buf.as_mut_ptr(), buf.len(), leaf_size);
+        BorrowedHeap {
+            // the buffer is exclusively ours for `'a`
+            inner: unsafe { BuddyAlloc::new(param) },
+            _buf: PhantomData,
+        }
+    }
+}
+
+impl<'a> BorrowedHeap<'a, FreelistAlloc> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        let param = FreelistAllocParam::new(buf.as_mut_ptr(), buf.len());
+        BorrowedHeap {
+            // the buffer is exclusively ours for `'a`
+            inner: unsafe { FreelistAlloc::new(param) },
+            _buf: PhantomData,
+        }
+    }
+}
+
+impl<A> BorrowedHeap<'_, A> {
+    /// the heap over the buffer
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A: HeapRange> HeapRange for BorrowedHeap<'_, A> {
+    fn heap_range(&self) -> Range<usize> {
+        self.inner.heap_range()
+    }
+}
+
+impl<A: HeapInspect> HeapInspect for BorrowedHeap<'_, A> {
+    unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        self.inner.alloc_size(p)
+    }
+
+    fn stats(&self) -> HeapStats {
+        self.inner.stats()
+    }
+
+    fn validate(&self) -> Result<(), Corruption> {
+        self.inner.validate()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for BorrowedHeap<'_, A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.allocate(layout)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.shrink(ptr, old_layout, new_layout)
+    }
+}
@@ -6,33 +6,67 @@
 
 #![allow(clippy::needless_range_loop)]
 
-use core::{
-    alloc::{AllocError, Allocator, Layout},
-    ptr::NonNull,
+use {
+    crate::{
+        error::Error,
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
+        memory_map::usable_ranges,
+        SliceSize,
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        mem::MaybeUninit,
+        ops::Range,
+        ptr::NonNull,
+    },
 };
 
+#[cfg(feature = "free-fill")]
+use crate::fill::FREE_FILL;
+#[cfg(feature = "stats")]
+use crate::inspect::Counters;
+#[cfg(feature = "double-free")]
+use crate::inspect::{double_free_panic, DoubleFreeHandler};
+#[cfg(any(feature = "stats", feature = "free-fill"))]
+use core::cell::Cell;
+
+pub mod atomic;
+
 const OOM_MSG: &str = "requires more memory space to initialize BuddyAlloc";
-const LEAF_ALIGN_ERROR_MSG: &str = "leaf size must be aligned to 16 bytes";
+pub(crate) const LEAF_ALIGN_ERROR_MSG: &str = "leaf size must be aligned to 16 bytes";
+const HEAP_SIZE_ERROR_MSG: &str = "heap too small to hold its metadata and one leaf";
+const METADATA_SIZE_ERROR_MSG: &str = "metadata buffer smaller than metadata_size";
+#[cfg(feature = "free-fill")]
+const FREE_MODE_ERROR_MSG: &str = "zero on free and poison on free exclude each other";
+#[cfg(feature = "free-fill")]
+const POISON_ERROR_MSG: &str = "free block written, use after free";
+const DETERMINISTIC_ALIGN_ERROR_MSG: &str = "deterministic alignment must be a power of two";
 /// required to align to 16 bytes, since Node takes 16 bytes on 64-bits machine.
-pub const MIN_LEAF_SIZE_ALIGN: usize = 16;
+/// Platforms with larger pointers, like CHERI capabilities, need room for a whole Node.
+pub const MIN_LEAF_SIZE_ALIGN: usize = if core::mem::size_of::<Node>() > 16 {
+    core::mem::size_of::<Node>()
+} else {
+    16
+};
 
 pub const fn block_size(k: usize, leaf_size: usize) -> usize {
     (1 << k) * leaf_size
 }
 
-const fn block_size_2base(k: usize, leaf2base: usize) -> usize {
+pub(crate) const fn block_size_2base(k: usize, leaf2base: usize) -> usize {
     (1 << k) << leaf2base
 }
 
-const fn nblock(k: usize, entries_size: usize) -> usize {
+pub(crate) const fn nblock(k: usize, entries_size: usize) -> usize {
     1 << (entries_size - k - 1)
 }
 
-const fn roundup(n: usize, sz2base: usize) -> usize {
+pub(crate) const fn roundup(n: usize, sz2base: usize) -> usize {
     (((n - 1) >> sz2base) + 1) << sz2base
 }
 
-fn log2(mut n: usize) -> usize {
+pub(crate) const fn log2(mut n: usize) -> usize {
     let mut k = 0;
     while n > 1 {
         k += 1;
@@ -41,7 +75,7 @@ fn log2(mut n: usize) -> usize {
     k
 }
 
-fn bit_isset(bit_array: *const u8, i: usize) -> bool {
+pub(crate) const fn bit_isset(bit_array: *const u8, i: usize) -> bool {
     unsafe {
         let b = bit_array.add(i >> 3);
         let m = 1 << (i % 8);
@@ -49,7 +83,7 @@ fn bit_isset(bit_array: *const u8, i: usize) -> bool {
     }
 }
 
-fn bit_set(bit_array: *mut u8, i: usize) {
+pub(crate) const fn bit_set(bit_array: *mut u8, i: usize) {
     unsafe {
         let b = bit_array.add(i >> 3);
         let m = 1 << (i % 8);
@@ -57,7 +91,7 @@ fn bit_set(bit_array: *mut u8, i: usize) {
     }
 }
 
-fn bit_clear(bit_array: *mut u8, i: usize) {
+pub(crate) const fn bit_clear(bit_array: *mut u8, i: usize) {
     debug_assert!(bit_isset(bit_array, i));
     unsafe {
         let b = bit_array.add(i >> 3);
@@ -66,13 +100,48 @@ fn bit_clear(bit_array: *mut u8, i: usize) {
     }
 }
 
+/// Bytes of metadata BuddyAlloc keeps for a `len` bytes heap,
+/// not counting the padding that aligns the heap to `leaf_size`.
+pub const fn metadata_size(len: usize, leaf_size: usize) -> usize {
+    let entries_size = log2(len >> log2(leaf_size)) + 2;
+    let mut size = (core::mem::size_of::<Entry>() + core::mem::size_of::<Node>()) * entries_size;
+    let mut k = 0;
+    while k < entries_size {
+        // alloc bitmap, and split bitmap above order 0
+        let bitmap_size = roundup(nblock(k, entries_size), 3) >> 3;
+        size += if k == 0 { bitmap_size } else { bitmap_size * 2 };
+        k += 1;
+    }
+    size
+}
+
+/// Whether `len` bytes hold the metadata and at least one leaf,
+/// the heap start and the end of metadata may both need aligning.
+pub(crate) const fn holds_heap(len: usize, leaf_size: usize) -> bool {
+    len >= metadata_size(len, leaf_size) + 3 * leaf_size
+}
+
+/// address of the order k bitmap, in the bitmaps laid out from order `first` at `addr`
+const fn bitmap_addr(mut addr: usize, first: usize, k: usize, entries_size: usize) -> usize {
+    let mut j = first;
+    while j < k {
+        addr += roundup(nblock(j, entries_size), 3) >> 3;
+        j += 1;
+    }
+    addr
+}
+
 // find a min k that is greater than n bytes
 pub fn first_up_k(n: usize, leaf_size: usize) -> usize {
     let mut k = 0;
     let mut size = leaf_size;
     while size < n {
         k += 1;
-        size <<= 1;
+        // past the largest power of two, no order is big enough
+        let Some(next) = size.checked_mul(2) else {
+            break;
+        };
+        size = next;
     }
     k
 }
@@ -83,13 +152,6 @@ struct Node {
 }
 
 impl Node {
-    fn init(list: *mut Node) {
-        unsafe {
-            (*list).next = list;
-            (*list).prev = list;
-        }
-    }
-
     fn remove(list: *mut Node) {
         unsafe {
             (*(*list).prev).next = (*list).next;
@@ -111,14 +173,14 @@ impl Node {
                 prev: list,
                 next: (*list).next,
             };
-            p.write_unaligned(n_list);
+            p.write(n_list);
             (*(*list).next).prev = p;
             (*list).next = p;
         }
     }
 
     fn is_empty(list: *const Node) -> bool {
-        unsafe { (*list).next as *const Node == list }
+        unsafe { core::ptr::eq((*list).next, list) }
     }
 }
 
@@ -148,248 +210,1095 @@ pub struct BuddyAllocParam {
     len: usize,
     /// Leaf size: the min size to allocate
     leaf_size: usize,
+    /// Slice size: length reported by allocate
+    slice_size: SliceSize,
+    /// Metadata addr: separate metadata buffer, null keeps it in the heap
+    metadata_addr: *const u8,
+    /// Metadata len: bytes of the metadata buffer
+    metadata_len: usize,
+    /// Deterministic align: base alignment of deterministic offsets, 0 if off
+    deterministic_align: usize,
+    /// Zeroed: the heap memory is all zero to begin with
+    #[cfg(feature = "free-fill")]
+    zeroed: bool,
+    /// Zero on free: freed blocks get zeroed
+    #[cfg(feature = "free-fill")]
+    zero_on_free: bool,
+    /// Poison on free: freed blocks get FREE_FILL, checked when handed out
+    #[cfg(feature = "free-fill")]
+    poison_on_free: bool,
+    /// Deferred coalescing: freed blocks merge on coalesce or coalesce_some only
+    deferred_coalescing: bool,
+    /// Double free: called on frees of free blocks
+    #[cfg(feature = "double-free")]
+    double_free: DoubleFreeHandler,
 }
 
+// only describes the memory, BuddyAlloc::new takes it over
+unsafe impl Send for BuddyAllocParam {}
+
 impl BuddyAllocParam {
     /// Base addr: the start address
     /// Len: available bytes from the start address
     /// Leaf size: the min size to allocate
+    ///
+    /// Invalid sizes panic, at compile time when evaluated in a const or static:
+    ///
+    /// ```compile_fail
+    /// # use buddy_alloc::BuddyAllocParam;
+    /// const PARAM: BuddyAllocParam = BuddyAllocParam::new(core::ptr::null(), 4096, 24);
+    /// let _ = PARAM;
+    /// ```
     pub const fn new(base_addr: *const u8, len: usize, leaf_size: usize) -> Self {
+        assert!(
+            leaf_size.is_multiple_of(MIN_LEAF_SIZE_ALIGN) && leaf_size != 0,
+            "{}",
+            LEAF_ALIGN_ERROR_MSG
+        );
+        assert!(holds_heap(len, leaf_size), "{}", HEAP_SIZE_ERROR_MSG);
+        BuddyAllocParam {
+            base_addr,
+            len,
+            leaf_size,
+            slice_size: SliceSize::Requested,
+            metadata_addr: core::ptr::null(),
+            metadata_len: 0,
+            deterministic_align: 0,
+            #[cfg(feature = "free-fill")]
+            zeroed: false,
+            #[cfg(feature = "free-fill")]
+            zero_on_free: false,
+            #[cfg(feature = "free-fill")]
+            poison_on_free: false,
+            deferred_coalescing: false,
+            #[cfg(feature = "double-free")]
+            double_free: double_free_panic,
+        }
+    }
+
+    /// Like new, but the metadata lives in `metadata_addr..(metadata_addr + metadata_len)`
+    /// and the whole heap range is left for blocks, see `metadata_size` and `buddy_heap!`.
+    /// The metadata buffer must be aligned to `usize`.
+    pub const fn new_with_metadata(
+        base_addr: *const u8,
+        len: usize,
+        leaf_size: usize,
+        metadata_addr: *const u8,
+        metadata_len: usize,
+    ) -> Self {
+        assert!(
+            leaf_size.is_multiple_of(MIN_LEAF_SIZE_ALIGN) && leaf_size != 0,
+            "{}",
+            LEAF_ALIGN_ERROR_MSG
+        );
+        assert!(len >= 2 * leaf_size, "{}", HEAP_SIZE_ERROR_MSG);
+        assert!(
+            metadata_len >= metadata_size(len, leaf_size),
+            "{}",
+            METADATA_SIZE_ERROR_MSG
+        );
         BuddyAllocParam {
             base_addr,
             len,
             leaf_size,
+            slice_size: SliceSize::Requested,
+            metadata_addr,
+            metadata_len,
+            deterministic_align: 0,
+            #[cfg(feature = "free-fill")]
+            zeroed: false,
+            #[cfg(feature = "free-fill")]
+            zero_on_free: false,
+            #[cfg(feature = "free-fill")]
+            poison_on_free: false,
+            deferred_coalescing: false,
+            #[cfg(feature = "double-free")]
+            double_free: double_free_panic,
+        }
+    }
+
+    /// Slice size: whether allocate reports the requested or the block size
+    pub const fn with_slice_size(mut self, slice_size: SliceSize) -> Self {
+        self.slice_size = slice_size;
+        self
+    }
+
+    /// Deterministic offsets: align the first block to `max_align`, so the same
+    /// operations return blocks at the same offsets from the heap base wherever
+    /// the heap lives, see BuddyAlloc::offset_of.
+    /// The heap is trimmed to a length that doesn't depend on the address, so about
+    /// `max_align` bytes go unused, and larger alignments fail to allocate.
+    pub const fn with_deterministic_offsets(mut self, max_align: usize) -> Self {
+        assert!(
+            max_align.is_power_of_two(),
+            "{}",
+            DETERMINISTIC_ALIGN_ERROR_MSG
+        );
+        self.deterministic_align = max_align;
+        self
+    }
+
+    /// Zeroed: the heap memory is all zero, e.g. in .bss,
+    /// so allocate_zeroed skips clearing blocks never handed out.
+    #[cfg(feature = "free-fill")]
+    pub const fn with_zeroed_memory(mut self, zeroed: bool) -> Self {
+        self.zeroed = zeroed;
+        self
+    }
+
+    /// Zero on free: clear blocks as they are freed, so allocate_zeroed
+    /// only clears the free list node. BuddyAlloc::new clears the heap first
+    /// unless it is zeroed already.
+    #[cfg(feature = "free-fill")]
+    pub const fn with_zero_on_free(mut self, zero_on_free: bool) -> Self {
+        self.zero_on_free = zero_on_free;
+        self
+    }
+
+    /// Poison on free: fill blocks with fill::FREE_FILL as they are freed, and panic
+    /// when a block handed out doesn't hold it anymore past its free list
+    /// node, catching writes after free. BuddyAlloc::new poisons the heap first.
+    /// Every allocation reads its whole block, it's meant for debugging.
+    /// Not for VmAlloc, decommitted pages come back zeroed.
+    #[cfg(feature = "free-fill")]
+    pub const fn with_poison_on_free(mut self, poison_on_free: bool) -> Self {
+        self.poison_on_free = poison_on_free;
+        self
+    }
+
+    /// Deferred coalescing: free only puts the block back on its free list,
+    /// merging buddies is left to BuddyAlloc::coalesce_some. An allocation
+    /// that finds no large enough block merges everything first.
+    pub const fn with_deferred_coalescing(mut self, deferred: bool) -> Self {
+        self.deferred_coalescing = deferred;
+        self
+    }
+
+    /// Double free: call `handler` instead of freeing a block that is free
+    /// already, the default panics
+    #[cfg(feature = "double-free")]
+    pub const fn with_double_free_handler(mut self, handler: DoubleFreeHandler) -> Self {
+        self.double_free = handler;
+        self
+    }
+}
+
+/// Declare a `$heap_size` bytes heap buffer and a metadata buffer of exactly
+/// `metadata_size` bytes, evaluating to the BuddyAllocParam wiring them up.
+/// Either buffer can be placed in its own linker section,
+/// e.g. the metadata in fast RAM and the heap in SDRAM:
+///
+/// ```ignore
+/// static ALLOC: NonThreadsafeAlloc = NonThreadsafeAlloc::new(
+///     freelist_param,
+///     buddy_heap!(8 << 20, 64, heap_section = ".sdram", metadata_section = ".dtcm"),
+/// );
+/// ```
+///
+/// The heap buffer is aligned to STATIC_HEAP_ALIGN, the largest leaf size
+/// it takes, so every byte of it is used. Each expansion declares its own
+/// buffers, so use it once per heap.
+#[macro_export]
+macro_rules! buddy_heap {
+    ($heap_size:expr, $leaf_size:expr
+        $(, heap_section = $heap_section:literal)?
+        $(, metadata_section = $metadata_section:literal)? $(,)?) => {{
+        const METADATA_WORDS: usize = $crate::buddy_alloc::metadata_size($heap_size, $leaf_size)
+            .div_ceil(core::mem::size_of::<usize>());
+        const _: () = assert!(
+            $leaf_size <= $crate::buddy_alloc::STATIC_HEAP_ALIGN,
+            "leaf size larger than STATIC_HEAP_ALIGN"
+        );
+        $(#[link_section = $heap_section])?
+        static mut HEAP: $crate::buddy_alloc::StaticHeapBytes<{ $heap_size }> =
+            $crate::buddy_alloc::StaticHeapBytes::zeroed();
+        $(#[link_section = $metadata_section])?
+        static mut METADATA: [usize; METADATA_WORDS] = [0; METADATA_WORDS];
+        $crate::buddy_alloc::BuddyAllocParam::new_with_metadata(
+            core::ptr::addr_of!(HEAP).cast(),
+            $heap_size,
+            $leaf_size,
+            core::ptr::addr_of!(METADATA).cast(),
+            METADATA_WORDS * core::mem::size_of::<usize>(),
+        )
+    }};
+}
+
+/// Allocations that got a larger block because of their alignment,
+/// per block order k
+#[cfg(feature = "stats")]
+pub struct AlignStats {
+    rounded: [Cell<usize>; usize::BITS as usize],
+    wasted_bytes: [Cell<usize>; usize::BITS as usize],
+}
+
+#[cfg(feature = "stats")]
+impl AlignStats {
+    const fn new() -> Self {
+        AlignStats {
+            rounded: [const { Cell::new(0) }; usize::BITS as usize],
+            wasted_bytes: [const { Cell::new(0) }; usize::BITS as usize],
+        }
+    }
+
+    fn record(&self, k: usize, size_k: usize, leaf2base: usize) {
+        if k > size_k {
+            let wasted = block_size_2base(k, leaf2base) - block_size_2base(size_k, leaf2base);
+            self.rounded[k].set(self.rounded[k].get() + 1);
+            self.wasted_bytes[k].set(self.wasted_bytes[k].get() + wasted);
         }
     }
+
+    /// number of order k allocations rounded up for alignment
+    pub fn rounded(&self, k: usize) -> usize {
+        self.rounded[k].get()
+    }
+
+    /// bytes spent on alignment by order k allocations
+    pub fn wasted_bytes(&self, k: usize) -> usize {
+        self.wasted_bytes[k].get()
+    }
+
+    /// number of allocations rounded up for alignment
+    pub fn total_rounded(&self) -> usize {
+        self.rounded.iter().map(Cell::get).sum()
+    }
+
+    /// bytes spent on alignment
+    pub fn total_wasted_bytes(&self) -> usize {
+        self.wasted_bytes.iter().map(Cell::get).sum()
+    }
 }
 
 pub struct BuddyAlloc {
-    /// memory start addr
-    base_addr: usize,
-    /// memory end addr
-    end_addr: usize,
-    /// unavailable memories at end_addr
+    /// the region pointer passed in, every pointer is derived from it
+    region: *mut u8,
+    /// memory start, past the metadata kept in front of the heap
+    base: *mut u8,
+    /// memory end
+    end: *mut u8,
+    /// unavailable memories at end
     unavailable: usize,
     entries: *mut Entry,
     entries_size: usize,
     /// min size of a block, represent in 1 << leaf2base
     leaf2base: usize,
+    slice_size: SliceSize,
+    /// alignments up to 1 << this are relative to base_addr only, None if off
+    deterministic2base: Option<u8>,
+    /// freed blocks wait for coalesce or coalesce_some to merge
+    deferred_coalescing: bool,
+    /// pattern for freed blocks, zero on free or FREE_FILL
+    #[cfg(feature = "free-fill")]
+    free_fill: Option<u8>,
+    #[cfg(feature = "double-free")]
+    double_free: DoubleFreeHandler,
+    /// blocks starting at or past this addr are zero, but for their free list node
+    #[cfg(feature = "free-fill")]
+    clean_from: Cell<usize>,
+    #[cfg(feature = "stats")]
+    align_stats: AlignStats,
+    #[cfg(feature = "stats")]
+    counters: Counters,
 }
 
+// the heap owns its memory, moving it moves every pointer into it
+unsafe impl Send for BuddyAlloc {}
+
 impl BuddyAlloc {
     /// # Safety
     ///
     /// The `base_addr..(base_addr + len)` must be allocated before using,
     /// and must guarantee no others write to the memory range, to avoid undefined behaviors.
     /// The new function panic if memory space not enough for initialize BuddyAlloc.
-    pub unsafe fn new(param: BuddyAllocParam) -> Self {
+    pub unsafe fn new(#[allow(unused_mut)] mut param: BuddyAllocParam) -> Self {
+        let heap = param.base_addr.cast_mut();
+        #[cfg(feature = "free-fill")]
+        {
+            assert!(
+                !(param.zero_on_free && param.poison_on_free),
+                "{}",
+                FREE_MODE_ERROR_MSG
+            );
+            if param.zero_on_free && !param.zeroed {
+                heap.write_bytes(0, param.len);
+                param.zeroed = true;
+            }
+            if param.poison_on_free {
+                heap.write_bytes(FREE_FILL, param.len);
+                param.zeroed = false;
+            }
+        }
+        let metadata = param.metadata_addr.cast_mut();
+        debug_assert!(metadata.cast::<Entry>().is_aligned(), "misalignment");
+        Self::build(param, heap, heap.addr(), metadata, metadata.addr(), true)
+    }
+
+    /// Safe new over a buffer nobody else can reach, e.g. from `uninit_region!`,
+    /// see BuddyAllocParam::new for `leaf_size`
+    pub fn from_static(buf: &'static mut [MaybeUninit<u8>], leaf_size: usize) -> Self {
+        let param = BuddyAllocParam::new(buf.as_mut_ptr().cast(), buf.len(), leaf_size);
+        // the buffer is exclusively ours for the rest of the program
+        unsafe { Self::new(param) }
+    }
+
+    /// Build one heap spanning every usable `(addr, len)` range of a memory
+    /// map, the gaps between them are marked allocated and never written.
+    /// Ranges may come in any order and overlap. The metadata for the whole
+    /// span goes to the first range holding it, panics if none does.
+    ///
+    /// # Safety
+    ///
+    /// The ranges must be mapped, exposed and used by nothing else.
+    pub unsafe fn from_ranges<I: IntoIterator<Item = (usize, usize)>>(
+        ranges: I,
+        leaf_size: usize,
+    ) -> Self {
+        let (ranges, n) = usable_ranges(ranges.into_iter().map(|(addr, len)| (addr, len, true)));
+        assert!(n > 0, "{}", OOM_MSG);
+        let ranges = &ranges[..n];
+        let (start, end) = (ranges[0].0, ranges[n - 1].1);
+        let metadata_len = metadata_size(end - start, leaf_size);
+        let metadata = ranges
+            .iter()
+            .find_map(|&(range_start, range_end)| {
+                let addr = range_start.next_multiple_of(core::mem::align_of::<usize>());
+                (addr + metadata_len <= range_end).then_some(addr)
+            })
+            .expect(OOM_MSG);
+        let param = BuddyAllocParam::new_with_metadata(
+            core::ptr::with_exposed_provenance(start),
+            end - start,
+            leaf_size,
+            core::ptr::with_exposed_provenance(metadata),
+            metadata_len,
+        );
+        let heap = Self::new_unlinked(param);
+        let gaps = ranges
+            .windows(2)
+            .map(|w| (w[0].1, w[1].0))
+            .chain([(metadata, metadata + metadata_len)]);
+        for (gap_start, gap_end) in gaps {
+            heap.reserve_gap(gap_start, gap_end);
+        }
+        heap.rebuild_free_lists();
+        heap
+    }
+
+    /// Mark the leaves overlapping `start..end` allocated for good,
+    /// in the bitmaps only, before the free lists are rebuilt.
+    fn reserve_gap(&self, start: usize, end: usize) {
+        let base = self.base_addr();
+        let limit = base + self.available_bytes();
+        let mut addr = base + (((start.max(base) - base) >> self.leaf2base) << self.leaf2base);
+        let end = (base + roundup(end.max(base + 1) - base, self.leaf2base)).min(limit);
+        while addr < end {
+            // the largest block starting at addr inside the gap
+            let offset = addr - base;
+            let mut k = 0;
+            while k + 2 < self.entries_size {
+                let size = block_size_2base(k + 1, self.leaf2base);
+                if !offset.is_multiple_of(size) || addr + size > end {
+                    break;
+                }
+                k += 1;
+            }
+            self.reserve_block(k, offset >> k >> self.leaf2base);
+            addr += block_size_2base(k, self.leaf2base);
+        }
+    }
+
+    /// mark block i of order k allocated, splitting its ancestors down to it
+    fn reserve_block(&self, k: usize, i: usize) {
+        if k > 0 && bit_isset(self.entry(k).split, i) {
+            // made of smaller blocks already
+            self.reserve_block(k - 1, 2 * i);
+            self.reserve_block(k - 1, 2 * i + 1);
+            return;
+        }
+        for j in (k + 1)..self.entries_size {
+            let i = i >> (j - k);
+            if bit_isset(self.entry(j).split, i) {
+                break;
+            }
+            if bit_isset(self.entry(j).alloc, i) {
+                // inside a block reserved whole already, splitting it would free the rest
+                return;
+            }
+        }
+        bit_set(self.entry(k).alloc, i);
+        for j in (k + 1)..self.entries_size {
+            let i = i >> (j - k);
+            if bit_isset(self.entry(j).split, i) {
+                break;
+            }
+            bit_set(self.entry(j).alloc, i);
+            bit_set(self.entry(j).split, i);
+        }
+    }
+
+    /// Donate `len` bytes at `addr` after construction, e.g. memory a
+    /// bootloader reports late. The range must lie in a gap of the span the
+    /// heap covers, left allocated by from_ranges, so a heap expecting more
+    /// memory spans it from the start: `from_ranges` with an empty range at
+    /// the span end. Leaves only partly inside the range stay reserved.
+    /// Fails with SizeUnsupported if the range is outside the heap or holds no whole leaf.
+    ///
+    /// # Safety
+    ///
+    /// The range must be mapped, exposed and used by nothing else,
+    /// and hold no allocation handed out by the heap.
+    pub unsafe fn add_region(&self, addr: *const u8, len: usize) -> Result<(), Error> {
+        let base = self.base_addr();
+        let limit = base + self.available_bytes();
+        let start = addr.addr();
+        let end = start.checked_add(len).ok_or(Error::SizeUnsupported)?;
+        if start < base || end > limit {
+            return Err(Error::SizeUnsupported);
+        }
+        let mut addr = base + (start - base).next_multiple_of(1 << self.leaf2base);
+        let end = base + (((end - base) >> self.leaf2base) << self.leaf2base);
+        if addr >= end {
+            return Err(Error::SizeUnsupported);
+        }
+        while addr < end {
+            // the largest block starting at addr inside the range
+            let offset = addr - base;
+            let mut k = 0;
+            while k + 2 < self.entries_size {
+                let size = block_size_2base(k + 1, self.leaf2base);
+                if !offset.is_multiple_of(size) || addr + size > end {
+                    break;
+                }
+                k += 1;
+            }
+            self.release_block(k, offset >> k >> self.leaf2base);
+            addr += block_size_2base(k, self.leaf2base);
+        }
+        Ok(())
+    }
+
+    /// free the reserved block i of order k, splitting a block reserved whole around it
+    unsafe fn release_block(&self, k: usize, i: usize) {
+        if k > 0 && bit_isset(self.entry(k).split, i) {
+            // made of smaller blocks
+            self.release_block(k - 1, 2 * i);
+            self.release_block(k - 1, 2 * i + 1);
+            return;
+        }
+        if !bit_isset(self.entry(k).alloc, i) {
+            let Some(j) = ((k + 1)..self.entries_size)
+                .find(|&j| bit_isset(self.entry(j).alloc, i >> (j - k)))
+            else {
+                return;
+            };
+            if bit_isset(self.entry(j).split, i >> (j - k)) {
+                // free already
+                return;
+            }
+            // reserved whole at order j, split it down to block i
+            for m in ((k + 1)..=j).rev() {
+                let parent = i >> (m - k);
+                bit_set(self.entry(m).split, parent);
+                bit_set(self.entry(m - 1).alloc, 2 * parent);
+                bit_set(self.entry(m - 1).alloc, 2 * parent + 1);
+            }
+        }
+        self.release(k, self.ptr(self.block_addr(k, i)));
+    }
+
+    /// Like new, but the free lists are left empty and the heap memory untouched,
+    /// to be filled by rebuild_free_lists once the bitmaps are restored.
+    pub(crate) unsafe fn new_unlinked(param: BuddyAllocParam) -> Self {
+        let heap = param.base_addr.cast_mut();
+        let metadata = param.metadata_addr.cast_mut();
+        Self::build(param, heap, heap.addr(), metadata, metadata.addr(), false)
+    }
+
+    /// Lay out the metadata and free lists of the heap `param` describes,
+    /// with the heap at `heap_addr` and the metadata buffer, if any, at `meta_addr`.
+    /// Without `link_free` the free lists stay empty and the heap isn't written.
+    ///
+    /// Memory is written through `heap_w` and `meta_w` while every pointer stored
+    /// is derived from the ones in `param`. Both are the same at runtime; a const
+    /// initializer writes a local copy of the static it links to, see StaticBuddyHeap.
+    const unsafe fn build(
+        param: BuddyAllocParam,
+        heap_w: *mut u8,
+        heap_addr: usize,
+        meta_w: *mut u8,
+        meta_addr: usize,
+        link_free: bool,
+    ) -> Self {
         let BuddyAllocParam {
-            base_addr,
+            base_addr: heap_t,
             len,
             leaf_size,
+            slice_size,
+            metadata_addr,
+            metadata_len,
+            deterministic_align,
+            #[cfg(feature = "free-fill")]
+            zeroed,
+            #[cfg(feature = "free-fill")]
+            zero_on_free,
+            #[cfg(feature = "free-fill")]
+            poison_on_free,
+            deferred_coalescing,
+            #[cfg(feature = "double-free")]
+            double_free,
         } = param;
-        let mut base_addr = base_addr as usize;
-        let end_addr = base_addr + len;
+        let heap_t = heap_t.cast_mut();
+        let end_addr = heap_addr + len;
         assert!(
-            leaf_size % MIN_LEAF_SIZE_ALIGN == 0 && leaf_size != 0,
+            leaf_size.is_multiple_of(MIN_LEAF_SIZE_ALIGN) && leaf_size != 0,
             "{}",
             LEAF_ALIGN_ERROR_MSG
         );
         let leaf2base = log2(leaf_size);
-        base_addr = roundup(base_addr, leaf2base);
+        let mut base_addr = roundup(heap_addr, leaf2base);
         // we use (k + 1)-th entry's split flag to test existence of k-th entry's blocks;
         // to accoding this convention, we make a dummy (entries_size - 1)-th entry.
         // so we plus 2 on entries_size.
         let entries_size = log2((end_addr - base_addr) >> leaf2base) + 2;
 
+        // metadata goes to its own buffer if given, otherwise in front of the heap
+        let external = !metadata_addr.is_null();
+        let (meta_w, meta_t, meta_start, meta_end) = if external {
+            (
+                meta_w,
+                metadata_addr.cast_mut(),
+                meta_addr,
+                meta_addr + metadata_len,
+            )
+        } else {
+            (heap_w, heap_t, heap_addr, end_addr)
+        };
+        let mut meta_addr = if external { meta_start } else { base_addr };
+
         // alloc buddy allocator memory
         let used_bytes = core::mem::size_of::<Entry>() * entries_size;
-        debug_assert!(end_addr >= base_addr + used_bytes, "{}", OOM_MSG);
-        let entries = base_addr as *mut Entry;
-        base_addr += used_bytes;
+        debug_assert!(meta_end >= meta_addr + used_bytes, "{}", OOM_MSG);
+        let entries_w = meta_w.wrapping_add(meta_addr - meta_start).cast::<Entry>();
+        let entries = meta_t.wrapping_add(meta_addr - meta_start).cast::<Entry>();
+        meta_addr += used_bytes;
 
         let buddy_list_size = core::mem::size_of::<Node>();
+        let nodes_addr = meta_addr;
         // init entries free
-        for k in 0..entries_size {
+        let mut k = 0;
+        while k < entries_size {
             // use one bit for per memory block
-            debug_assert!(end_addr >= base_addr + buddy_list_size, "{}", OOM_MSG);
-            let entry = entries.add(k).as_mut().expect("entry");
-            entry.free = base_addr as *mut Node;
-            core::ptr::write_bytes(entry.free, 0, buddy_list_size);
-            Node::init(entry.free);
-            base_addr += buddy_list_size;
+            debug_assert!(meta_end >= meta_addr + buddy_list_size, "{}", OOM_MSG);
+            let free = meta_t.wrapping_add(meta_addr - meta_start).cast::<Node>();
+            entries_w.add(k).write(Entry {
+                free,
+                alloc: core::ptr::null_mut(),
+                split: core::ptr::null_mut(),
+            });
+            meta_w
+                .wrapping_add(meta_addr - meta_start)
+                .cast::<Node>()
+                .write(Node {
+                    next: free,
+                    prev: free,
+                });
+            meta_addr += buddy_list_size;
+            k += 1;
         }
 
         // init alloc
-        for k in 0..entries_size {
+        let alloc_addr = meta_addr;
+        let mut k = 0;
+        while k < entries_size {
             // use one bit for per memory block
             // use shift instead `/`, 8 == 1 << 3
             let used_bytes = roundup(nblock(k, entries_size), 3) >> 3;
-            debug_assert!(end_addr >= base_addr + used_bytes, "{}", OOM_MSG);
-            let entry = entries.add(k).as_mut().expect("entry");
-            entry.alloc = base_addr as *mut u8;
+            debug_assert!(meta_end >= meta_addr + used_bytes, "{}", OOM_MSG);
+            (*entries_w.add(k)).alloc = meta_t.wrapping_add(meta_addr - meta_start);
             // mark all blocks as allocated
-            core::ptr::write_bytes(entry.alloc, 0, used_bytes);
-            base_addr += used_bytes;
+            core::ptr::write_bytes(meta_w.wrapping_add(meta_addr - meta_start), 0, used_bytes);
+            meta_addr += used_bytes;
+            k += 1;
         }
 
         // init split
-        for k in 1..entries_size {
+        let split_addr = meta_addr;
+        let mut k = 1;
+        while k < entries_size {
             // use one bit for per memory block
             // use shift instead `/`, 8 == 1 << 3
             let used_bytes = roundup(nblock(k, entries_size), 3) >> 3;
-            debug_assert!(end_addr >= base_addr + used_bytes, "{}", OOM_MSG);
-            let entry = entries.add(k).as_mut().expect("entry");
-            entry.split = base_addr as *mut u8;
-            core::ptr::write_bytes(entry.split, 0, used_bytes);
-            base_addr += used_bytes;
+            debug_assert!(meta_end >= meta_addr + used_bytes, "{}", OOM_MSG);
+            (*entries_w.add(k)).split = meta_t.wrapping_add(meta_addr - meta_start);
+            core::ptr::write_bytes(meta_w.wrapping_add(meta_addr - meta_start), 0, used_bytes);
+            meta_addr += used_bytes;
+            k += 1;
+        }
+        assert!(meta_end >= meta_addr, "{}", OOM_MSG);
+        if !external {
+            base_addr = meta_addr;
         }
 
-        // align base_addr to leaf size
-        base_addr = roundup(base_addr, leaf2base);
+        // align base_addr to leaf size, or the deterministic alignment
+        let base2base = if deterministic_align > leaf_size {
+            log2(deterministic_align)
+        } else {
+            leaf2base
+        };
+        base_addr = roundup(base_addr, base2base);
         assert!(end_addr >= base_addr, "{}", OOM_MSG);
-        debug_assert_eq!(
-            (base_addr >> leaf2base) << leaf2base,
-            base_addr,
+        let end_addr = if deterministic_align != 0 {
+            // the same length wherever the heap lives, past the worst case padding
+            let metadata = if external {
+                0
+            } else {
+                metadata_size(len, leaf_size)
+            };
+            let reserved = metadata + deterministic_align + leaf_size;
+            assert!(len >= reserved, "{}", OOM_MSG);
+            base_addr + (len - reserved)
+        } else {
+            end_addr
+        };
+        debug_assert!(
+            (base_addr >> leaf2base) << leaf2base == base_addr,
             "misalignment"
         );
 
-        let mut allocator = BuddyAlloc {
-            base_addr,
-            end_addr,
-            entries,
-            entries_size,
-            leaf2base,
-            unavailable: 0,
-        };
-        allocator.init_free_list();
-        allocator
-    }
-
-    fn init_free_list(&mut self) {
-        let mut base_addr = self.base_addr;
-        let end_addr = self.end_addr;
-        let entries_size = self.entries_size;
-
+        let block_base = base_addr;
         // try alloc blocks
-        for k in (0..(entries_size - 1)).rev() {
-            let block_size = block_size_2base(k, self.leaf2base);
-            let entry = self.entry(k);
-            let parent_entry = self.entry(k + 1);
+        let mut k = entries_size - 1;
+        while k > 0 {
+            k -= 1;
+            let block_size = block_size_2base(k, leaf2base);
+            let head_addr = nodes_addr + buddy_list_size * k;
+            let head_w = meta_w.wrapping_add(head_addr - meta_start).cast::<Node>();
+            let head_t = meta_t.wrapping_add(head_addr - meta_start).cast::<Node>();
+            // the block heading the free list, pushed last
+            let mut first: Option<usize> = None;
 
             // alloc free blocks
             while base_addr + block_size <= end_addr {
-                debug_assert!(!bit_isset(
-                    entry.alloc,
-                    self.block_index(k, base_addr as *const u8)
-                ));
-                Node::push(entry.free, base_addr as *mut u8);
+                let block_w = heap_w.wrapping_add(base_addr - heap_addr).cast::<Node>();
+                let block_t = heap_t.wrapping_add(base_addr - heap_addr).cast::<Node>();
+                if link_free {
+                    let (next_w, next_t) = match first {
+                        Some(addr) => (
+                            heap_w.wrapping_add(addr - heap_addr).cast::<Node>(),
+                            heap_t.wrapping_add(addr - heap_addr).cast::<Node>(),
+                        ),
+                        None => (head_w, head_t),
+                    };
+                    block_w.write(Node {
+                        prev: head_t,
+                        next: next_t,
+                    });
+                    (*next_w).prev = block_t;
+                    (*head_w).next = block_t;
+                }
+                first = Some(base_addr);
                 // mark parent's split and alloc
-                let block_index = self.block_index(k, base_addr as *const u8);
+                let block_index = ((base_addr - block_base) >> k) >> leaf2base;
                 if block_index & 1 == 0 {
-                    let parent_index = self.block_index(k + 1, base_addr as *const u8);
-                    bit_set(parent_entry.alloc, parent_index);
-                    bit_set(parent_entry.split, parent_index);
+                    let parent_index = ((base_addr - block_base) >> (k + 1)) >> leaf2base;
+                    let alloc = bitmap_addr(alloc_addr, 0, k + 1, entries_size);
+                    let split = bitmap_addr(split_addr, 1, k + 1, entries_size);
+                    bit_set(meta_w.wrapping_add(alloc - meta_start), parent_index);
+                    bit_set(meta_w.wrapping_add(split - meta_start), parent_index);
                 }
                 base_addr += block_size;
             }
 
             // mark unavailable blocks as allocated
             let n = nblock(k, entries_size);
-            let unavailable_block_index = self.block_index(k, base_addr as *const u8);
+            let unavailable_block_index = ((base_addr - block_base) >> k) >> leaf2base;
             debug_assert!(unavailable_block_index < n);
-            bit_set(entry.alloc, unavailable_block_index);
+            let alloc = bitmap_addr(alloc_addr, 0, k, entries_size);
+            bit_set(
+                meta_w.wrapping_add(alloc - meta_start),
+                unavailable_block_index,
+            );
         }
 
-        self.unavailable = end_addr - base_addr;
+        BuddyAlloc {
+            region: heap_t,
+            base: heap_t.wrapping_add(block_base - heap_addr),
+            end: heap_t.wrapping_add(end_addr - heap_addr),
+            entries,
+            entries_size,
+            leaf2base,
+            slice_size,
+            deterministic2base: if deterministic_align != 0 {
+                Some(log2(deterministic_align) as u8)
+            } else {
+                None
+            },
+            deferred_coalescing,
+            #[cfg(feature = "free-fill")]
+            free_fill: if zero_on_free {
+                Some(0)
+            } else if poison_on_free {
+                Some(FREE_FILL)
+            } else {
+                None
+            },
+            #[cfg(feature = "free-fill")]
+            clean_from: Cell::new(if zeroed && link_free {
+                block_base
+            } else {
+                usize::MAX
+            }),
+            unavailable: end_addr - base_addr,
+            #[cfg(feature = "stats")]
+            align_stats: AlignStats::new(),
+            #[cfg(feature = "stats")]
+            counters: Counters::new(),
+            #[cfg(feature = "double-free")]
+            double_free,
+        }
     }
 
     /// available bytes
     pub fn available_bytes(&self) -> usize {
-        self.end_addr - self.unavailable - self.base_addr
+        self.end_addr() - self.unavailable - self.base_addr()
     }
 
-    fn entry(&self, i: usize) -> &Entry {
-        debug_assert!(i < self.entries_size, "index out of range");
-        unsafe { self.entries.add(i).as_ref().expect("entry") }
+    /// offset of p from the heap base, see BuddyAllocParam::with_deterministic_offsets
+    pub fn offset_of(&self, p: *const u8) -> usize {
+        p as usize - self.base_addr()
     }
 
-    /// find k for p
-    fn find_k_for_p(&self, p: *const u8) -> usize {
-        for k in 0..(self.entries_size - 1) {
-            if bit_isset(self.entry(k + 1).split, self.block_index(k + 1, p)) {
-                debug_assert!(bit_isset(self.entry(k).alloc, self.block_index(k, p)));
-                return k;
+    /// pointer `offset` bytes past the heap base
+    pub fn ptr_at(&self, offset: usize) -> *mut u8 {
+        self.base.wrapping_add(offset)
+    }
+
+    /// call `f` with the address and size of every free block,
+    /// blocks are visited from the smallest order to the largest
+    pub fn for_each_free_block<F: FnMut(usize, usize)>(&self, mut f: F) {
+        for k in 0..self.entries_size {
+            let list = self.entry(k).free;
+            let block_size = block_size_2base(k, self.leaf2base);
+            let mut node = unsafe { (*list).next };
+            while !core::ptr::eq(node, list) {
+                f(node as usize, block_size);
+                node = unsafe { (*node).next };
             }
         }
-        0
     }
 
-    /// block index of p under k
-    fn block_index(&self, k: usize, p: *const u8) -> usize {
-        if (p as usize) < self.base_addr {
-            // TODO handle this outside
-            panic!("out of memory");
+    /// Zero every free block, past the free list node it holds,
+    /// to wipe residual data without touching live allocations.
+    /// With poison on free they get FREE_FILL instead.
+    /// Returns the number of bytes zeroed.
+    pub fn scrub_free(&self) -> usize {
+        let node_size = core::mem::size_of::<Node>();
+        #[cfg(feature = "free-fill")]
+        let fill = self.free_fill.unwrap_or(0);
+        #[cfg(not(feature = "free-fill"))]
+        let fill = 0;
+        let mut scrubbed = 0;
+        self.for_each_free_block(|addr, size| {
+            let p: *mut u8 = self.ptr(addr + node_size);
+            unsafe { p.write_bytes(fill, size - node_size) };
+            scrubbed += size - node_size;
+        });
+        // keep the stores even though nothing reads the memory back
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        scrubbed
+    }
+
+    /// Allocate `size` bytes aligned to the leaf size,
+    /// returns null if out of memory. For C-style callers, without Layout.
+    pub fn malloc(&self, size: usize) -> *mut u8 {
+        match Layout::from_size_align(size, 1) {
+            Ok(layout) => self
+                .allocate(layout)
+                .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr()),
+            Err(_) => core::ptr::null_mut(),
         }
-        let n = p as usize - self.base_addr;
-        // equal to: n / block_size_2base(k, self.leaf2base);
-        let index = (n >> k) >> self.leaf2base;
-        debug_assert!(index < nblock(k, self.entries_size));
-        index
     }
 
-    /// block addr of index under k
-    fn block_addr(&self, k: usize, i: usize) -> usize {
-        // equal to: i * block_size_2base(k, self.leaf2base);
-        let n = (i << k) << self.leaf2base;
-        self.base_addr + n
+    /// Free an allocation from malloc, or any other one of this heap, null is ignored.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a live allocation of this heap.
+    pub unsafe fn free(&self, ptr: *mut u8) {
+        if !ptr.is_null() {
+            self.free_block(ptr);
+        }
     }
-}
 
-unsafe impl Allocator for BuddyAlloc {
-    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    /// whether p points into the heap, to route frees by address
+    pub fn owns(&self, p: *const u8) -> bool {
+        (self.base_addr()..self.end_addr()).contains(&(p as usize))
+    }
+
+    /// size of the block backing the live allocation at p
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this allocator and not freed yet.
+    pub unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        self.block_end(self.find_k_for_p(p), p) - p as usize
+    }
+
+    /// The free block allocate would carve `layout` out of, as address and
+    /// size, and the size of the block it hands out from that address, or
+    /// the whole free block where the alignment is past the heap base.
+    /// Halves split off in between get their node at `addr + size` for
+    /// every size up to the free block's. None if no free block fits, without
+    /// the merging deferred coalescing does. Lets VmAlloc commit the pages
+    /// the allocation writes first.
+    #[cfg(all(any(test, feature = "std"), any(unix, windows)))]
+    pub(crate) fn next_carve(&self, layout: Layout) -> Option<(usize, usize, usize)> {
+        let nbytes = layout.size();
+        let leaf_size = 1 << self.leaf2base;
+        if self.base_addr().is_multiple_of(layout.align()) {
+            let fk = first_up_k(nbytes.max(layout.align()), leaf_size);
+            let k = (fk..self.entries_size).find(|&k| !Node::is_empty(self.entry(k).free))?;
+            let block = unsafe { (*self.entry(k).free).next };
+            Some((
+                block as usize,
+                block_size_2base(k, self.leaf2base),
+                block_size_2base(fk, self.leaf2base),
+            ))
+        } else {
+            let fk = first_up_k(nbytes, leaf_size);
+            let (k, node, _) = self.find_aligned(nbytes, fk, layout.align())?;
+            let size = block_size_2base(k, self.leaf2base);
+            Some((node as usize, size, size))
+        }
+    }
+
+    /// Allocate, merging at most `budget` deferred frees if no block fits.
+    /// Fails with the number of merges done, `budget` of them means there may
+    /// be more to merge and a retry could succeed.
+    pub(crate) fn allocate_budgeted(
+        &self,
+        layout: Layout,
+        budget: usize,
+    ) -> Result<NonNull<[u8]>, usize> {
         let nbytes = layout.size();
-        // TODO: alignment!
-        let fk = first_up_k(nbytes, 1 << self.leaf2base);
-        let mut k = match (fk..self.entries_size).find(|&k| !Node::is_empty(self.entry(k).free)) {
-            Some(k) => k,
-            None => return Err(AllocError),
+        let leaf_size = 1 << self.leaf2base;
+        // blocks are aligned to their size relative to base_addr,
+        // so take a block at least as large as the alignment if that's enough,
+        // otherwise look for an aligned block inside a larger free one
+        if self.past_deterministic_align(layout.align()) {
+            return Err(0);
+        }
+        let take = || {
+            if self.base_addr().is_multiple_of(layout.align()) {
+                let fk = first_up_k(nbytes.max(layout.align()), leaf_size);
+                Some((fk, self.alloc_block(fk)?))
+            } else {
+                let fk = first_up_k(nbytes, leaf_size);
+                self.alloc_aligned(nbytes, fk, layout.align())
+            }
         };
-        let p: *mut u8 = Node::pop(self.entry(k).free) as *mut u8;
-        bit_set(self.entry(k).alloc, self.block_index(k, p));
-        while k > fk {
-            let q: *mut u8 = (p as usize + block_size_2base(k - 1, self.leaf2base)) as *mut u8;
-            bit_set(self.entry(k).split, self.block_index(k, p));
-            let parent_entry = self.entry(k - 1);
-            bit_set(parent_entry.alloc, self.block_index(k - 1, p));
-            debug_assert!(!bit_isset(parent_entry.alloc, self.block_index(k - 1, q)));
-            Node::push(parent_entry.free, q);
-            k -= 1;
+        let mut merged = 0;
+        let mut block = take();
+        if block.is_none() && self.deferred_coalescing {
+            merged = self.coalesce_some(budget);
+            if merged > 0 {
+                block = take();
+            }
         }
-        debug_assert_eq!(
-            ((p as usize) >> self.leaf2base) << self.leaf2base,
-            p as usize,
-            "misalignment"
+        let Some((fk, p)) = block else {
+            #[cfg(feature = "stats")]
+            if merged < budget {
+                self.counters.fail();
+            }
+            return Err(merged);
+        };
+        #[cfg(feature = "stats")]
+        {
+            self.align_stats
+                .record(fk, first_up_k(nbytes, leaf_size), self.leaf2base);
+            self.counters.alloc(block_size_2base(fk, self.leaf2base));
+        }
+
+        let len = match self.slice_size {
+            SliceSize::Requested => layout.size(),
+            SliceSize::Block => self.block_end(fk, p) - p as usize,
+        };
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(p) },
+            len,
+        ))
+    }
+
+    /// Take back a pointer that only kept its address, e.g. one that went
+    /// through C or another handle over the same region. The returned pointer
+    /// is derived from this heap, so deallocate and grow may use it.
+    /// Debug builds check the block is allocated and holds `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must address a live allocation of this heap made with `layout`.
+    pub unsafe fn assume_owned(&self, ptr: *const u8, layout: Layout) -> NonNull<[u8]> {
+        debug_assert!(self.heap_range().contains(&ptr.addr()), "not in this heap");
+        let p: *mut u8 = self.ptr(ptr.addr());
+        debug_assert!(
+            {
+                let k = self.block_k(p);
+                bit_isset(self.entry(k).alloc, self.block_index(k, p))
+                    && self.block_end(k, p) - p.addr() >= layout.size()
+            },
+            "not an allocated block holding the layout"
         );
+        NonNull::slice_from_raw_parts(NonNull::new_unchecked(p), layout.size())
+    }
 
+    /// Allocate `layout` at exactly `addr`, e.g. a buffer a device can only
+    /// reach at a fixed address. Takes the smallest block holding the whole
+    /// range, splitting off its other parts, and fails with OutOfMemory if any
+    /// of that block is in use. Freed like any other allocation.
+    pub fn allocate_at(&self, addr: usize, layout: Layout) -> Result<NonNull<[u8]>, Error> {
+        if !addr.is_multiple_of(layout.align()) {
+            return Err(Error::AlignmentUnsupported);
+        }
+        let end = self.end_addr() - self.unavailable;
+        if addr < self.base_addr() || addr.saturating_add(layout.size()) > end {
+            return Err(Error::SizeUnsupported);
+        }
+        let leaf_size = 1 << self.leaf2base;
+        let ak = self.holding_k(addr, layout.size(), first_up_k(layout.size(), leaf_size));
+        if ak >= self.entries_size {
+            return Err(Error::SizeUnsupported);
+        }
+        let p: *mut u8 = self.ptr(addr);
+        let free_block = || {
+            let k = self.block_k(p);
+            let block: *mut u8 = self.ptr(self.block_addr(k, self.block_index(k, p)));
+            (k >= ak && !bit_isset(self.entry(k).alloc, self.block_index(k, p)))
+                .then_some((k, block))
+        };
+        let mut found = free_block();
+        if found.is_none() && self.deferred_coalescing {
+            self.coalesce();
+            found = free_block();
+        }
+        let Some((k, block)) = found else {
+            #[cfg(feature = "stats")]
+            self.counters.fail();
+            return Err(Error::OutOfMemory);
+        };
+        Node::remove(block.cast());
+        let p = self.carve(block, k, ak, addr);
+        #[cfg(feature = "stats")]
+        self.counters.alloc(block_size_2base(ak, self.leaf2base));
+        let len = match self.slice_size {
+            SliceSize::Requested => layout.size(),
+            SliceSize::Block => self.block_end(ak, p) - addr,
+        };
         Ok(NonNull::slice_from_raw_parts(
             unsafe { NonNull::new_unchecked(p) },
-            layout.size(),
+            len,
         ))
     }
 
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
-        let mut p = ptr.as_ptr();
+    /// Resize the live allocation at p to hold `new_size` bytes without moving it,
+    /// shrinking hands the upper halves back and growing merges free buddies.
+    /// Returns false, leaving the allocation untouched, if it can't grow in place.
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this allocator and not freed yet.
+    pub unsafe fn resize_in_place(&self, p: *mut u8, new_size: usize) -> bool {
         let mut k = self.find_k_for_p(p);
-        while k < (self.entries_size - 1) {
-            let block_index = self.block_index(k, p);
-            let entry = self.entry(k);
-            bit_clear(entry.alloc, block_index);
-            let is_head = block_index & 1 == 0;
-            let buddy = if is_head {
-                block_index + 1
-            } else {
-                block_index - 1
+        if self.block_addr(k, self.block_index(k, p)) != p as usize {
+            // inside its block from an aligned allocation, keep the block
+            return new_size <= self.alloc_size(p);
+        }
+        let fk = first_up_k(new_size, 1 << self.leaf2base);
+        #[cfg(feature = "stats")]
+        let old_size = block_size_2base(k, self.leaf2base);
+        if fk > k {
+            if fk >= self.entries_size - 1 {
+                return false;
+            }
+            // p must be the head of every merged block, with a free buddy
+            let can_grow = (k..fk).all(|j| {
+                let block_index = self.block_index(j, p);
+                block_index & 1 == 0 && !bit_isset(self.entry(j).alloc, block_index + 1)
+            });
+            if !can_grow {
+                return false;
+            }
+            while k < fk {
+                let block_index = self.block_index(k, p);
+                bit_clear(self.entry(k).alloc, block_index);
+                Node::remove(self.ptr(self.block_addr(k, block_index + 1)));
+                bit_clear(self.entry(k + 1).split, self.block_index(k + 1, p));
+                k += 1;
+            }
+            #[cfg(feature = "free-fill")]
+            self.touch(self.block_end(k, p));
+        }
+        while k > fk {
+            let half = block_size_2base(k - 1, self.leaf2base);
+            let q: *mut u8 = p.wrapping_add(half);
+            #[cfg(feature = "free-fill")]
+            if let Some(fill) = self.free_fill {
+                q.write_bytes(fill, half);
+            }
+            bit_set(self.entry(k).split, self.block_index(k, p));
+            let parent_entry = self.entry(k - 1);
+            bit_set(parent_entry.alloc, self.block_index(k - 1, p));
+            Node::push(parent_entry.free, q);
+            k -= 1;
+        }
+        #[cfg(feature = "stats")]
+        {
+            let new_size = block_size_2base(k, self.leaf2base);
+            self.counters.grow(new_size.saturating_sub(old_size));
+            self.counters.shrink(old_size.saturating_sub(new_size));
+        }
+        true
+    }
+
+    /// free the block at p and merge it with its buddies,
+    /// returns address and size of the resulting free block
+    pub(crate) unsafe fn free_block(&self, p: *mut u8) -> (usize, usize) {
+        #[cfg(feature = "double-free")]
+        {
+            let k = self.block_k(p);
+            if !bit_isset(self.entry(k).alloc, self.block_index(k, p)) {
+                (self.double_free)(p);
+                return (p as usize, 0);
+            }
+        }
+        let k = self.find_k_for_p(p);
+        #[cfg(feature = "stats")]
+        self.counters.free(block_size_2base(k, self.leaf2base));
+        // aligned allocations may start inside their block
+        self.release(k, self.ptr(self.block_addr(k, self.block_index(k, p))))
+    }
+
+    /// free the allocated order k block at p and merge it with its buddies,
+    /// returns address and size of the resulting free block
+    unsafe fn release(&self, mut k: usize, mut p: *mut u8) -> (usize, usize) {
+        #[cfg(feature = "free-fill")]
+        if let Some(fill) = self.free_fill {
+            p.write_bytes(fill, block_size_2base(k, self.leaf2base));
+        }
+        if self.deferred_coalescing {
+            bit_clear(self.entry(k).alloc, self.block_index(k, p));
+            Node::push(self.entry(k).free, p);
+            return (p as usize, block_size_2base(k, self.leaf2base));
+        }
+        while k < (self.entries_size - 1) {
+            let block_index = self.block_index(k, p);
+            let entry = self.entry(k);
+            bit_clear(entry.alloc, block_index);
+            let is_head = block_index & 1 == 0;
+            let buddy = if is_head {
+                block_index + 1
+            } else {
+                block_index - 1
             };
             if bit_isset(entry.alloc, buddy) {
                 break;
@@ -399,15 +1308,917 @@ unsafe impl Allocator for BuddyAlloc {
             // 2. set p to the address of merged block
             // 3. repeat for k = k + 1 until reach MAX_K
             // 4. push p back to k entry free list
-            let q = self.block_addr(k, buddy);
-            Node::remove(q as *mut Node);
+            let q: *mut u8 = self.ptr(self.block_addr(k, buddy));
+            Node::remove(q.cast());
+            #[cfg(feature = "free-fill")]
+            self.clear_absorbed(if is_head { q } else { p });
             if !is_head {
-                p = q as *mut u8;
+                p = q;
             }
             bit_clear(self.entry(k + 1).split, self.block_index(k + 1, p));
             k += 1;
         }
         debug_assert!(!bit_isset(self.entry(k).alloc, self.block_index(k, p)));
         Node::push(self.entry(k).free, p);
+        (p as usize, block_size_2base(k, self.leaf2base))
+    }
+
+    /// Merge free buddies left apart, at most `budget` merges per call
+    /// so it fits in a cooperative or watchdog bound loop.
+    /// Returns the number of merges, fewer than `budget` once nothing is left.
+    pub fn coalesce_some(&self, budget: usize) -> usize {
+        let mut merges = 0;
+        for k in 0..(self.entries_size - 1) {
+            let list = self.entry(k).free;
+            let mut node = unsafe { (*list).next };
+            while !core::ptr::eq(node, list) {
+                if merges == budget {
+                    return merges;
+                }
+                let (next, head) = self.merge_free(k, node);
+                if let Some(head) = head {
+                    // merged blocks are scanned again at order k + 1
+                    Node::push(self.entry(k + 1).free, head);
+                    merges += 1;
+                }
+                node = next;
+            }
+        }
+        merges
+    }
+
+    /// Merge every free buddy pair left apart, e.g. in idle time.
+    /// Returns the bytes in the larger free blocks the merges made.
+    pub fn coalesce(&self) -> usize {
+        let mut bytes = 0;
+        // blocks merged by this pass wait in `fresh` until their order is
+        // scanned, those left there after it merged no further
+        let mut fresh = [const {
+            Node {
+                next: core::ptr::null_mut(),
+                prev: core::ptr::null_mut(),
+            }
+        }; 2];
+        let fresh = fresh.each_mut().map(|node| {
+            let node: *mut Node = node;
+            unsafe {
+                node.write(Node {
+                    next: node,
+                    prev: node,
+                })
+            };
+            node
+        });
+        for k in 0..self.entries_size {
+            let (this, up) = (fresh[k & 1], fresh[(k + 1) & 1]);
+            if k + 1 < self.entries_size {
+                for list in [self.entry(k).free, this] {
+                    let mut node = unsafe { (*list).next };
+                    while !core::ptr::eq(node, list) {
+                        let (next, head) = self.merge_free(k, node);
+                        if let Some(head) = head {
+                            Node::push(up, head);
+                        }
+                        node = next;
+                    }
+                }
+            }
+            while !Node::is_empty(this) {
+                let p = Node::pop(this);
+                Node::push(self.entry(k).free, p.cast());
+                bytes += block_size_2base(k, self.leaf2base);
+            }
+        }
+        bytes
+    }
+
+    /// Merge the free block `node` of order k with its buddy if that's free
+    /// too, returns the node to visit next and the merged block, out of any list.
+    fn merge_free(&self, k: usize, node: *mut Node) -> (*mut Node, Option<*mut u8>) {
+        let p: *mut u8 = node.cast();
+        let mut next = unsafe { (*node).next };
+        let block_index = self.block_index(k, p);
+        let buddy = block_index ^ 1;
+        if bit_isset(self.entry(k).alloc, buddy) {
+            return (next, None);
+        }
+        let q: *mut u8 = self.ptr(self.block_addr(k, buddy));
+        if core::ptr::eq(next, q.cast()) {
+            next = unsafe { (*next).next };
+        }
+        Node::remove(node);
+        Node::remove(q.cast());
+        let (head, _upper) = if block_index & 1 == 0 { (p, q) } else { (q, p) };
+        #[cfg(feature = "free-fill")]
+        self.clear_absorbed(_upper);
+        bit_clear(self.entry(k + 1).alloc, self.block_index(k + 1, head));
+        bit_clear(self.entry(k + 1).split, self.block_index(k + 1, head));
+        (next, Some(head))
+    }
+
+    /// The node of the upper buddy a merge absorbed ends up inside the merged
+    /// block, give it the free fill, or zero it where the block is known zero.
+    #[cfg(feature = "free-fill")]
+    fn clear_absorbed(&self, upper: *mut u8) {
+        let fill = match self.free_fill {
+            Some(fill) => fill,
+            None if upper.addr() < self.clean_from.get() => return,
+            None => 0,
+        };
+        unsafe { upper.write_bytes(fill, core::mem::size_of::<Node>()) };
+    }
+
+    /// allocate a block of order fk, splitting a larger block if needed
+    fn alloc_block(&self, fk: usize) -> Option<*mut u8> {
+        let k = (fk..self.entries_size).find(|&k| !Node::is_empty(self.entry(k).free))?;
+        let p: *mut u8 = Node::pop(self.entry(k).free) as *mut u8;
+        Some(self.split_down(p, k, fk))
+    }
+
+    /// mark the free block p of order k, out of its free list, as allocated,
+    /// splitting it down to order fk, the upper halves go to the free lists
+    fn split_down(&self, p: *mut u8, mut k: usize, fk: usize) -> *mut u8 {
+        #[cfg(feature = "free-fill")]
+        self.check_poison(p, k);
+        bit_set(self.entry(k).alloc, self.block_index(k, p));
+        while k > fk {
+            let q: *mut u8 = p.wrapping_add(block_size_2base(k - 1, self.leaf2base));
+            bit_set(self.entry(k).split, self.block_index(k, p));
+            let parent_entry = self.entry(k - 1);
+            bit_set(parent_entry.alloc, self.block_index(k - 1, p));
+            debug_assert!(!bit_isset(parent_entry.alloc, self.block_index(k - 1, q)));
+            Node::push(parent_entry.free, q);
+            k -= 1;
+        }
+        debug_assert_eq!(
+            ((p as usize) >> self.leaf2base) << self.leaf2base,
+            p as usize,
+            "misalignment"
+        );
+        #[cfg(feature = "free-fill")]
+        self.touch(self.block_end(fk, p));
+        p
+    }
+
+    /// with poison on free, panic unless the free block p of order k
+    /// holds FREE_FILL past its node
+    #[cfg(feature = "free-fill")]
+    fn check_poison(&self, p: *const u8, k: usize) {
+        if self.free_fill != Some(FREE_FILL) {
+            return;
+        }
+        let node_size = core::mem::size_of::<Node>();
+        let size = block_size_2base(k, self.leaf2base);
+        let block = unsafe { core::slice::from_raw_parts(p.add(node_size), size - node_size) };
+        if let Some(i) = block.iter().position(|&b| b != FREE_FILL) {
+            panic!("{} at {:#x}", POISON_ERROR_MSG, p as usize + node_size + i);
+        }
+    }
+
+    /// Note the allocated memory up to end may be dirtied,
+    /// nothing to note when freed blocks get zeroed.
+    #[cfg(feature = "free-fill")]
+    fn touch(&self, end: usize) {
+        if self.free_fill != Some(0) && end > self.clean_from.get() {
+            self.clean_from.set(end);
+        }
+    }
+
+    /// whether `align` is past the deterministic alignment, which fails
+    fn past_deterministic_align(&self, align: usize) -> bool {
+        self.deterministic2base
+            .is_some_and(|shift| align > 1 << shift)
+    }
+
+    /// Move the live allocation at ptr to the lowest addressed free block that
+    /// fits it, copying its contents, and return the new allocation.
+    /// The primitive for cooperative defragmentation. Fails with OutOfMemory
+    /// when no free block below it fits, and with AlignmentUnsupported when
+    /// `layout` is aligned past the heap base, the allocation stays put then.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this heap made with `layout`,
+    /// and nothing may access it through the old pointer after a move.
+    pub unsafe fn migrate(&self, ptr: NonNull<u8>, layout: Layout) -> Result<NonNull<[u8]>, Error> {
+        if !self.base_addr().is_multiple_of(layout.align()) {
+            return Err(Error::AlignmentUnsupported);
+        }
+        let fk = first_up_k(layout.size().max(layout.align()), 1 << self.leaf2base);
+        // the lowest free block of order fk or larger
+        let mut lowest: Option<(usize, *mut Node)> = None;
+        for j in fk..self.entries_size {
+            let list = self.entry(j).free;
+            let mut node = unsafe { (*list).next };
+            while !core::ptr::eq(node, list) {
+                if lowest.is_none_or(|(_, low)| node < low) {
+                    lowest = Some((j, node));
+                }
+                node = unsafe { (*node).next };
+            }
+        }
+        let (j, node) = match lowest {
+            Some((j, node)) if (node as usize) < ptr.as_ptr() as usize => (j, node),
+            _ => return Err(Error::OutOfMemory),
+        };
+        Node::remove(node);
+        let p = self.split_down(node.cast(), j, fk);
+        #[cfg(feature = "stats")]
+        self.counters.alloc(block_size_2base(fk, self.leaf2base));
+        core::ptr::copy_nonoverlapping(ptr.as_ptr(), p, layout.size());
+        self.free_block(ptr.as_ptr());
+        let len = match self.slice_size {
+            SliceSize::Requested => layout.size(),
+            SliceSize::Block => block_size_2base(fk, self.leaf2base),
+        };
+        Ok(NonNull::slice_from_raw_parts(
+            NonNull::new_unchecked(p),
+            len,
+        ))
+    }
+
+    /// Allocate `nbytes` at an `align` aligned address, from the smallest free
+    /// block holding them, splitting off its other parts.
+    /// Block starts share the low bits of base_addr, so the address may lie inside
+    /// the block; returns the block's order along with the address.
+    fn alloc_aligned(&self, nbytes: usize, fk: usize, align: usize) -> Option<(usize, *mut u8)> {
+        let (k, node, target) = self.find_aligned(nbytes, fk, align)?;
+        let ak = self.holding_k(target, nbytes, fk).min(k);
+        Node::remove(node);
+        Some((ak, self.carve(node.cast(), k, ak, target)))
+    }
+
+    /// the first free block of order fk or more holding `nbytes` at an
+    /// `align` boundary, with its order and that boundary
+    fn find_aligned(
+        &self,
+        nbytes: usize,
+        fk: usize,
+        align: usize,
+    ) -> Option<(usize, *mut Node, usize)> {
+        (fk..self.entries_size).find_map(|k| {
+            let list = self.entry(k).free;
+            let block_size = block_size_2base(k, self.leaf2base);
+            let mut node = unsafe { (*list).next };
+            while !core::ptr::eq(node, list) {
+                let addr = node as usize;
+                if let Some(target) = addr.checked_next_multiple_of(align) {
+                    if target - addr + nbytes <= block_size {
+                        return Some((k, node, target));
+                    }
+                }
+                node = unsafe { (*node).next };
+            }
+            None
+        })
+    }
+
+    /// the smallest order from fk whose block at addr holds all `nbytes`
+    fn holding_k(&self, addr: usize, nbytes: usize, fk: usize) -> usize {
+        let offset = addr - self.base_addr();
+        let last = offset + nbytes.max(1) - 1;
+        (fk..)
+            .find(|&j| (offset >> j >> self.leaf2base) == (last >> j >> self.leaf2base))
+            .expect("orders past the heap hold it")
+    }
+
+    /// Mark the free block p of order k, out of its free list, as allocated,
+    /// splitting it down to the order ak block holding target.
+    fn carve(&self, mut p: *mut u8, mut k: usize, ak: usize, target: usize) -> *mut u8 {
+        #[cfg(feature = "free-fill")]
+        self.check_poison(p, k);
+        bit_set(self.entry(k).alloc, self.block_index(k, p));
+        // split towards target, the halves left behind go to the free lists
+        while k > ak {
+            let half = block_size_2base(k - 1, self.leaf2base);
+            bit_set(self.entry(k).split, self.block_index(k, p));
+            let q = p.wrapping_add(half);
+            let rest = if target >= q as usize {
+                core::mem::replace(&mut p, q)
+            } else {
+                q
+            };
+            let parent_entry = self.entry(k - 1);
+            bit_set(parent_entry.alloc, self.block_index(k - 1, p));
+            debug_assert!(!bit_isset(
+                parent_entry.alloc,
+                self.block_index(k - 1, rest)
+            ));
+            Node::push(parent_entry.free, rest);
+            k -= 1;
+        }
+        #[cfg(feature = "free-fill")]
+        self.touch(self.block_end(k, p));
+        p.with_addr(target)
+    }
+
+    /// Allocate the largest block available between `min_layout.size()` and
+    /// `preferred_size` bytes, the returned slice tells the size obtained.
+    pub fn allocate_up_to(
+        &self,
+        min_layout: Layout,
+        preferred_size: usize,
+    ) -> Result<NonNull<[u8]>, Error> {
+        let leaf_size = 1 << self.leaf2base;
+        let min_k = first_up_k(min_layout.size().max(min_layout.align()), leaf_size);
+        let preferred_k = first_up_k(preferred_size, leaf_size)
+            .min(self.entries_size - 1)
+            .max(min_k);
+        let k = if (preferred_k..self.entries_size).any(|k| !Node::is_empty(self.entry(k).free)) {
+            preferred_k
+        } else {
+            (min_k..preferred_k)
+                .rev()
+                .find(|&k| !Node::is_empty(self.entry(k).free))
+                .ok_or_else(|| self.exhausted(block_size_2base(min_k, self.leaf2base)))?
+        };
+        let p = self.alloc_block(k).ok_or(Error::OutOfMemory)?;
+        #[cfg(feature = "stats")]
+        self.counters.alloc(block_size_2base(k, self.leaf2base));
+        let size = block_size_2base(k, self.leaf2base).min(preferred_size.max(min_layout.size()));
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(p) },
+            size,
+        ))
+    }
+
+    /// Like allocate, but the error says why nothing was handed out
+    pub fn try_allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, Error> {
+        self.allocate_budgeted(layout, usize::MAX).map_err(|_| {
+            if self.past_deterministic_align(layout.align()) {
+                Error::AlignmentUnsupported
+            } else if layout.size() > self.end_addr() - self.base_addr() {
+                Error::SizeUnsupported
+            } else {
+                self.exhausted(layout.size())
+            }
+        })
+    }
+
+    /// Allocate the largest free block, the returned slice covers all of it.
+    pub fn allocate_largest(&self) -> Result<NonNull<[u8]>, Error> {
+        let k = (0..self.entries_size)
+            .rev()
+            .find(|&k| !Node::is_empty(self.entry(k).free))
+            .ok_or(Error::OutOfMemory)?;
+        let p = self.alloc_block(k).ok_or(Error::OutOfMemory)?;
+        #[cfg(feature = "stats")]
+        self.counters.alloc(block_size_2base(k, self.leaf2base));
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(p) },
+            block_size_2base(k, self.leaf2base),
+        ))
+    }
+
+    /// every block in address order, see Blocks
+    pub fn blocks(&self) -> Blocks<'_> {
+        Blocks {
+            alloc: self,
+            addr: self.base_addr(),
+        }
+    }
+
+    /// Panic unless the blocks in address order are exactly `expected`, given
+    /// as offset from the heap base, size and state. For golden-image tests of
+    /// a boot sequence, the message lists both layouts, `-` expected, `+` actual.
+    #[track_caller]
+    pub fn assert_layout(&self, expected: &[(usize, usize, BlockState)]) {
+        if !self.blocks().eq(expected.iter().copied()) {
+            panic!(
+                "heap layout mismatch, - expected + actual\n{}",
+                LayoutDiff {
+                    alloc: self,
+                    expected,
+                }
+            );
+        }
+    }
+
+    /// maximal free ranges in address order, see FreeRegions
+    pub fn free_regions(&self) -> FreeRegions<'_> {
+        FreeRegions {
+            alloc: self,
+            addr: self.base_addr(),
+        }
+    }
+
+    /// number of free blocks of order k
+    pub fn free_blocks(&self, k: usize) -> usize {
+        if k >= self.entries_size {
+            return 0;
+        }
+        let list = self.entry(k).free;
+        let mut count = 0;
+        let mut node = unsafe { (*list).next };
+        while !core::ptr::eq(node, list) {
+            count += 1;
+            node = unsafe { (*node).next };
+        }
+        count
+    }
+
+    /// Split larger free blocks until at least `count` blocks of order k
+    /// are free, so allocating them later needs no split.
+    /// Freed blocks still merge with their buddies as usual.
+    pub fn reserve_blocks(&self, k: usize, count: usize) -> Result<(), Error> {
+        if k >= self.entries_size - 1 {
+            return Err(Error::SizeUnsupported);
+        }
+        let mut free = self.free_blocks(k);
+        while free < count {
+            let j = ((k + 1)..self.entries_size)
+                .find(|&j| !Node::is_empty(self.entry(j).free))
+                .ok_or_else(|| {
+                    self.exhausted((count - free) * block_size_2base(k, self.leaf2base))
+                })?;
+            for m in ((k + 1)..=j).rev() {
+                self.split_free_block(m);
+            }
+            free += 2;
+        }
+        Ok(())
+    }
+
+    /// why `bytes` more bytes can't be had: too few free, or too scattered
+    fn exhausted(&self, bytes: usize) -> Error {
+        if self.stats().free_bytes >= bytes {
+            Error::FragmentationLimit
+        } else {
+            Error::OutOfMemory
+        }
+    }
+
+    /// split the first free block of order k into two free blocks of order k - 1
+    fn split_free_block(&self, k: usize) {
+        let p = Node::pop(self.entry(k).free) as *mut u8;
+        let block_index = self.block_index(k, p);
+        bit_set(self.entry(k).alloc, block_index);
+        bit_set(self.entry(k).split, block_index);
+        let q = p.wrapping_add(block_size_2base(k - 1, self.leaf2base));
+        let child_entry = self.entry(k - 1);
+        Node::push(child_entry.free, q);
+        // p goes first, the next split in a chain takes it
+        Node::push(child_entry.free, p);
+    }
+
+    /// number of block orders, including the dummy top one
+    pub(crate) fn entries_size(&self) -> usize {
+        self.entries_size
+    }
+
+    pub(crate) fn leaf_size(&self) -> usize {
+        1 << self.leaf2base
+    }
+
+    /// offset of the first block from the start of the heap range passed in
+    pub(crate) fn base_offset(&self) -> usize {
+        self.base_addr() - self.region.addr()
+    }
+
+    /// bytes from the start of the heap range passed in to the end of the last block
+    pub(crate) fn region_len(&self) -> usize {
+        self.end_addr() - self.region.addr()
+    }
+
+    /// bytes of the order k alloc bitmap, the split bitmap is the same size above order 0
+    pub(crate) fn bitmap_len(&self, k: usize) -> usize {
+        roundup(nblock(k, self.entries_size), 3) >> 3
+    }
+
+    /// the alloc and split bitmaps of order k, split is empty at order 0
+    pub(crate) fn bitmaps(&self, k: usize) -> (&[u8], &[u8]) {
+        let len = self.bitmap_len(k);
+        let entry = self.entry(k);
+        unsafe {
+            (
+                core::slice::from_raw_parts(entry.alloc, len),
+                if k == 0 {
+                    &[]
+                } else {
+                    core::slice::from_raw_parts(entry.split, len)
+                },
+            )
+        }
+    }
+
+    /// Like bitmaps, but writable
+    ///
+    /// # Safety
+    ///
+    /// Writes must leave the bitmaps consistent, then call rebuild_free_lists.
+    pub(crate) unsafe fn bitmaps_mut(&mut self, k: usize) -> (&mut [u8], &mut [u8]) {
+        let len = self.bitmap_len(k);
+        let entry = self.entry(k);
+        (
+            core::slice::from_raw_parts_mut(entry.alloc, len),
+            if k == 0 {
+                &mut []
+            } else {
+                core::slice::from_raw_parts_mut(entry.split, len)
+            },
+        )
+    }
+
+    /// Rebuild every free list from the bitmaps, a block is free
+    /// if it isn't allocated and its parent is split.
+    pub(crate) fn rebuild_free_lists(&self) {
+        for k in 0..self.entries_size {
+            let list = self.entry(k).free;
+            unsafe {
+                (*list).next = list;
+                (*list).prev = list;
+            }
+        }
+        for k in 0..(self.entries_size - 1) {
+            let entry = self.entry(k);
+            let parent = self.entry(k + 1);
+            for i in 0..nblock(k, self.entries_size) {
+                if !bit_isset(entry.alloc, i) && bit_isset(parent.split, i >> 1) {
+                    let p: *mut u8 = self.ptr(self.block_addr(k, i));
+                    #[cfg(feature = "free-fill")]
+                    if self.free_fill == Some(FREE_FILL) {
+                        unsafe { p.write_bytes(FREE_FILL, block_size_2base(k, self.leaf2base)) };
+                    }
+                    Node::push(entry.free, p);
+                }
+            }
+        }
+    }
+
+    /// alignment statistics
+    #[cfg(feature = "stats")]
+    pub fn align_stats(&self) -> &AlignStats {
+        &self.align_stats
+    }
+
+    fn base_addr(&self) -> usize {
+        self.base.addr()
+    }
+
+    fn end_addr(&self) -> usize {
+        self.end.addr()
+    }
+
+    /// pointer to addr, derived from the region pointer
+    fn ptr<T>(&self, addr: usize) -> *mut T {
+        self.region.with_addr(addr).cast()
+    }
+
+    fn entry(&self, i: usize) -> &Entry {
+        debug_assert!(i < self.entries_size, "index out of range");
+        unsafe { self.entries.add(i).as_ref().expect("entry") }
+    }
+
+    /// find k for p
+    fn find_k_for_p(&self, p: *const u8) -> usize {
+        let k = self.block_k(p);
+        debug_assert!(bit_isset(self.entry(k).alloc, self.block_index(k, p)));
+        k
+    }
+
+    /// find k of the block starting at p, allocated or free
+    fn block_k(&self, p: *const u8) -> usize {
+        for k in 0..(self.entries_size - 1) {
+            if bit_isset(self.entry(k + 1).split, self.block_index(k + 1, p)) {
+                return k;
+            }
+        }
+        0
+    }
+
+    /// block index of p under k
+    fn block_index(&self, k: usize, p: *const u8) -> usize {
+        if (p as usize) < self.base_addr() {
+            // TODO handle this outside
+            panic!("out of memory");
+        }
+        let n = p as usize - self.base_addr();
+        // equal to: n / block_size_2base(k, self.leaf2base);
+        let index = (n >> k) >> self.leaf2base;
+        debug_assert!(index < nblock(k, self.entries_size));
+        index
+    }
+
+    /// end of the order k block holding p
+    fn block_end(&self, k: usize, p: *const u8) -> usize {
+        self.block_addr(k, self.block_index(k, p) + 1)
+    }
+
+    /// block addr of index under k
+    fn block_addr(&self, k: usize, i: usize) -> usize {
+        // equal to: i * block_size_2base(k, self.leaf2base);
+        let n = (i << k) << self.leaf2base;
+        self.base_addr() + n
+    }
+}
+
+/// Iterator over maximal free ranges of a BuddyAlloc in address order,
+/// yields `(addr, len)` pairs; adjacent free blocks are reported as one range.
+pub struct FreeRegions<'a> {
+    alloc: &'a BuddyAlloc,
+    addr: usize,
+}
+
+impl FreeRegions<'_> {
+    /// order and free state of the block starting at self.addr
+    fn block(&self) -> (usize, bool) {
+        let k = self.alloc.block_k(self.alloc.ptr(self.addr));
+        let free = !bit_isset(
+            self.alloc.entry(k).alloc,
+            self.alloc.block_index(k, self.alloc.ptr(self.addr)),
+        );
+        (block_size_2base(k, self.alloc.leaf2base), free)
+    }
+}
+
+impl Iterator for FreeRegions<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let end = self.alloc.end_addr() - self.alloc.unavailable;
+        // skip allocated blocks
+        loop {
+            if self.addr >= end {
+                return None;
+            }
+            let (size, free) = self.block();
+            if free {
+                break;
+            }
+            self.addr += size;
+        }
+        let start = self.addr;
+        while self.addr < end {
+            let (size, free) = self.block();
+            if !free {
+                break;
+            }
+            self.addr += size;
+        }
+        Some((start, self.addr - start))
+    }
+}
+
+/// Whether a block is handed out, see BuddyAlloc::blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockState {
+    Free,
+    Allocated,
+}
+
+/// Iterator over every block of a heap in address order,
+/// yields the offset from the heap base, size and state of each
+pub struct Blocks<'a> {
+    alloc: &'a BuddyAlloc,
+    addr: usize,
+}
+
+impl Iterator for Blocks<'_> {
+    type Item = (usize, usize, BlockState);
+
+    fn next(&mut self) -> Option<(usize, usize, BlockState)> {
+        let end = self.alloc.end_addr() - self.alloc.unavailable;
+        if self.addr >= end {
+            return None;
+        }
+        let p = self.alloc.ptr(self.addr);
+        let k = self.alloc.block_k(p);
+        let state = if bit_isset(self.alloc.entry(k).alloc, self.alloc.block_index(k, p)) {
+            BlockState::Allocated
+        } else {
+            BlockState::Free
+        };
+        let offset = self.addr - self.alloc.base_addr();
+        let size = block_size_2base(k, self.alloc.leaf2base);
+        self.addr += size;
+        Some((offset, size, state))
+    }
+}
+
+/// Expected and actual blocks side by side, mismatches as `-` and `+` lines
+struct LayoutDiff<'a> {
+    alloc: &'a BuddyAlloc,
+    expected: &'a [(usize, usize, BlockState)],
+}
+
+impl core::fmt::Display for LayoutDiff<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let row = |f: &mut core::fmt::Formatter<'_>, sign, (offset, size, state)| {
+            writeln!(f, "{sign} {offset:#x} {size:#x} {state:?}")
+        };
+        let mut actual = self.alloc.blocks();
+        let mut expected = self.expected.iter().copied();
+        loop {
+            match (expected.next(), actual.next()) {
+                (None, None) => return Ok(()),
+                (Some(e), Some(a)) if e == a => row(f, ' ', a)?,
+                (e, a) => {
+                    if let Some(e) = e {
+                        row(f, '-', e)?;
+                    }
+                    if let Some(a) = a {
+                        row(f, '+', a)?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl HeapRange for BuddyAlloc {
+    fn heap_range(&self) -> Range<usize> {
+        self.base_addr()..self.end_addr()
+    }
+}
+
+impl HeapInspect for BuddyAlloc {
+    unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        BuddyAlloc::alloc_size(self, p)
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            total_bytes: self.available_bytes(),
+            ..HeapStats::default()
+        };
+        self.for_each_free_block(|_, size| {
+            stats.free_bytes += size;
+            stats.largest_free = stats.largest_free.max(size);
+            stats.free_blocks += 1;
+        });
+        stats.used_bytes = stats.total_bytes - stats.free_bytes;
+        #[cfg(feature = "stats")]
+        self.counters.fill(&mut stats);
+        stats
+    }
+
+    fn validate(&self) -> Result<(), Corruption> {
+        let end = self.end_addr() - self.unavailable;
+        for k in 0..self.entries_size {
+            let list = self.entry(k).free;
+            let block_size = block_size_2base(k, self.leaf2base);
+            let mut prev = list;
+            let mut node = unsafe { (*list).next };
+            while !core::ptr::eq(node, list) {
+                let addr = node as usize;
+                // check the node is a block before reading it
+                let in_heap = addr >= self.base_addr() && addr + block_size <= end;
+                if !in_heap || !(addr - self.base_addr()).is_multiple_of(block_size) {
+                    return Err(Corruption { addr });
+                }
+                let p = node.cast_const().cast::<u8>();
+                let linked = core::ptr::eq(unsafe { (*node).prev }, prev);
+                let free = !bit_isset(self.entry(k).alloc, self.block_index(k, p));
+                if !linked || !free || self.block_k(p) != k {
+                    return Err(Corruption { addr });
+                }
+                prev = node;
+                node = unsafe { (*node).next };
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Allocator for BuddyAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_budgeted(layout, usize::MAX)
+            .map_err(|_| AllocError)
+    }
+
+    /// Only clears what may be dirty: blocks never handed out from a zeroed
+    /// heap, or freed with zero on free, just hold their free list node.
+    #[cfg(feature = "free-fill")]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let clean_from = self.clean_from.get();
+        let ptr = self.allocate(layout)?;
+        let p = ptr.as_mut_ptr();
+        let k = self.find_k_for_p(p);
+        let start = self.block_addr(k, self.block_index(k, p));
+        let len = if start >= clean_from {
+            (start + core::mem::size_of::<Node>())
+                .saturating_sub(p as usize)
+                .min(ptr.len())
+        } else {
+            ptr.len()
+        };
+        unsafe { p.write_bytes(0, len) };
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.free_block(ptr.as_ptr());
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.resize(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.resize(ptr, old_layout, new_layout)
+    }
+}
+
+impl BuddyAlloc {
+    /// grow or shrink in place by merging or splitting buddies,
+    /// fall back to allocate, copy and free if the block can't be reused
+    unsafe fn resize(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let p = ptr.as_ptr();
+        if p.align_offset(new_layout.align()) == 0 && self.resize_in_place(p, new_layout.size()) {
+            let len = match self.slice_size {
+                SliceSize::Requested => new_layout.size(),
+                SliceSize::Block => self.alloc_size(p),
+            };
+            return Ok(NonNull::slice_from_raw_parts(ptr, len));
+        }
+        let new = self.allocate(new_layout)?;
+        core::ptr::copy_nonoverlapping(
+            p,
+            new.as_mut_ptr(),
+            old_layout.size().min(new_layout.size()),
+        );
+        self.free_block(p);
+        Ok(new)
+    }
+}
+
+/// Alignment of a StaticBuddyHeap, the largest leaf size it takes
+pub const STATIC_HEAP_ALIGN: usize = 4096;
+const STATIC_LEAF_ERROR_MSG: &str = "leaf size larger than STATIC_HEAP_ALIGN";
+
+/// heap bytes aligned to STATIC_HEAP_ALIGN, for buddy_heap! and StaticBuddyHeap
+#[doc(hidden)]
+#[repr(C, align(4096))]
+pub struct StaticHeapBytes<const LEN: usize>(MaybeUninit<[u8; LEN]>);
+
+impl<const LEN: usize> StaticHeapBytes<LEN> {
+    #[doc(hidden)]
+    pub const fn zeroed() -> Self {
+        StaticHeapBytes(MaybeUninit::zeroed())
+    }
+}
+
+/// StaticBuddyHeap
+/// a `LEN` bytes heap together with its BuddyAlloc, fully built at compile time,
+/// so it can live in a static with no runtime init step:
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// # use {buddy_alloc::StaticBuddyHeap, core::alloc::{Allocator, Layout}};
+/// static mut HEAP: StaticBuddyHeap<4096> =
+///     unsafe { StaticBuddyHeap::new(core::ptr::addr_of_mut!(HEAP), 16) };
+///
+/// let heap = unsafe { &*core::ptr::addr_of!(HEAP) };
+/// assert!(heap.alloc().allocate(Layout::new::<u64>()).is_ok());
+/// ```
+///
+/// The metadata is kept in front of the heap, like BuddyAlloc::new does.
+#[repr(C)]
+pub struct StaticBuddyHeap<const LEN: usize> {
+    heap: StaticHeapBytes<LEN>,
+    alloc: BuddyAlloc,
+}
+
+impl<const LEN: usize> StaticBuddyHeap<LEN> {
+    /// see BuddyAllocParam::new for `leaf_size`
+    ///
+    /// # Safety
+    ///
+    /// `this` must be the address of the static this call initializes,
+    /// the allocator points into it and the heap must never move.
+    pub const unsafe fn new(this: *mut Self, leaf_size: usize) -> Self {
+        assert!(leaf_size <= STATIC_HEAP_ALIGN, "{}", STATIC_LEAF_ERROR_MSG);
+        let mut heap = StaticHeapBytes::zeroed();
+        let param = BuddyAllocParam::new(this.cast(), LEN, leaf_size);
+        // the heap comes first and is aligned, so any aligned address stands in for its own
+        let alloc = BuddyAlloc::build(
+            param,
+            core::ptr::addr_of_mut!(heap).cast(),
+            STATIC_HEAP_ALIGN,
+            core::ptr::null_mut(),
+            0,
+            true,
+        );
+        StaticBuddyHeap { heap, alloc }
+    }
+
+    /// the allocator over this heap
+    pub fn alloc(&self) -> &BuddyAlloc {
+        &self.alloc
     }
 }
@@ -0,0 +1,277 @@
+//! Atomic buddy alloc
+//! A buddy allocator whose free blocks are tracked in per-order atomic bitmaps.
+//!
+//! Taking a block clears its bit and freeing one sets it, both with single
+//! atomic operations, so cores allocate concurrently without a lock.
+//! Scans start at a per order hint word, moved on as words run out, instead
+//! of crossing the exhausted words at the front every time.
+//! Freeing claims a free buddy the same way before merging; of two buddies
+//! freed at the same moment, the one released last sees the other and merges.
+//! No free list lives inside the blocks, so any power of two leaf size works.
+
+use {
+    super::{first_up_k, log2, roundup},
+    crate::heap_registry::HeapRange,
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        mem::size_of,
+        ops::Range,
+        ptr::NonNull,
+        sync::atomic::{fence, AtomicUsize, Ordering},
+    },
+};
+
+const OOM_MSG: &str = "requires more memory space to initialize AtomicBuddyAlloc";
+const LEAF_SIZE_ERROR_MSG: &str = "leaf size must be a power of two";
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+#[derive(Clone, Copy)]
+pub struct AtomicBuddyAllocParam {
+    /// Base addr: the start address
+    base_addr: *const u8,
+    /// Len: available bytes from the start address
+    len: usize,
+    /// Leaf size: the min size to allocate
+    leaf_size: usize,
+}
+
+impl AtomicBuddyAllocParam {
+    /// Base addr: the start address
+    /// Len: available bytes from the start address
+    /// Leaf size: the min size to allocate
+    pub const fn new(base_addr: *const u8, len: usize, leaf_size: usize) -> Self {
+        assert!(leaf_size.is_power_of_two(), "{}", LEAF_SIZE_ERROR_MSG);
+        AtomicBuddyAllocParam {
+            base_addr,
+            len,
+            leaf_size,
+        }
+    }
+}
+
+/// bitmap words of every order for `nleaves` leaves
+const fn bitmap_words(nleaves: usize) -> usize {
+    let mut words = 0;
+    let mut n = nleaves;
+    while n > 1 {
+        words += n.div_ceil(WORD_BITS);
+        n = n.div_ceil(2);
+    }
+    words + 1
+}
+
+pub struct AtomicBuddyAlloc {
+    /// the region pointer passed in, every pointer is derived from it
+    region: *mut u8,
+    /// first block, past the bitmaps kept in front of the heap
+    base_addr: usize,
+    nleaves: usize,
+    /// orders 0..=max_k
+    max_k: usize,
+    leaf2base: usize,
+    /// a set bit marks a free block, order 0 first
+    bitmaps: *const AtomicUsize,
+    /// per order, the bitmap word scans start at
+    hints: *const AtomicUsize,
+}
+
+// blocks and bitmaps are only touched through atomics
+unsafe impl Sync for AtomicBuddyAlloc {}
+unsafe impl Send for AtomicBuddyAlloc {}
+
+impl AtomicBuddyAlloc {
+    /// # Safety
+    ///
+    /// The `base_addr..(base_addr + len)` must be allocated before use,
+    /// and must guarantee no others write to the memory range, otherwise behavior is undefined.
+    /// Panics if the range can't hold the bitmaps and one leaf.
+    pub unsafe fn new(param: AtomicBuddyAllocParam) -> Self {
+        let region = param.base_addr.cast_mut();
+        let start = region.addr();
+        let end = start + param.len;
+        let leaf2base = log2(param.leaf_size);
+        // size the bitmaps for the whole range, the heap gets a bit less
+        let words = bitmap_words(param.len >> leaf2base);
+        let orders = log2((param.len >> leaf2base).max(1)) + 1;
+        let bitmaps_addr = roundup(start, log2(size_of::<AtomicUsize>()));
+        let hints_addr = bitmaps_addr + words * size_of::<AtomicUsize>();
+        let base_addr = roundup(hints_addr + orders * size_of::<AtomicUsize>(), leaf2base);
+        assert!(end >= base_addr + param.leaf_size, "{}", OOM_MSG);
+        let nleaves = (end - base_addr) >> leaf2base;
+        let bitmaps = region.with_addr(bitmaps_addr).cast::<AtomicUsize>();
+        for i in 0..(words + orders) {
+            bitmaps.add(i).write(AtomicUsize::new(0));
+        }
+
+        let alloc = AtomicBuddyAlloc {
+            region,
+            base_addr,
+            nleaves,
+            max_k: log2(nleaves),
+            leaf2base,
+            bitmaps,
+            hints: region.with_addr(hints_addr).cast(),
+        };
+        // cover the heap with the largest blocks that fit, each aligned to its
+        // size relative to base_addr
+        let mut leaf = 0;
+        for k in (0..=alloc.max_k).rev() {
+            if leaf + (1 << k) <= nleaves {
+                alloc.release(k, leaf >> k);
+                leaf += 1 << k;
+            }
+        }
+        alloc
+    }
+
+    /// available bytes
+    pub fn available_bytes(&self) -> usize {
+        self.nleaves << self.leaf2base
+    }
+
+    /// bytes in free blocks
+    pub fn free_bytes(&self) -> usize {
+        (0..=self.max_k)
+            .map(|k| {
+                let words = self.order_words(k);
+                let free: u32 = words
+                    .iter()
+                    .map(|w| w.load(Ordering::Relaxed).count_ones())
+                    .sum();
+                (free as usize) << (k + self.leaf2base)
+            })
+            .sum()
+    }
+
+    fn order_words(&self, k: usize) -> &[AtomicUsize] {
+        let offset = (0..k)
+            .map(|j| self.nleaves.div_ceil(1 << j).div_ceil(WORD_BITS))
+            .sum::<usize>();
+        let len = self.nleaves.div_ceil(1 << k).div_ceil(WORD_BITS);
+        unsafe { core::slice::from_raw_parts(self.bitmaps.add(offset), len) }
+    }
+
+    /// take a free block of order k, splitting a larger one if none is free
+    fn take(&self, k: usize) -> Option<usize> {
+        if k > self.max_k {
+            return None;
+        }
+        let words = self.order_words(k);
+        let hint = unsafe { &*self.hints.add(k) };
+        let start = hint.load(Ordering::Relaxed);
+        for w in (start..words.len()).chain(0..start) {
+            let word = &words[w];
+            let mut bits = word.load(Ordering::Relaxed);
+            while bits != 0 {
+                let mask = 1 << bits.trailing_zeros();
+                let prev = word.fetch_and(!mask, Ordering::Acquire);
+                if prev & mask != 0 {
+                    // the next scan starts past the words found empty
+                    let next = if prev & !mask == 0 {
+                        (w + 1) % words.len()
+                    } else {
+                        w
+                    };
+                    if next != start {
+                        hint.store(next, Ordering::Relaxed);
+                    }
+                    return Some(w * WORD_BITS + mask.trailing_zeros() as usize);
+                }
+                bits = prev & !mask;
+            }
+        }
+        let i = self.take(k + 1)? << 1;
+        self.release(k, i + 1);
+        Some(i)
+    }
+
+    /// mark the order k block i free
+    fn release(&self, k: usize, i: usize) {
+        self.order_words(k)[i / WORD_BITS].fetch_or(1 << (i % WORD_BITS), Ordering::Release);
+    }
+
+    /// clear the free bit of the order k block i, false if it wasn't free
+    fn claim(&self, k: usize, i: usize) -> bool {
+        let Some(word) = self.order_words(k).get(i / WORD_BITS) else {
+            return false;
+        };
+        let mask = 1 << (i % WORD_BITS);
+        word.fetch_and(!mask, Ordering::Acquire) & mask != 0
+    }
+
+    /// whether the order k block i is free
+    fn is_free(&self, k: usize, i: usize) -> bool {
+        self.order_words(k)
+            .get(i / WORD_BITS)
+            .is_some_and(|word| word.load(Ordering::Relaxed) & (1 << (i % WORD_BITS)) != 0)
+    }
+
+    /// free the order k block i, merging it with buddies that are free
+    fn free(&self, mut k: usize, mut i: usize) {
+        loop {
+            while k < self.max_k && self.claim(k, i ^ 1) {
+                i >>= 1;
+                k += 1;
+            }
+            self.release(k, i);
+            // a buddy freed meanwhile saw this block taken and didn't merge,
+            // the fence makes sure one of the two sees the other released
+            fence(Ordering::SeqCst);
+            if k == self.max_k || !self.is_free(k, i ^ 1) || !self.claim(k, i) {
+                return;
+            }
+        }
+    }
+
+    fn order_for(&self, layout: Layout) -> Option<usize> {
+        if !self.base_addr.is_multiple_of(layout.align()) {
+            return None;
+        }
+        Some(first_up_k(
+            layout.size().max(layout.align()),
+            1 << self.leaf2base,
+        ))
+    }
+}
+
+impl HeapRange for AtomicBuddyAlloc {
+    fn heap_range(&self) -> Range<usize> {
+        self.base_addr..(self.base_addr + self.available_bytes())
+    }
+}
+
+unsafe impl Allocator for AtomicBuddyAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let k = self.order_for(layout).ok_or(AllocError)?;
+        let i = self.take(k).ok_or(AllocError)?;
+        let p = self
+            .region
+            .with_addr(self.base_addr + ((i << k) << self.leaf2base));
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(p) },
+            layout.size(),
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let k = self
+            .order_for(layout)
+            .expect("layout not served by this heap");
+        let i = ((ptr.as_ptr().addr() - self.base_addr) >> self.leaf2base) >> k;
+        self.free(k, i);
+    }
+}
+
+unsafe impl GlobalAlloc for AtomicBuddyAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
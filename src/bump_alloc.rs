@@ -0,0 +1,174 @@
+//! Bump alloc
+//! Pointer-bump allocation over a fixed buffer, everything is freed at once.
+
+use {
+    crate::heap_registry::HeapRange,
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        ops::Range,
+        ptr::NonNull,
+    },
+};
+
+#[derive(Clone, Copy)]
+pub struct BumpAllocParam {
+    /// Base addr: the start address
+    base_addr: *const u8,
+    /// Len: available bytes from the start address
+    len: usize,
+}
+
+impl BumpAllocParam {
+    /// Base addr: the start address
+    /// Len: available bytes from the start address
+    pub const fn new(base_addr: *const u8, len: usize) -> Self {
+        BumpAllocParam { base_addr, len }
+    }
+}
+
+/// Allocation state of a BumpAlloc, see `BumpAlloc::checkpoint`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    next: usize,
+    live: usize,
+}
+
+/// Rewinds to the checkpoint on drop, also while unwinding.
+struct ScopeGuard<'a> {
+    alloc: &'a BumpAlloc,
+    checkpoint: Checkpoint,
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.alloc.rewind(self.checkpoint) };
+    }
+}
+
+pub struct BumpAlloc {
+    /// the region pointer passed in, every pointer is derived from it
+    region: *mut u8,
+    /// memory start addr
+    base_addr: usize,
+    /// memory end addr
+    end_addr: usize,
+    /// next free addr
+    next: Cell<usize>,
+    /// allocations not yet deallocated
+    live: Cell<usize>,
+}
+
+// the arena owns its memory, moving it moves every pointer into it
+unsafe impl Send for BumpAlloc {}
+
+impl BumpAlloc {
+    /// # Safety
+    ///
+    /// The `base_addr..(base_addr + len)` must be allocated before use,
+    /// and must guarantee no others write to the memory range, otherwise behavior is undefined.
+    pub unsafe fn new(param: BumpAllocParam) -> Self {
+        let base_addr = param.base_addr as usize;
+        BumpAlloc {
+            region: param.base_addr.cast_mut(),
+            base_addr,
+            end_addr: base_addr + param.len,
+            next: Cell::new(base_addr),
+            live: Cell::new(0),
+        }
+    }
+
+    /// Free every allocation at once.
+    ///
+    /// # Safety
+    ///
+    /// No memory handed out since the last reset may be used afterwards.
+    pub unsafe fn reset(&self) {
+        self.next.set(self.base_addr);
+        self.live.set(0);
+    }
+
+    /// Record the current allocation state.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            next: self.next.get(),
+            live: self.live.get(),
+        }
+    }
+
+    /// Free every allocation made since `checkpoint` was taken.
+    ///
+    /// # Safety
+    ///
+    /// `checkpoint` must come from this allocator, with no reset or earlier
+    /// rewind since, and memory handed out after it may not be used afterwards.
+    pub unsafe fn rewind(&self, checkpoint: Checkpoint) {
+        debug_assert!(checkpoint.next <= self.next.get(), "stale checkpoint");
+        self.next.set(checkpoint.next);
+        self.live.set(checkpoint.live);
+    }
+
+    /// Run `f` and rewind to the state before it, also when `f` panics,
+    /// so a failed test case doesn't leak arena space into the next one.
+    ///
+    /// # Safety
+    ///
+    /// Memory allocated during `f` may not be used after it returns or unwinds.
+    pub unsafe fn scope<R, F: FnOnce(&Self) -> R>(&self, f: F) -> R {
+        let _guard = ScopeGuard {
+            alloc: self,
+            checkpoint: self.checkpoint(),
+        };
+        f(self)
+    }
+
+    /// number of allocations not yet deallocated
+    pub fn live_allocations(&self) -> usize {
+        self.live.get()
+    }
+
+    /// bytes handed out since the last reset, including alignment padding
+    pub fn used_bytes(&self) -> usize {
+        self.next.get() - self.base_addr
+    }
+
+    /// available bytes
+    pub fn available_bytes(&self) -> usize {
+        self.end_addr - self.next.get()
+    }
+
+    pub fn contains_ptr(&self, p: *mut u8) -> bool {
+        let addr = p as usize;
+        addr >= self.base_addr && addr < self.end_addr
+    }
+}
+
+impl HeapRange for BumpAlloc {
+    fn heap_range(&self) -> Range<usize> {
+        self.base_addr..self.end_addr
+    }
+}
+
+unsafe impl Allocator for BumpAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let align_mask = layout.align() - 1;
+        let addr = (self.next.get() + align_mask) & !align_mask;
+        let end = addr.checked_add(layout.size()).ok_or(AllocError)?;
+        if end > self.end_addr {
+            return Err(AllocError);
+        }
+        self.next.set(end);
+        self.live.set(self.live.get() + 1);
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(self.region.with_addr(addr)) },
+            layout.size(),
+        ))
+    }
+
+    /// Memory is only reclaimed by `reset`.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        debug_assert!(self.contains_ptr(ptr.as_ptr()));
+        debug_assert!(self.live.get() > 0, "deallocate without allocate");
+        self.live.set(self.live.get() - 1);
+    }
+}
@@ -0,0 +1,109 @@
+//! C API
+//! libc style `malloc`, `free`, `calloc` and `realloc` over a GlobalBuddyAlloc.
+//!
+//! `c_api!` exports the four symbols backed by one static heap, for C code
+//! and vendor SDKs linked into the firmware. The functions here are the
+//! bodies it expands to, callable from Rust against any heap.
+//!
+//! ```no_run
+//! buddy_alloc::static_heap!(HEAP, 64 * 1024);
+//! buddy_alloc::c_api!(HEAP);
+//! # fn main() {}
+//! ```
+
+use {
+    crate::{global_buddy::GlobalBuddyAlloc, locked::RawMutex},
+    core::alloc::{Allocator, Layout},
+};
+
+/// Alignment of every block, that of max_align_t on common targets
+pub const MALLOC_ALIGN: usize = 16;
+
+/// Export `malloc`, `free`, `calloc` and `realloc` backed by the
+/// GlobalBuddyAlloc static `$heap`. Use it once per program.
+#[macro_export]
+macro_rules! c_api {
+    ($heap:path) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn malloc(size: usize) -> *mut u8 {
+            $crate::c_api::malloc(&$heap, size)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn free(ptr: *mut u8) {
+            $crate::c_api::free(&$heap, ptr)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut u8 {
+            $crate::c_api::calloc(&$heap, count, size)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn realloc(ptr: *mut u8, size: usize) -> *mut u8 {
+            $crate::c_api::realloc(&$heap, ptr, size)
+        }
+    };
+}
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size, MALLOC_ALIGN).ok()
+}
+
+/// `size` bytes aligned to MALLOC_ALIGN, null if out of memory
+pub fn malloc<R: RawMutex>(heap: &GlobalBuddyAlloc<R>, size: usize) -> *mut u8 {
+    layout(size)
+        .and_then(|layout| heap.allocate(layout).ok())
+        .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+}
+
+/// Free `ptr`, null is ignored.
+///
+/// # Safety
+///
+/// `ptr` must be null or come from these functions on the same heap.
+pub unsafe fn free<R: RawMutex>(heap: &GlobalBuddyAlloc<R>, ptr: *mut u8) {
+    heap.with(|heap| heap.free(ptr))
+}
+
+/// `count * size` zeroed bytes, null on overflow or if out of memory
+pub fn calloc<R: RawMutex>(heap: &GlobalBuddyAlloc<R>, count: usize, size: usize) -> *mut u8 {
+    count
+        .checked_mul(size)
+        .and_then(layout)
+        .and_then(|layout| heap.allocate_zeroed(layout).ok())
+        .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+}
+
+/// Resize `ptr` to `size` bytes, in place if its block allows, else moved
+/// to a new block. A null `ptr` allocates, a zero `size` frees and returns
+/// null. On failure null is returned and `ptr` stays live.
+///
+/// # Safety
+///
+/// `ptr` must be null or come from these functions on the same heap.
+pub unsafe fn realloc<R: RawMutex>(
+    heap: &GlobalBuddyAlloc<R>,
+    ptr: *mut u8,
+    size: usize,
+) -> *mut u8 {
+    if ptr.is_null() {
+        return malloc(heap, size);
+    }
+    if size == 0 {
+        free(heap, ptr);
+        return core::ptr::null_mut();
+    }
+    heap.with(|heap| {
+        if heap.resize_in_place(ptr, size) {
+            return ptr;
+        }
+        let Some(p) = layout(size).and_then(|layout| heap.allocate(layout).ok()) else {
+            return core::ptr::null_mut();
+        };
+        let p = p.as_mut_ptr();
+        p.copy_from_nonoverlapping(ptr, heap.alloc_size(ptr).min(size));
+        heap.free(ptr);
+        p
+    })
+}
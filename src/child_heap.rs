@@ -0,0 +1,116 @@
+//! Child heap
+//! A buddy heap carved out of a parent allocator, isolated from it.
+//!
+//! The child owns a fixed slice of the parent and never falls back to it,
+//! so a bursty or untrusted component runs out of its own memory without
+//! touching anyone else's. The slice goes back to the parent on drop.
+
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        error::Error,
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        mem::align_of,
+        ops::Range,
+        ptr::NonNull,
+    },
+};
+
+pub struct ChildHeap<'a, A: Allocator> {
+    parent: &'a A,
+    /// the slice taken from the parent
+    region: NonNull<u8>,
+    region_layout: Layout,
+    heap: BuddyAlloc,
+    /// requested bytes in use
+    used: Cell<usize>,
+    /// allocations the child couldn't serve
+    failures: Cell<usize>,
+}
+
+impl<'a, A: Allocator> ChildHeap<'a, A> {
+    /// Take `len` bytes from `parent` and build a heap over them,
+    /// see BuddyAllocParam::new for `leaf_size`.
+    /// Panics like BuddyAlloc::new if `len` can't hold the heap metadata.
+    pub fn new(parent: &'a A, len: usize, leaf_size: usize) -> Result<Self, Error> {
+        let region_layout = Layout::from_size_align(len, align_of::<usize>())
+            .map_err(|_| Error::SizeUnsupported)?;
+        let region = parent.allocate(region_layout)?.as_non_null_ptr();
+        let param = BuddyAllocParam::new(region.as_ptr(), len, leaf_size);
+        Ok(ChildHeap {
+            parent,
+            region,
+            region_layout,
+            heap: unsafe { BuddyAlloc::new(param) },
+            used: Cell::new(0),
+            failures: Cell::new(0),
+        })
+    }
+
+    /// the heap inside the slice
+    pub fn heap(&self) -> &BuddyAlloc {
+        &self.heap
+    }
+
+    /// bytes taken from the parent, the hard limit of the child
+    pub fn quota(&self) -> usize {
+        self.region_layout.size()
+    }
+
+    /// requested bytes in use
+    pub fn used_bytes(&self) -> usize {
+        self.used.get()
+    }
+
+    /// allocations refused because the child ran out
+    pub fn failures(&self) -> usize {
+        self.failures.get()
+    }
+}
+
+impl<A: Allocator> Drop for ChildHeap<'_, A> {
+    fn drop(&mut self) {
+        unsafe { self.parent.deallocate(self.region, self.region_layout) };
+    }
+}
+
+impl<A: Allocator> HeapRange for ChildHeap<'_, A> {
+    fn heap_range(&self) -> Range<usize> {
+        self.heap.heap_range()
+    }
+}
+
+impl<A: Allocator> HeapInspect for ChildHeap<'_, A> {
+    unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        self.heap.alloc_size(p)
+    }
+
+    fn stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    fn validate(&self) -> Result<(), Corruption> {
+        self.heap.validate()
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for ChildHeap<'_, A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // no fallback to the parent
+        let p = self.heap.allocate(layout).inspect_err(|_| {
+            self.failures.set(self.failures.get() + 1);
+        })?;
+        self.used.set(self.used.get() + layout.size());
+        Ok(p)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.used.set(self.used.get() - layout.size());
+        self.heap.deallocate(ptr, layout)
+    }
+}
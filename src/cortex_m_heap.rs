@@ -0,0 +1,166 @@
+//! Cortex-M heap
+//! A drop-in global allocator for Cortex-M firmware.
+//!
+//! Mirrors the embedded-alloc ergonomics: a const `empty()` static, one
+//! `init` call at startup and a `critical_section::with` around every heap
+//! operation. The target picks the critical section implementation, e.g.
+//! `cortex-m`'s `critical-section-single-core` feature masking interrupts,
+//! or a spinlock across cores on a multi-core part like the RP2040.
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static HEAP: CortexMHeap = CortexMHeap::empty();
+//!
+//! extern "C" {
+//!     static mut __sheap: u8;
+//!     static mut _heap_end: u8;
+//! }
+//!
+//! unsafe { HEAP.init_range(addr_of_mut!(__sheap), addr_of_mut!(_heap_end)) };
+//! ```
+//!
+//! # Embassy and RTIC
+//!
+//! `empty()` is const so the heap can be a plain static, initialized from
+//! `main` before the executor or the RTIC `init` task spawns anything.
+//! Every operation runs in a critical section, so tasks on thread mode
+//! executors, interrupt executors and RTIC tasks of any priority may
+//! allocate; the cost is the interrupt latency of one heap operation.
+//!
+//! `uninit_region!` declares the heap memory outside `.bss`, so the runtime
+//! doesn't spend startup time zeroing it:
+//!
+//! ```ignore
+//! HEAP.init_uninit(unsafe { uninit_region!(16 * 1024) });
+//! ```
+
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam, MIN_LEAF_SIZE_ALIGN},
+        error::Error,
+    },
+    core::{
+        alloc::{Allocator, GlobalAlloc, Layout},
+        cell::RefCell,
+        mem::MaybeUninit,
+        ptr::NonNull,
+    },
+    critical_section::Mutex,
+};
+
+/// Declare a `[MaybeUninit<u8>; $size]` outside `.bss` and
+/// evaluate to a `&'static mut` to it, see CortexMHeap::init_uninit.
+///
+/// # Safety
+///
+/// Each expansion must be evaluated at most once.
+#[macro_export]
+macro_rules! uninit_region {
+    ($size:expr) => {{
+        #[cfg_attr(target_os = "none", link_section = ".uninit.buddy_alloc")]
+        static mut REGION: [core::mem::MaybeUninit<u8>; $size] =
+            [core::mem::MaybeUninit::uninit(); $size];
+        &mut *core::ptr::addr_of_mut!(REGION)
+    }};
+}
+
+/// CortexMHeap
+/// a BuddyAlloc global allocator guarded by a critical section
+pub struct CortexMHeap {
+    heap: Mutex<RefCell<Option<BuddyAlloc>>>,
+}
+
+impl Default for CortexMHeap {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl CortexMHeap {
+    /// An empty heap, every allocation fails until `init`.
+    pub const fn empty() -> Self {
+        CortexMHeap {
+            heap: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Initialize the heap over `start_addr..(start_addr + size)`.
+    ///
+    /// # Safety
+    ///
+    /// Call it once, before the first allocation. The range must be
+    /// valid RAM used by nothing else.
+    pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init_range(
+            core::ptr::with_exposed_provenance_mut(start_addr),
+            core::ptr::with_exposed_provenance_mut(start_addr + size),
+        );
+    }
+
+    /// Initialize the heap over `start..end`, typically two linker symbols.
+    ///
+    /// # Safety
+    ///
+    /// see CortexMHeap::init
+    pub unsafe fn init_range(&self, start: *mut u8, end: *mut u8) {
+        let param = BuddyAllocParam::new(start, end as usize - start as usize, MIN_LEAF_SIZE_ALIGN);
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            assert!(heap.is_none(), "heap already initialized");
+            heap.replace(BuddyAlloc::new(param));
+        });
+    }
+
+    /// Initialize the heap over memory that needs no zeroing,
+    /// typically from `uninit_region!`. Panics if already initialized.
+    pub fn init_uninit(&self, region: &'static mut [MaybeUninit<u8>]) {
+        let range = region.as_mut_ptr_range();
+        // the region is exclusively ours for the rest of the program
+        unsafe { self.init_range(range.start.cast(), range.end.cast()) };
+    }
+
+    /// free bytes, zero before `init`
+    pub fn free(&self) -> usize {
+        critical_section::with(|cs| {
+            let mut free = 0;
+            if let Some(heap) = self.heap.borrow_ref(cs).as_ref() {
+                heap.for_each_free_block(|_, size| free += size);
+            }
+            free
+        })
+    }
+
+    /// Allocate, failing with NotInitialized before `init`,
+    /// see BuddyAlloc::try_allocate
+    pub fn try_allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, Error> {
+        critical_section::with(|cs| {
+            self.heap
+                .borrow_ref(cs)
+                .as_ref()
+                .ok_or(Error::NotInitialized)?
+                .try_allocate(layout)
+        })
+    }
+}
+
+unsafe impl GlobalAlloc for CortexMHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        critical_section::with(|cs| {
+            self.heap
+                .borrow_ref(cs)
+                .as_ref()
+                .and_then(|heap| heap.allocate(layout).ok())
+                .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(p) = NonNull::new(ptr) {
+            critical_section::with(|cs| {
+                if let Some(heap) = self.heap.borrow_ref(cs).as_ref() {
+                    heap.deallocate(p, layout);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Descriptor arrays
+//! N individually aligned descriptors carved out of one allocation.
+//!
+//! DMA engines often want rings of descriptors where each entry starts on
+//! its own boundary, e.g. a cache line. The stride is the descriptor size
+//! rounded up to that alignment, the whole array is one zeroed block
+//! aligned the same way.
+
+use {
+    crate::error::Error,
+    core::{
+        alloc::{Allocator, Layout},
+        marker::PhantomData,
+        ptr::NonNull,
+    },
+};
+
+pub struct DescriptorArray<'a, T, A: Allocator> {
+    alloc: &'a A,
+    base: NonNull<u8>,
+    layout: Layout,
+    stride: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T, A: Allocator> DescriptorArray<'a, T, A> {
+    /// Allocate `len` zeroed descriptors from `alloc`, each aligned to `align`
+    /// and to the alignment of `T`.
+    pub fn new(alloc: &'a A, len: usize, align: usize) -> Result<Self, Error> {
+        let entry = Layout::new::<T>()
+            .align_to(align)
+            .map_err(|_| Error::AlignmentUnsupported)?
+            .pad_to_align();
+        let stride = entry.size();
+        let size = stride.checked_mul(len).ok_or(Error::SizeUnsupported)?;
+        let layout =
+            Layout::from_size_align(size, entry.align()).map_err(|_| Error::SizeUnsupported)?;
+        let base = alloc.allocate_zeroed(layout)?.as_non_null_ptr();
+        Ok(DescriptorArray {
+            alloc,
+            base,
+            layout,
+            stride,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// number of descriptors
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// bytes from one descriptor to the next
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// the first descriptor, e.g. the ring base handed to the device
+    pub fn as_ptr(&self) -> NonNull<T> {
+        self.base.cast()
+    }
+
+    /// the descriptor at `index`, None past the end
+    pub fn get(&self, index: usize) -> Option<NonNull<T>> {
+        (index < self.len).then(|| unsafe { self.base.add(index * self.stride).cast() })
+    }
+
+    /// every descriptor in order
+    pub fn iter(&self) -> impl Iterator<Item = NonNull<T>> + '_ {
+        (0..self.len).map(|i| unsafe { self.base.add(i * self.stride).cast() })
+    }
+}
+
+impl<T, A: Allocator> Drop for DescriptorArray<'_, T, A> {
+    fn drop(&mut self) {
+        unsafe { self.alloc.deallocate(self.base, self.layout) };
+    }
+}
@@ -0,0 +1,71 @@
+//! Errors
+//! Why an inherent API call failed, the Allocator impls keep AllocError.
+
+use {
+    crate::inspect,
+    core::{alloc::AllocError, fmt},
+};
+
+/// Why an operation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// not enough free memory
+    OutOfMemory,
+    /// enough free memory, but no single block large enough
+    FragmentationLimit,
+    /// the heap can't return blocks with the requested alignment
+    AlignmentUnsupported,
+    /// a size the call doesn't serve, e.g. past the freelist block size
+    SizeUnsupported,
+    /// a fixed limit was hit, e.g. a full table
+    QuotaExceeded,
+    /// the heap isn't set up yet
+    NotInitialized,
+    /// inconsistent metadata at `addr`, the heap can't be trusted any more
+    Corruption { addr: usize },
+}
+
+impl Error {
+    /// whether retrying later, after frees, may succeed
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            Error::OutOfMemory | Error::FragmentationLimit | Error::QuotaExceeded
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfMemory => f.write_str("out of memory"),
+            Error::FragmentationLimit => f.write_str("no free block large enough"),
+            Error::AlignmentUnsupported => f.write_str("alignment not supported"),
+            Error::SizeUnsupported => f.write_str("size not supported"),
+            Error::QuotaExceeded => f.write_str("quota exceeded"),
+            Error::NotInitialized => f.write_str("heap not initialized"),
+            Error::Corruption { addr } => write!(f, "heap corrupted at {addr:#x}"),
+        }
+    }
+}
+
+/// AllocError carries no reason, take it as out of memory
+impl From<AllocError> for Error {
+    fn from(_: AllocError) -> Self {
+        Error::OutOfMemory
+    }
+}
+
+impl From<Error> for AllocError {
+    fn from(_: Error) -> Self {
+        AllocError
+    }
+}
+
+impl From<inspect::Corruption> for Error {
+    fn from(corruption: inspect::Corruption) -> Self {
+        Error::Corruption {
+            addr: corruption.addr,
+        }
+    }
+}
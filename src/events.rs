@@ -0,0 +1,120 @@
+//! Event ring
+//! An allocator wrapper remembering the most recent heap events.
+//!
+//! The last N alloc, free and out of memory events are kept in a fixed size
+//! ring, so a fault handler can dump what the heap was doing right before
+//! things went wrong.
+//!
+//! With a sample period of N only 1-in-N allocs and frees are recorded,
+//! out of memory events always are.
+
+use {
+    crate::sampling::Sampler,
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        fmt,
+        ptr::NonNull,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Alloc,
+    Free,
+    /// allocation failed, `addr` is 0
+    Oom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub op: Op,
+    pub size: usize,
+    pub addr: usize,
+    /// the tag set when the event happened
+    pub tag: u32,
+}
+
+pub struct EventRing<A: Allocator, const N: usize> {
+    inner: A,
+    events: [Cell<Option<Event>>; N],
+    /// total events recorded, the next slot is `next % N`
+    next: Cell<usize>,
+    tag: Cell<u32>,
+    sampler: Sampler,
+}
+
+impl<A: Allocator, const N: usize> EventRing<A, N> {
+    pub const fn new(inner: A) -> Self {
+        EventRing {
+            inner,
+            events: [const { Cell::new(None) }; N],
+            next: Cell::new(0),
+            tag: Cell::new(0),
+            sampler: Sampler::new(1),
+        }
+    }
+
+    /// Record only 1-in-`period` allocs and frees.
+    pub fn set_sample_period(&self, period: u32) {
+        self.sampler.set_period(period);
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Tag following events, e.g. with the current task or subsystem id.
+    pub fn set_tag(&self, tag: u32) {
+        self.tag.set(tag);
+    }
+
+    /// recorded events, oldest first
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        let next = self.next.get();
+        (next.saturating_sub(N)..next).filter_map(move |i| self.events[i % N].get())
+    }
+
+    /// Write the recorded events, oldest first, one line per event.
+    pub fn report<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for event in self.events() {
+            writeln!(
+                w,
+                "{:?} size {} addr {:#x} tag {}",
+                event.op, event.size, event.addr, event.tag
+            )?;
+        }
+        Ok(())
+    }
+
+    fn record(&self, op: Op, size: usize, addr: usize) {
+        if N == 0 || (op != Op::Oom && !self.sampler.sample()) {
+            return;
+        }
+        let next = self.next.get();
+        self.events[next % N].set(Some(Event {
+            op,
+            size,
+            addr,
+            tag: self.tag.get(),
+        }));
+        self.next.set(next.wrapping_add(1));
+    }
+}
+
+unsafe impl<A: Allocator, const N: usize> Allocator for EventRing<A, N> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let r = self.inner.allocate(layout);
+        match r {
+            Ok(p) => self.record(Op::Alloc, layout.size(), p.as_mut_ptr() as usize),
+            Err(_) => self.record(Op::Oom, layout.size(), 0),
+        }
+        r
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.record(Op::Free, layout.size(), ptr.as_ptr() as usize);
+        self.inner.deallocate(ptr, layout)
+    }
+}
@@ -0,0 +1,76 @@
+//! C FFI
+//! `extern "C"` entry points over a BuddyAlloc,
+//! the allocator state is kept at the start of the heap region.
+
+use {
+    crate::buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    core::mem::{align_of, size_of},
+};
+
+/// Build a heap over `base..(base + len)` and return its handle,
+/// null if the region can't hold the allocator state.
+///
+/// # Safety
+///
+/// see BuddyAlloc::new, panics abort the caller.
+#[no_mangle]
+pub unsafe extern "C" fn buddy_init(
+    base: *mut u8,
+    len: usize,
+    leaf_size: usize,
+) -> *mut BuddyAlloc {
+    let offset = base.align_offset(align_of::<BuddyAlloc>());
+    let reserved = offset + size_of::<BuddyAlloc>();
+    if base.is_null() || len < reserved {
+        return core::ptr::null_mut();
+    }
+    let heap = base.add(offset).cast::<BuddyAlloc>();
+    let param = BuddyAllocParam::new(base.add(reserved), len - reserved, leaf_size);
+    heap.write(BuddyAlloc::new(param));
+    heap
+}
+
+/// Allocate `size` bytes, null on failure.
+///
+/// # Safety
+///
+/// `heap` must come from buddy_init.
+#[no_mangle]
+pub unsafe extern "C" fn buddy_malloc(heap: *mut BuddyAlloc, size: usize) -> *mut u8 {
+    (*heap).malloc(size)
+}
+
+/// Free `ptr`, null is ignored.
+///
+/// # Safety
+///
+/// `ptr` must come from buddy_malloc on the same heap.
+#[no_mangle]
+pub unsafe extern "C" fn buddy_free(heap: *mut BuddyAlloc, ptr: *mut u8) {
+    (*heap).free(ptr)
+}
+
+/// Usable size of the live allocation at `ptr`, see BuddyAlloc::alloc_size.
+///
+/// # Safety
+///
+/// `ptr` must come from buddy_malloc on the same heap.
+#[no_mangle]
+pub unsafe extern "C" fn buddy_usable_size(heap: *mut BuddyAlloc, ptr: *mut u8) -> usize {
+    (*heap).alloc_size(ptr)
+}
+
+/// Grow or shrink the allocation at `ptr` without moving it,
+/// returns false if it must be moved. see BuddyAlloc::resize_in_place.
+///
+/// # Safety
+///
+/// `ptr` must come from buddy_malloc on the same heap.
+#[no_mangle]
+pub unsafe extern "C" fn buddy_realloc_in_place(
+    heap: *mut BuddyAlloc,
+    ptr: *mut u8,
+    new_size: usize,
+) -> bool {
+    (*heap).resize_in_place(ptr, new_size)
+}
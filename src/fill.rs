@@ -0,0 +1,69 @@
+//! Fill alloc
+//! An allocator wrapper filling memory with recognizable patterns.
+//!
+//! Fresh allocations are filled with one pattern and freed memory with
+//! another, so reads of uninitialized memory and uses after free stand out
+//! in memory dumps.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    ptr::NonNull,
+};
+
+/// Default pattern for fresh allocations
+pub const ALLOC_FILL: u8 = 0xaa;
+/// Default pattern for freed memory
+pub const FREE_FILL: u8 = 0xdd;
+
+/// Patterns used by FillAlloc, `None` leaves memory untouched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FillPatterns {
+    pub on_alloc: Option<u8>,
+    pub on_free: Option<u8>,
+}
+
+impl Default for FillPatterns {
+    fn default() -> Self {
+        FillPatterns {
+            on_alloc: Some(ALLOC_FILL),
+            on_free: Some(FREE_FILL),
+        }
+    }
+}
+
+pub struct FillAlloc<A: Allocator> {
+    inner: A,
+    patterns: FillPatterns,
+}
+
+impl<A: Allocator> FillAlloc<A> {
+    pub const fn new(inner: A, patterns: FillPatterns) -> Self {
+        FillAlloc { inner, patterns }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for FillAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let p = self.inner.allocate(layout)?;
+        if let Some(pattern) = self.patterns.on_alloc {
+            unsafe { p.as_mut_ptr().write_bytes(pattern, p.len()) };
+        }
+        Ok(p)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if let Some(pattern) = self.patterns.on_free {
+            ptr.as_ptr().write_bytes(pattern, layout.size());
+        }
+        self.inner.deallocate(ptr, layout)
+    }
+}
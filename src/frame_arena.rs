@@ -0,0 +1,77 @@
+//! Frame arena
+//! Two bump arenas used alternately, one per frame.
+//!
+//! Allocations go to the current arena. `swap` resets the arena that served
+//! the previous frame and makes it current, so memory from the frame that
+//! just ended stays valid for exactly one more frame.
+
+use {
+    crate::bump_alloc::{BumpAlloc, BumpAllocParam},
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        ptr::NonNull,
+    },
+};
+
+pub struct FrameArena {
+    arenas: [BumpAlloc; 2],
+    current: Cell<usize>,
+}
+
+impl FrameArena {
+    /// # Safety
+    ///
+    /// see BumpAlloc::new, the two arenas must not overlap
+    pub unsafe fn new(front: BumpAllocParam, back: BumpAllocParam) -> Self {
+        FrameArena {
+            arenas: [BumpAlloc::new(front), BumpAlloc::new(back)],
+            current: Cell::new(0),
+        }
+    }
+
+    /// the arena serving the current frame
+    pub fn current(&self) -> &BumpAlloc {
+        &self.arenas[self.current.get()]
+    }
+
+    /// the arena that served the previous frame
+    pub fn previous(&self) -> &BumpAlloc {
+        &self.arenas[self.current.get() ^ 1]
+    }
+
+    /// Retire the previous frame's arena and start the next frame in it.
+    /// Panics in debug builds if allocations of the retired frame are still live.
+    ///
+    /// # Safety
+    ///
+    /// Memory allocated during the previous frame may not be used afterwards.
+    pub unsafe fn swap(&self) {
+        let next = self.current.get() ^ 1;
+        debug_assert_eq!(
+            self.arenas[next].live_allocations(),
+            0,
+            "retired frame still has live allocations"
+        );
+        self.arenas[next].reset();
+        self.current.set(next);
+    }
+
+    fn owner(&self, p: *mut u8) -> &BumpAlloc {
+        if self.arenas[0].contains_ptr(p) {
+            &self.arenas[0]
+        } else {
+            &self.arenas[1]
+        }
+    }
+}
+
+unsafe impl Allocator for FrameArena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.current().allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.owner(ptr.as_ptr()).deallocate(ptr, layout)
+    }
+}
@@ -1,14 +1,41 @@
 //! Freelist allocator
 //! Optimized for fixed small memory block.
+//!
+//! Blocks are BLOCK_SIZE aligned if the region is, alignments above
+//! BLOCK_SIZE are rejected.
 
-use core::{
-    alloc::{AllocError, Allocator, Layout},
-    cell::RefCell,
-    ptr::NonNull,
+use {
+    crate::{
+        error::Error,
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
+        SliceSize,
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::RefCell,
+        mem::MaybeUninit,
+        ops::Range,
+        ptr::NonNull,
+    },
 };
 
+#[cfg(feature = "free-fill")]
+use crate::fill::FREE_FILL;
+#[cfg(feature = "stats")]
+use crate::inspect::Counters;
+#[cfg(feature = "double-free")]
+use crate::inspect::{double_free_panic, DoubleFreeHandler};
+
 /// Fixed size 64 Bytes, can't allocate more in one allocation.
 pub const BLOCK_SIZE: usize = 64;
+/// Bytes at the end of each block taken by the guard word, if enabled.
+pub const GUARD_SIZE: usize = core::mem::size_of::<usize>();
+const GUARD_WORD: usize = usize::from_ne_bytes([0xfd; GUARD_SIZE]);
+const LEN_ERROR_MSG: &str = "FreelistAlloc len must be a non-zero multiple of BLOCK_SIZE";
+const GUARD_ERROR_MSG: &str = "FreelistAlloc guard word overwritten, block overrun detected";
+#[cfg(feature = "free-fill")]
+const POISON_ERROR_MSG: &str = "FreelistAlloc free block written, use after free";
 
 struct Node {
     next: *mut Node,
@@ -30,12 +57,6 @@ impl Node {
         }
     }
 
-    fn pop(list: *mut Node) -> *mut Node {
-        let n_list: *mut Node = unsafe { (*list).next };
-        Self::remove(n_list);
-        n_list
-    }
-
     fn push(list: *mut Node, p: *mut u8) {
         let p = p.cast::<Node>();
         unsafe {
@@ -50,7 +71,7 @@ impl Node {
     }
 
     fn is_empty(list: *const Node) -> bool {
-        unsafe { (*list).next as *const Node == list }
+        unsafe { core::ptr::eq((*list).next, list) }
     }
 }
 
@@ -58,11 +79,76 @@ impl Node {
 pub struct FreelistAllocParam {
     base_addr: *const u8,
     len: usize,
+    guard: bool,
+    #[cfg(feature = "free-fill")]
+    poison: bool,
+    round_base: bool,
+    slice_size: SliceSize,
+    #[cfg(feature = "double-free")]
+    double_free: DoubleFreeHandler,
 }
 
+// only describes the memory, FreelistAlloc::new takes it over
+unsafe impl Send for FreelistAllocParam {}
+
 impl FreelistAllocParam {
+    /// `len` must be a non-zero multiple of BLOCK_SIZE,
+    /// checked at compile time when evaluated in a const or static.
     pub const fn new(base_addr: *const u8, len: usize) -> Self {
-        FreelistAllocParam { base_addr, len }
+        assert!(
+            len.is_multiple_of(BLOCK_SIZE) && len != 0,
+            "{}",
+            LEN_ERROR_MSG
+        );
+        FreelistAllocParam {
+            base_addr,
+            len,
+            guard: false,
+            #[cfg(feature = "free-fill")]
+            poison: false,
+            round_base: false,
+            slice_size: SliceSize::Requested,
+            #[cfg(feature = "double-free")]
+            double_free: double_free_panic,
+        }
+    }
+
+    /// Whether allocate reports the requested or the block size
+    pub const fn with_slice_size(mut self, slice_size: SliceSize) -> Self {
+        self.slice_size = slice_size;
+        self
+    }
+
+    /// Keep a guard word at the end of every block, checked on deallocate.
+    /// Allocations are limited to `BLOCK_SIZE - GUARD_SIZE` bytes.
+    pub const fn with_guard(mut self, guard: bool) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Fill blocks with fill::FREE_FILL as they are freed, and panic when a
+    /// block handed out doesn't hold it anymore past its free list node,
+    /// catching writes after free. FreelistAlloc::new poisons the region first.
+    #[cfg(feature = "free-fill")]
+    pub const fn with_poison_on_free(mut self, poison: bool) -> Self {
+        self.poison = poison;
+        self
+    }
+
+    /// Round the base up to BLOCK_SIZE in FreelistAlloc::new, keeping the
+    /// whole blocks left, so every block is BLOCK_SIZE aligned whatever the
+    /// region's alignment. A misaligned region loses one block.
+    pub const fn with_rounded_base(mut self, round_base: bool) -> Self {
+        self.round_base = round_base;
+        self
+    }
+
+    /// Call `handler` instead of freeing a block that is on the free list
+    /// already, the default panics. Frees walk the whole free list.
+    #[cfg(feature = "double-free")]
+    pub const fn with_double_free_handler(mut self, handler: DoubleFreeHandler) -> Self {
+        self.double_free = handler;
+        self
     }
 }
 
@@ -71,67 +157,405 @@ pub struct FreelistAlloc {
     base_addr: usize,
     /// memory end addr
     end_addr: usize,
+    /// guard words enabled
+    guard: bool,
+    /// freed blocks get FREE_FILL, checked when handed out
+    #[cfg(feature = "free-fill")]
+    poison: bool,
+    slice_size: SliceSize,
     free: RefCell<*mut Node>,
+    #[cfg(feature = "stats")]
+    counters: Counters,
+    #[cfg(feature = "double-free")]
+    double_free: DoubleFreeHandler,
 }
 
+// the heap owns its memory, moving it moves every pointer into it
+unsafe impl Send for FreelistAlloc {}
+
 impl FreelistAlloc {
     /// # Safety
     ///
     /// The `base_addr..(base_addr + len)` must be allocated before use,
     /// and must guarantee no others write to the memory range, otherwise behavior is undefined.
     pub unsafe fn new(param: FreelistAllocParam) -> Self {
-        let FreelistAllocParam { base_addr, len } = param;
+        let FreelistAllocParam {
+            base_addr,
+            len,
+            guard,
+            #[cfg(feature = "free-fill")]
+            poison,
+            round_base,
+            slice_size,
+            #[cfg(feature = "double-free")]
+            double_free,
+        } = param;
+        let (base_addr, len) = if round_base {
+            let pad = base_addr.align_offset(BLOCK_SIZE).min(len);
+            (
+                base_addr.wrapping_add(pad),
+                (len - pad) / BLOCK_SIZE * BLOCK_SIZE,
+            )
+        } else {
+            (base_addr, len)
+        };
+        assert!(len != 0, "{}", LEN_ERROR_MSG);
+        let region = base_addr.cast_mut();
+        #[cfg(feature = "free-fill")]
+        if poison {
+            region.write_bytes(FREE_FILL, len);
+        }
         let base_addr = base_addr as usize;
         let end_addr = base_addr + len;
-        debug_assert_eq!(len % BLOCK_SIZE, 0);
 
         let nblocks = len / BLOCK_SIZE;
 
         // initialize free list
-        let free = base_addr as *mut Node;
+        let free = region.cast::<Node>();
         Node::init(free);
 
         let mut addr = base_addr;
         for _ in 0..(nblocks - 1) {
             addr += BLOCK_SIZE;
-            Node::push(free, addr as *mut u8);
+            Node::push(free, region.with_addr(addr));
         }
 
         FreelistAlloc {
             base_addr,
             end_addr,
+            guard,
+            #[cfg(feature = "free-fill")]
+            poison,
+            slice_size,
             free: RefCell::new(free),
+            #[cfg(feature = "stats")]
+            counters: Counters::new(),
+            #[cfg(feature = "double-free")]
+            double_free,
         }
     }
 
+    /// Safe new over a buffer nobody else can reach, its start is rounded up
+    /// to BLOCK_SIZE and its len down to a multiple of it.
+    /// Panics if no block is left.
+    pub fn from_static(buf: &'static mut [MaybeUninit<u8>]) -> Self {
+        let pad = buf.as_ptr().align_offset(BLOCK_SIZE).min(buf.len());
+        let buf = &mut buf[pad..];
+        let len = buf.len() - buf.len() % BLOCK_SIZE;
+        let param = FreelistAllocParam::new(buf.as_mut_ptr().cast(), len);
+        // the buffer is exclusively ours for the rest of the program
+        unsafe { Self::new(param) }
+    }
+
     pub fn contains_ptr(&self, p: *mut u8) -> bool {
         let addr = p as usize;
         addr >= self.base_addr && addr < self.end_addr
     }
+
+    /// Zero every free block, past the free list node it holds,
+    /// or poison it with poison on free. Returns the number of bytes zeroed.
+    pub fn scrub_free(&self) -> usize {
+        let head = *self.free.borrow();
+        if head.is_null() {
+            return 0;
+        }
+        let node_size = core::mem::size_of::<Node>();
+        #[cfg(feature = "free-fill")]
+        let fill = if self.poison { FREE_FILL } else { 0 };
+        #[cfg(not(feature = "free-fill"))]
+        let fill = 0;
+        let mut scrubbed = 0;
+        let mut node = head;
+        loop {
+            unsafe {
+                node.cast::<u8>()
+                    .add(node_size)
+                    .write_bytes(fill, BLOCK_SIZE - node_size)
+            };
+            scrubbed += BLOCK_SIZE - node_size;
+            node = unsafe { (*node).next };
+            if core::ptr::eq(node, head) {
+                break;
+            }
+        }
+        // keep the stores even though nothing reads the memory back
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        scrubbed
+    }
+
+    /// Add a BLOCK_SIZE block from outside the region to the free list.
+    ///
+    /// # Safety
+    ///
+    /// `p` must point to BLOCK_SIZE bytes used by nothing else until allocated from here.
+    /// It doesn't pass `contains_ptr`, so frees must go back to where it came from.
+    pub(crate) unsafe fn push_block(&self, p: *mut u8) {
+        #[cfg(feature = "free-fill")]
+        if self.poison {
+            p.write_bytes(FREE_FILL, BLOCK_SIZE);
+        }
+        let mut free = self.free.borrow_mut();
+        if free.is_null() {
+            *free = p.cast();
+            Node::init(*free);
+        } else {
+            Node::push(*free, p);
+        }
+    }
+
+    /// Like allocate, but the error says why nothing was handed out
+    pub fn try_allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, Error> {
+        self.allocate(layout).map_err(|_| {
+            if layout.size() > self.max_alloc_size() {
+                Error::SizeUnsupported
+            } else if layout.align() > BLOCK_SIZE {
+                Error::AlignmentUnsupported
+            } else {
+                Error::OutOfMemory
+            }
+        })
+    }
+
+    /// max bytes of one allocation
+    pub fn max_alloc_size(&self) -> usize {
+        if self.guard {
+            BLOCK_SIZE - GUARD_SIZE
+        } else {
+            BLOCK_SIZE
+        }
+    }
+
+    /// Allocate up to max_alloc_size bytes, returns null if out of memory
+    /// or too large. For C-style callers, without Layout.
+    pub fn malloc(&self, size: usize) -> *mut u8 {
+        match Layout::from_size_align(size, 1) {
+            Ok(layout) => self
+                .allocate(layout)
+                .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr()),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    /// Free an allocation from malloc, or any other one of this heap, null is ignored.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a live allocation of this heap.
+    pub unsafe fn free(&self, ptr: *mut u8) {
+        if let Some(p) = NonNull::new(ptr) {
+            self.deallocate(p, Layout::new::<u8>());
+        }
+    }
+
+    /// usable size of the live allocation at p
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this allocator and not freed yet.
+    pub unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        debug_assert!(self.contains_ptr(p.cast_mut()));
+        self.max_alloc_size()
+    }
+
+    /// Walk the free list like validate, checking every node lies on a
+    /// block boundary of the region, or is a block from `push_block` that
+    /// `pushed` accepts, before following it.
+    pub(crate) fn validate_with(&self, pushed: impl Fn(usize) -> bool) -> Result<(), Corruption> {
+        let placed = |node: *mut Node| {
+            let addr = node as usize;
+            if self.contains_ptr(node.cast()) {
+                (addr - self.base_addr).is_multiple_of(BLOCK_SIZE)
+            } else {
+                node.is_aligned() && pushed(addr)
+            }
+        };
+        let head = *self.free.borrow();
+        if head.is_null() {
+            return Ok(());
+        }
+        if !placed(head) {
+            return Err(Corruption {
+                addr: head as usize,
+            });
+        }
+        let mut prev = unsafe { (*head).prev };
+        let mut node = head;
+        loop {
+            let next = unsafe { (*node).next };
+            if !core::ptr::eq(unsafe { (*node).prev }, prev) || !placed(next) {
+                return Err(Corruption {
+                    addr: node as usize,
+                });
+            }
+            prev = node;
+            node = next;
+            if core::ptr::eq(node, head) {
+                // closing the ring
+                return if core::ptr::eq(unsafe { (*head).prev }, prev) {
+                    Ok(())
+                } else {
+                    Err(Corruption {
+                        addr: head as usize,
+                    })
+                };
+            }
+        }
+    }
+
+    /// whether p is on the free list
+    #[cfg(feature = "double-free")]
+    fn is_free(&self, p: *mut u8) -> bool {
+        let head = *self.free.borrow();
+        if head.is_null() {
+            return false;
+        }
+        let mut node = head;
+        loop {
+            if core::ptr::eq(node.cast(), p) {
+                return true;
+            }
+            node = unsafe { (*node).next };
+            if core::ptr::eq(node, head) {
+                return false;
+            }
+        }
+    }
+
+    /// unlink the first free block aligned to `align`, in pop order
+    fn take_block(&self, align: usize) -> Option<*mut u8> {
+        let mut free = self.free.borrow_mut();
+        let head = *free;
+        if head.is_null() {
+            return None;
+        }
+        let mut node = unsafe { (*head).next };
+        loop {
+            if node.cast::<u8>().align_offset(align) == 0 {
+                if !core::ptr::eq(node, head) {
+                    Node::remove(node);
+                } else if Node::is_empty(head) {
+                    *free = core::ptr::null_mut();
+                } else {
+                    *free = unsafe { (*head).next };
+                    Node::remove(head);
+                }
+                return Some(node.cast());
+            }
+            if core::ptr::eq(node, head) {
+                return None;
+            }
+            node = unsafe { (*node).next };
+        }
+    }
+
+    fn guard_ptr(p: *mut u8) -> *mut usize {
+        p.wrapping_add(BLOCK_SIZE - GUARD_SIZE).cast()
+    }
+}
+
+impl HeapRange for FreelistAlloc {
+    fn heap_range(&self) -> Range<usize> {
+        self.base_addr..self.end_addr
+    }
+}
+
+/// Blocks added by `push_block` count as free, but aren't owned,
+/// and fail validate, see validate_with
+impl HeapInspect for FreelistAlloc {
+    unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        FreelistAlloc::alloc_size(self, p)
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut free_blocks = 0;
+        let head = *self.free.borrow();
+        if !head.is_null() {
+            let mut node = head;
+            loop {
+                free_blocks += 1;
+                node = unsafe { (*node).next };
+                if core::ptr::eq(node, head) {
+                    break;
+                }
+            }
+        }
+        let mut stats = HeapStats {
+            total_bytes: self.end_addr - self.base_addr,
+            free_bytes: free_blocks * BLOCK_SIZE,
+            largest_free: if free_blocks > 0 { BLOCK_SIZE } else { 0 },
+            free_blocks,
+            ..HeapStats::default()
+        };
+        stats.used_bytes = stats.total_bytes.saturating_sub(stats.free_bytes);
+        #[cfg(feature = "stats")]
+        self.counters.fill(&mut stats);
+        stats
+    }
+
+    fn validate(&self) -> Result<(), Corruption> {
+        self.validate_with(|_| false)
+    }
 }
 
 unsafe impl Allocator for FreelistAlloc {
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
         let nbytes = layout.size();
-        // TODO: alignment!
-        if nbytes > BLOCK_SIZE || self.free.borrow().is_null() {
+        let block = if nbytes > self.max_alloc_size() || layout.align() > BLOCK_SIZE {
+            None
+        } else {
+            self.take_block(layout.align())
+        };
+        let Some(p) = block else {
+            #[cfg(feature = "stats")]
+            self.counters.fail();
             return Err(AllocError);
+        };
+        #[cfg(feature = "stats")]
+        self.counters.alloc(BLOCK_SIZE);
+        #[cfg(feature = "free-fill")]
+        if self.poison {
+            let node_size = core::mem::size_of::<Node>();
+            let block =
+                unsafe { core::slice::from_raw_parts(p.add(node_size), BLOCK_SIZE - node_size) };
+            if let Some(i) = block.iter().position(|&b| b != FREE_FILL) {
+                panic!("{} at {:#x}", POISON_ERROR_MSG, p as usize + node_size + i);
+            }
         }
-
-        let is_last = Node::is_empty(self.free.borrow().cast_const());
-        let p = Node::pop(*self.free.borrow_mut()) as *mut u8;
-        if is_last {
-            self.free.replace(core::ptr::null_mut());
+        if self.guard {
+            unsafe { Self::guard_ptr(p).write_unaligned(GUARD_WORD) };
         }
+        let len = match self.slice_size {
+            SliceSize::Requested => layout.size(),
+            SliceSize::Block => self.max_alloc_size(),
+        };
         Ok(NonNull::slice_from_raw_parts(
             unsafe { NonNull::new_unchecked(p) },
-            layout.size(),
+            len,
         ))
     }
 
     unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
         let p = ptr.as_ptr();
         debug_assert!(self.contains_ptr(p));
+        #[cfg(feature = "double-free")]
+        if self.is_free(p) {
+            (self.double_free)(p);
+            return;
+        }
+        if self.guard {
+            assert_eq!(
+                Self::guard_ptr(p).read_unaligned(),
+                GUARD_WORD,
+                "{}",
+                GUARD_ERROR_MSG
+            );
+        }
+        #[cfg(feature = "stats")]
+        self.counters.free(BLOCK_SIZE);
+        #[cfg(feature = "free-fill")]
+        if self.poison {
+            p.write_bytes(FREE_FILL, BLOCK_SIZE);
+        }
         let f = self.free.borrow();
         if f.is_null() {
             let n = p.cast();
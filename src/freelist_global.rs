@@ -0,0 +1,67 @@
+//! FreelistGlobalAlloc
+//! FreelistAlloc alone as a `#[global_allocator]`, without the buddy heap.
+//!
+//! For small targets whose heap objects all fit in a block. Larger
+//! requests fail, ending up in the alloc error handler. The free list is
+//! built on first use, so the allocator can be const constructed in a
+//! static.
+
+use {
+    crate::freelist_alloc::{FreelistAlloc, FreelistAllocParam},
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        cell::RefCell,
+        ptr::NonNull,
+    },
+};
+
+/// FreelistGlobalAlloc
+/// single threaded like NonThreadsafeAlloc, wrap it in Locked for more cores
+pub struct FreelistGlobalAlloc {
+    param: FreelistAllocParam,
+    inner: RefCell<Option<FreelistAlloc>>,
+}
+
+impl FreelistGlobalAlloc {
+    /// see FreelistAlloc::new
+    pub const fn new(param: FreelistAllocParam) -> Self {
+        FreelistGlobalAlloc {
+            param,
+            inner: RefCell::new(None),
+        }
+    }
+
+    /// Run `f` on the freelist heap, built first if needed,
+    /// e.g. to read statistics or scrub it.
+    pub fn with<R>(&self, f: impl FnOnce(&FreelistAlloc) -> R) -> R {
+        let mut inner = self.inner.borrow_mut();
+        f(inner.get_or_insert_with(|| unsafe { FreelistAlloc::new(self.param) }))
+    }
+}
+
+unsafe impl Allocator for FreelistGlobalAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|alloc| alloc.allocate(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with(|alloc| alloc.deallocate(ptr, layout))
+    }
+}
+
+// only sound without other threads or interrupts touching the heap,
+// lets it be a #[global_allocator] on single core targets
+unsafe impl Sync for FreelistGlobalAlloc {}
+
+unsafe impl GlobalAlloc for FreelistGlobalAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
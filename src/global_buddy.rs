@@ -0,0 +1,141 @@
+//! GlobalBuddyAlloc
+//! A BuddyAlloc behind a mutex, const constructed for `#[global_allocator]`.
+//!
+//! The heap is built on first use with the mutex held, so it can live in a
+//! static and be shared by every thread or core. See ThreadsafeAlloc for
+//! the freelist in front of the buddy heap.
+
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        locked::{Locked, RawMutex, RawSpinlock},
+    },
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        cell::UnsafeCell,
+        ptr::NonNull,
+    },
+};
+
+/// Declare `static $name: GlobalBuddyAlloc` over a `$size` bytes buffer
+/// of its own, the metadata kept apart like `buddy_heap!` does. The leaf
+/// size defaults to MIN_LEAF_SIZE_ALIGN:
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// # use core::alloc::{Allocator, Layout};
+/// buddy_alloc::static_heap!(HEAP, 128 * 1024);
+/// buddy_alloc::static_heap!(pub(crate) PAGES, 1 << 20, 4096);
+///
+/// assert!(HEAP.allocate(Layout::new::<u64>()).is_ok());
+/// ```
+#[macro_export]
+macro_rules! static_heap {
+    ($vis:vis $name:ident, $size:expr $(,)?) => {
+        $crate::static_heap!($vis $name, $size, $crate::buddy_alloc::MIN_LEAF_SIZE_ALIGN);
+    };
+    ($vis:vis $name:ident, $size:expr, $leaf_size:expr $(,)?) => {
+        $vis static $name: $crate::global_buddy::GlobalBuddyAlloc =
+            $crate::global_buddy::GlobalBuddyAlloc::new($crate::buddy_heap!($size, $leaf_size));
+    };
+}
+
+/// the heap, built by the first caller, only touched within Locked
+struct LazyHeap {
+    param: BuddyAllocParam,
+    heap: UnsafeCell<Option<BuddyAlloc>>,
+}
+
+impl LazyHeap {
+    fn get(&self) -> &BuddyAlloc {
+        // Locked serializes every call, nothing else holds a reference
+        unsafe { (*self.heap.get()).get_or_insert_with(|| BuddyAlloc::new(self.param)) }
+    }
+}
+
+/// GlobalBuddyAlloc
+/// one buddy heap, one caller at a time through a `R` mutex
+pub struct GlobalBuddyAlloc<R: RawMutex = RawSpinlock> {
+    inner: Locked<LazyHeap, R>,
+}
+
+impl<R: RawMutex> GlobalBuddyAlloc<R> {
+    /// see BuddyAlloc::new
+    pub const fn new(param: BuddyAllocParam) -> Self {
+        GlobalBuddyAlloc {
+            inner: Locked::new(LazyHeap {
+                param,
+                heap: UnsafeCell::new(None),
+            }),
+        }
+    }
+
+    /// Run `f` on the heap with the mutex held, built first if needed,
+    /// e.g. to read statistics or call inherent methods.
+    pub fn with<T>(&self, f: impl FnOnce(&BuddyAlloc) -> T) -> T {
+        self.inner.with(|lazy| f(lazy.get()))
+    }
+}
+
+unsafe impl<R: RawMutex> Allocator for GlobalBuddyAlloc<R> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|heap| heap.allocate(layout))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|heap| heap.allocate_zeroed(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with(|heap| heap.deallocate(ptr, layout))
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|heap| heap.grow(ptr, old_layout, new_layout))
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|heap| heap.shrink(ptr, old_layout, new_layout))
+    }
+}
+
+unsafe impl<R: RawMutex> GlobalAlloc for GlobalBuddyAlloc<R> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.allocate_zeroed(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) else {
+            return core::ptr::null_mut();
+        };
+        let ptr = NonNull::new_unchecked(ptr);
+        let resized = if new_size >= layout.size() {
+            self.grow(ptr, layout, new_layout)
+        } else {
+            self.shrink(ptr, layout, new_layout)
+        };
+        resized.map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+}
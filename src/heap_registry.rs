@@ -0,0 +1,178 @@
+//! Heap registry
+//! Several heaps behind one global allocator.
+//!
+//! Allocation tries the heaps in registration order, deallocation goes to
+//! the heap whose address range holds the pointer, found by binary search
+//! in a RegionTable. It does no locking itself and is Sync when the heaps
+//! are, wrap single threaded ones the way NonThreadsafeAlloc does.
+//!
+//! For tiered memory register the heaps fastest first, e.g. TCM, SRAM then
+//! SDRAM: allocations fill the fastest heap and spill to slower ones, and
+//! `migrate` moves hot blocks back once room frees up.
+
+use {
+    crate::region_table::RegionTable,
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        ops::Range,
+        ptr::NonNull,
+        sync::atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Address range a heap hands out memory from
+pub trait HeapRange {
+    fn heap_range(&self) -> Range<usize>;
+}
+
+/// Where an allocation should live
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locality {
+    #[default]
+    Any,
+    /// in the heap holding this address, e.g. next to related data
+    Near(usize),
+}
+
+/// How long an allocation is expected to live
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lifetime {
+    #[default]
+    Unknown,
+    /// freed soon, preferably placed in reclaimable heaps
+    Short,
+    /// lives long, never placed in reclaimable heaps
+    Long,
+}
+
+/// Hints steering `allocate_with_hint` between heaps
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocHint {
+    /// index of the heap to try first
+    pub region: Option<usize>,
+    pub locality: Locality,
+    pub lifetime: Lifetime,
+}
+
+/// HeapRegistry
+/// owns N heaps, dispatching deallocation by address
+pub struct HeapRegistry<A: Allocator + HeapRange, const N: usize> {
+    heaps: [A; N],
+    regions: RegionTable<N>,
+    /// heaps the system may want to shrink or power down
+    reclaimable: [AtomicBool; N],
+}
+
+impl<A: Allocator + HeapRange, const N: usize> HeapRegistry<A, N> {
+    /// Panics if heaps overlap.
+    pub fn new(heaps: [A; N]) -> Self {
+        let mut regions = RegionTable::new();
+        for (i, heap) in heaps.iter().enumerate() {
+            regions.insert(heap.heap_range(), i);
+        }
+        HeapRegistry {
+            heaps,
+            regions,
+            reclaimable: core::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+
+    /// Mark heap `index` as one the system may shrink or power down,
+    /// long lived allocations stay out of it.
+    pub fn set_reclaimable(&self, index: usize, reclaimable: bool) {
+        self.reclaimable[index].store(reclaimable, Ordering::Relaxed);
+    }
+
+    /// Allocate from the heap the hint points at if it can,
+    /// then from the other heaps in registration order: reclaimable heaps
+    /// first for short lived allocations, last otherwise.
+    pub fn allocate_with_hint(
+        &self,
+        layout: Layout,
+        hint: AllocHint,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let eligible = |i: usize| hint.lifetime != Lifetime::Long || !self.is_reclaimable(i);
+        let first = hint.region.or(match hint.locality {
+            Locality::Any => None,
+            Locality::Near(addr) => self.regions.find(addr),
+        });
+        if let Some(i) = first.filter(|&i| i < N && eligible(i)) {
+            if let Ok(p) = self.heaps[i].allocate(layout) {
+                return Ok(p);
+            }
+        }
+        let short = hint.lifetime == Lifetime::Short;
+        [short, !short]
+            .into_iter()
+            .flat_map(|reclaimable| (0..N).filter(move |&i| self.is_reclaimable(i) == reclaimable))
+            .filter(|&i| eligible(i))
+            .find_map(|i| self.heaps[i].allocate(layout).ok())
+            .ok_or(AllocError)
+    }
+
+    fn is_reclaimable(&self, index: usize) -> bool {
+        self.reclaimable[index].load(Ordering::Relaxed)
+    }
+
+    pub fn heaps(&self) -> &[A; N] {
+        &self.heaps
+    }
+
+    /// the heap owning p
+    pub fn owner(&self, p: *const u8) -> Option<&A> {
+        self.owner_index(p).map(|i| &self.heaps[i])
+    }
+
+    /// index of the heap owning p
+    pub fn owner_index(&self, p: *const u8) -> Option<usize> {
+        self.regions.find(p as usize)
+    }
+
+    /// Move the block at ptr to heap `to`: allocate, copy, then free the old block.
+    /// Returns the new block, on failure the old one stays valid.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this registry made with `layout`.
+    pub unsafe fn migrate(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        to: usize,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.owner_index(ptr.as_ptr()) == Some(to) {
+            return Ok(NonNull::slice_from_raw_parts(ptr, layout.size()));
+        }
+        let new = self.heaps.get(to).ok_or(AllocError)?.allocate(layout)?;
+        core::ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_mut_ptr(), layout.size());
+        self.deallocate(ptr, layout);
+        Ok(new)
+    }
+}
+
+unsafe impl<A: Allocator + HeapRange, const N: usize> Allocator for HeapRegistry<A, N> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_with_hint(layout, AllocHint::default())
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let owner = self.owner(ptr.as_ptr());
+        debug_assert!(owner.is_some(), "pointer not owned by any heap");
+        if let Some(heap) = owner {
+            heap.deallocate(ptr, layout);
+        }
+    }
+}
+
+unsafe impl<A: Allocator + HeapRange, const N: usize> GlobalAlloc for HeapRegistry<A, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
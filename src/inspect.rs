@@ -0,0 +1,124 @@
+//! Heap inspection
+//! Pointer ownership and introspection shared by the heaps, so wrappers,
+//! registries and debug tooling can be written once over any of them.
+
+use crate::heap_registry::HeapRange;
+#[cfg(feature = "stats")]
+use core::cell::Cell;
+
+/// Usage of a heap at one point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// bytes handed out as blocks, not counting metadata
+    pub total_bytes: usize,
+    /// bytes in free blocks
+    pub free_bytes: usize,
+    /// largest free block
+    pub largest_free: usize,
+    /// number of free blocks
+    pub free_blocks: usize,
+    /// bytes in allocated blocks, `total_bytes - free_bytes`
+    pub used_bytes: usize,
+    /// most bytes allocated at once, zero without the `stats` feature
+    pub peak_used: usize,
+    /// successful allocations, zero without the `stats` feature
+    pub allocs: usize,
+    /// failed allocations, zero without the `stats` feature
+    pub failed_allocs: usize,
+    /// frees, zero without the `stats` feature
+    pub frees: usize,
+}
+
+/// Counters behind the peak and the operation counts of HeapStats
+#[cfg(feature = "stats")]
+pub(crate) struct Counters {
+    live: Cell<usize>,
+    peak: Cell<usize>,
+    allocs: Cell<usize>,
+    failed: Cell<usize>,
+    frees: Cell<usize>,
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Counters {
+            live: Cell::new(0),
+            peak: Cell::new(0),
+            allocs: Cell::new(0),
+            failed: Cell::new(0),
+            frees: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn alloc(&self, bytes: usize) {
+        self.allocs.set(self.allocs.get() + 1);
+        self.grow(bytes);
+    }
+
+    pub(crate) fn fail(&self) {
+        self.failed.set(self.failed.get() + 1);
+    }
+
+    pub(crate) fn free(&self, bytes: usize) {
+        self.frees.set(self.frees.get() + 1);
+        self.shrink(bytes);
+    }
+
+    /// an allocation grew by `bytes`
+    pub(crate) fn grow(&self, bytes: usize) {
+        let live = self.live.get() + bytes;
+        self.live.set(live);
+        self.peak.set(self.peak.get().max(live));
+    }
+
+    /// an allocation shrank by `bytes`
+    pub(crate) fn shrink(&self, bytes: usize) {
+        self.live.set(self.live.get().saturating_sub(bytes));
+    }
+
+    /// fill in the fields only the counters know
+    pub(crate) fn fill(&self, stats: &mut HeapStats) {
+        stats.peak_used = self.peak.get().max(stats.used_bytes);
+        stats.allocs = self.allocs.get();
+        stats.failed_allocs = self.failed.get();
+        stats.frees = self.frees.get();
+    }
+}
+
+/// Called with the pointer of a free of an already free block,
+/// the free is ignored once it returns
+#[cfg(feature = "double-free")]
+pub type DoubleFreeHandler = fn(*const u8);
+
+#[cfg(feature = "double-free")]
+pub(crate) fn double_free_panic(p: *const u8) {
+    panic!("double free of {p:p}");
+}
+
+/// Inconsistent metadata found by `validate`,
+/// at the address of the first bad free block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Corruption {
+    pub addr: usize,
+}
+
+/// Ownership and introspection of a heap
+pub trait HeapInspect: HeapRange {
+    /// Whether p points into memory this heap hands out
+    fn owns(&self, p: *const u8) -> bool {
+        self.heap_range().contains(&(p as usize))
+    }
+
+    /// usable size of the live allocation at p
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this heap and not freed yet.
+    unsafe fn alloc_size(&self, p: *const u8) -> usize;
+
+    fn stats(&self) -> HeapStats;
+
+    /// Walk the free lists and check them against the rest of the metadata
+    fn validate(&self) -> Result<(), Corruption>;
+}
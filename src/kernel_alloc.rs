@@ -0,0 +1,89 @@
+//! Kernel alloc
+//! A shim shaped after the Rust-for-Linux `kernel::alloc::Allocator` trait.
+//!
+//! The kernel trait funnels allocation, resizing and freeing through a single
+//! `realloc` on a static heap. Implement it on a marker type forwarding to a
+//! `KernelAlloc` kept behind the kernel's own lock:
+//!
+//! ```ignore
+//! unsafe impl kernel::alloc::Allocator for SubsysHeap {
+//!     unsafe fn realloc(
+//!         ptr: Option<NonNull<u8>>,
+//!         layout: Layout,
+//!         old_layout: Layout,
+//!         _flags: Flags,
+//!     ) -> Result<NonNull<[u8]>, AllocError> {
+//!         HEAP.lock().realloc(ptr, layout, old_layout)
+//!     }
+//! }
+//! ```
+
+use {
+    crate::buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+/// KernelAlloc
+/// a BuddyAlloc exposing the kernel's realloc semantics
+pub struct KernelAlloc {
+    inner: BuddyAlloc,
+}
+
+impl KernelAlloc {
+    /// # Safety
+    ///
+    /// see BuddyAlloc::new
+    pub unsafe fn new(param: BuddyAllocParam) -> Self {
+        KernelAlloc {
+            inner: BuddyAlloc::new(param),
+        }
+    }
+
+    pub fn inner(&self) -> &BuddyAlloc {
+        &self.inner
+    }
+
+    /// Allocate when `ptr` is None, free when `layout` has zero size,
+    /// otherwise resize, in place when the block allows it.
+    /// Zero sized results are dangling pointers aligned to `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be None or a live allocation of this heap made with `old_layout`.
+    pub unsafe fn realloc(
+        &self,
+        ptr: Option<NonNull<u8>>,
+        layout: Layout,
+        old_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            if let Some(p) = ptr {
+                self.inner.deallocate(p, old_layout);
+            }
+            let dangling =
+                NonNull::new_unchecked(core::ptr::without_provenance_mut(layout.align()));
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+        let Some(p) = ptr else {
+            return self.inner.allocate(layout);
+        };
+        if p.as_ptr().align_offset(layout.align()) == 0
+            && self
+                .inner
+                .resize_in_place(p.as_ptr(), layout.size().max(layout.align()))
+        {
+            return Ok(NonNull::slice_from_raw_parts(p, layout.size()));
+        }
+        let new = self.inner.allocate(layout)?;
+        core::ptr::copy_nonoverlapping(
+            p.as_ptr(),
+            new.as_mut_ptr(),
+            old_layout.size().min(layout.size()),
+        );
+        self.inner.deallocate(p, old_layout);
+        Ok(new)
+    }
+}
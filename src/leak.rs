@@ -0,0 +1,112 @@
+//! Leak tracker
+//! An allocator wrapper recording live allocations in a side table.
+//!
+//! Each allocation is recorded with its address, size and the tag set by
+//! `set_tag` at the time, e.g. a subsystem id, until it is freed. What is
+//! still recorded after a while of steady state is a leak candidate. The
+//! table has `SLOTS` records, allocations made while it is full are counted
+//! but not recorded.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    cell::Cell,
+    fmt,
+    ptr::NonNull,
+};
+
+/// A recorded live allocation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveAllocation {
+    pub addr: usize,
+    pub size: usize,
+    pub tag: u32,
+}
+
+pub struct LeakTracker<A: Allocator, const SLOTS: usize> {
+    inner: A,
+    tag: Cell<u32>,
+    records: [Cell<Option<LiveAllocation>>; SLOTS],
+    /// live allocations missing from the table
+    untracked: Cell<usize>,
+}
+
+impl<A: Allocator, const SLOTS: usize> LeakTracker<A, SLOTS> {
+    pub const fn new(inner: A) -> Self {
+        LeakTracker {
+            inner,
+            tag: Cell::new(0),
+            records: [const { Cell::new(None) }; SLOTS],
+            untracked: Cell::new(0),
+        }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Tag the allocations from now on with `tag`, 0 to begin with.
+    pub fn set_tag(&self, tag: u32) {
+        self.tag.set(tag);
+    }
+
+    /// live allocations made while the table was full
+    pub fn untracked(&self) -> usize {
+        self.untracked.get()
+    }
+
+    /// call `f` with every recorded live allocation, in table order
+    pub fn for_each_live<F: FnMut(LiveAllocation)>(&self, mut f: F) {
+        self.records.iter().filter_map(Cell::get).for_each(&mut f);
+    }
+
+    /// Write one line per recorded live allocation, then a summary line.
+    pub fn report<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        let (mut count, mut bytes) = (0, 0);
+        for record in self.records.iter().filter_map(Cell::get) {
+            writeln!(
+                w,
+                "{:#x}: {} bytes, tag {}",
+                record.addr, record.size, record.tag
+            )?;
+            count += 1;
+            bytes += record.size;
+        }
+        writeln!(
+            w,
+            "{} live allocations, {} bytes, {} untracked",
+            count,
+            bytes,
+            self.untracked()
+        )
+    }
+}
+
+unsafe impl<A: Allocator, const SLOTS: usize> Allocator for LeakTracker<A, SLOTS> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let p = self.inner.allocate(layout)?;
+        let record = LiveAllocation {
+            addr: p.as_mut_ptr() as usize,
+            size: layout.size(),
+            tag: self.tag.get(),
+        };
+        match self.records.iter().find(|slot| slot.get().is_none()) {
+            Some(slot) => slot.set(Some(record)),
+            None => self.untracked.set(self.untracked.get() + 1),
+        }
+        Ok(p)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let addr = ptr.as_ptr() as usize;
+        match self
+            .records
+            .iter()
+            .find(|slot| slot.get().is_some_and(|record| record.addr == addr))
+        {
+            Some(slot) => slot.set(None),
+            None => self.untracked.set(self.untracked.get().saturating_sub(1)),
+        }
+        self.inner.deallocate(ptr, layout)
+    }
+}
@@ -1,16 +1,125 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![feature(allocator_api)]
-#![feature(nonnull_slice_from_raw_parts)]
 #![feature(slice_ptr_get)]
+#![feature(strict_provenance_lints)]
+#![deny(fuzzy_provenance_casts)]
 
+pub mod borrowed;
 pub mod buddy_alloc;
+pub mod bump_alloc;
+#[cfg(feature = "c-api")]
+pub mod c_api;
+pub mod child_heap;
+#[cfg(feature = "cortex-m")]
+pub mod cortex_m_heap;
+pub mod descriptors;
+pub mod error;
+#[cfg(feature = "events")]
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fill;
+pub mod frame_arena;
 pub mod freelist_alloc;
+pub mod freelist_global;
+pub mod global_buddy;
+pub mod heap_registry;
+pub mod inspect;
+#[cfg(feature = "kernel")]
+pub mod kernel_alloc;
+#[cfg(feature = "heavy-debug")]
+pub mod leak;
+#[cfg(feature = "heavy-debug")]
+pub mod lifetime;
+pub mod locked;
+pub mod locked_alloc;
+pub mod memory_map;
+#[cfg(feature = "mte")]
+pub mod mte;
 pub mod non_threadsafe_alloc;
+pub mod object_pool;
+pub mod persist;
+pub mod pin_table;
+#[cfg(any(test, feature = "std"))]
+pub mod rc_alloc;
+pub mod redzone;
+pub mod reentry;
+pub mod region_table;
+pub mod remote_free;
+pub mod rt_pool;
+pub mod sampling;
+#[cfg(any(test, feature = "std"))]
+pub mod scratch;
+pub mod seal;
+pub mod shared_alloc;
+pub mod size_header;
+pub mod slab_alloc;
+pub mod slab_color;
+pub mod small_alloc;
+pub mod snapshot;
+pub mod task_accounting;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
 #[cfg(test)]
 mod tests;
+pub mod tlsf_alloc;
+#[cfg(all(any(test, feature = "std"), any(unix, windows)))]
+pub mod vm_alloc;
+
+/// Length reported by `allocate` in the returned slice
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SliceSize {
+    /// exactly the requested size
+    #[default]
+    Requested,
+    /// the whole underlying block, callers may use the slack
+    Block,
+}
 
 pub use crate::{
-    buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    borrowed::{BorrowedBuddyAlloc, BorrowedFreelistAlloc, BorrowedHeap},
+    buddy_alloc::{
+        atomic::{AtomicBuddyAlloc, AtomicBuddyAllocParam},
+        BlockState, BuddyAlloc, BuddyAllocParam, StaticBuddyHeap,
+    },
+    bump_alloc::{BumpAlloc, BumpAllocParam},
+    child_heap::ChildHeap,
+    descriptors::DescriptorArray,
+    error::Error,
+    fill::{FillAlloc, FillPatterns},
+    frame_arena::FrameArena,
     freelist_alloc::{FreelistAlloc, FreelistAllocParam},
+    freelist_global::FreelistGlobalAlloc,
+    global_buddy::GlobalBuddyAlloc,
+    heap_registry::{AllocHint, HeapRange, HeapRegistry},
+    inspect::{HeapInspect, HeapStats},
+    locked::{Locked, PreemptibleLocked, RawMutex, RawSpinlock},
+    locked_alloc::ThreadsafeAlloc,
+    memory_map::MultiRegionAlloc,
     non_threadsafe_alloc::NonThreadsafeAlloc,
+    object_pool::{ObjectPool, PoolBox},
+    pin_table::PinTable,
+    redzone::RedzoneAlloc,
+    reentry::ReentryGuard,
+    region_table::RegionTable,
+    remote_free::{RemoteFreeAlloc, RemoteFreer},
+    rt_pool::RtPool,
+    seal::SealAlloc,
+    shared_alloc::{HeapOffset, SharedAlloc},
+    size_header::SizeHeaderAlloc,
+    slab_alloc::SlabAlloc,
+    small_alloc::SmallAlloc,
+    snapshot::Snapshot,
+    task_accounting::{TaskAccounting, TaskUsage},
+    tlsf_alloc::{TlsfAlloc, TlsfAllocParam},
+};
+
+#[cfg(feature = "double-free")]
+pub use crate::inspect::DoubleFreeHandler;
+#[cfg(all(any(test, feature = "std"), any(unix, windows)))]
+pub use crate::vm_alloc::VmAlloc;
+#[cfg(any(test, feature = "std"))]
+pub use crate::{
+    rc_alloc::{ArcAlloc, RcAlloc},
+    scratch::scratch,
 };
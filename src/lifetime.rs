@@ -0,0 +1,108 @@
+//! Lifetime tracker
+//! An allocator wrapper reporting how long allocations live.
+//!
+//! Every allocation carries a small header with its allocation time taken
+//! from a user supplied clock. Lifetimes are collected in a histogram of
+//! power of two buckets: bucket i counts lifetimes in `2^i..2^(i+1)` ticks,
+//! bucket 0 also counts lifetimes of 0 ticks.
+//!
+//! With a sample period of N only 1-in-N allocations read the clock and
+//! land in the histogram, the others still carry the header.
+
+use {
+    crate::sampling::Sampler,
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        fmt,
+        ptr::NonNull,
+    },
+};
+
+/// number of histogram buckets
+pub const BUCKETS: usize = u64::BITS as usize;
+/// header of allocations left out by sampling
+const NOT_SAMPLED: u64 = u64::MAX;
+
+pub struct LifetimeTracker<A: Allocator> {
+    inner: A,
+    clock: fn() -> u64,
+    histogram: [Cell<usize>; BUCKETS],
+    sampler: Sampler,
+}
+
+impl<A: Allocator> LifetimeTracker<A> {
+    /// `clock` returns the current time in ticks of any unit.
+    /// It runs inside allocator calls and must not allocate from this heap,
+    /// see ReentryGuard.
+    pub const fn new(inner: A, clock: fn() -> u64) -> Self {
+        LifetimeTracker {
+            inner,
+            clock,
+            histogram: [const { Cell::new(0) }; BUCKETS],
+            sampler: Sampler::new(1),
+        }
+    }
+
+    /// Track only 1-in-`period` allocations.
+    pub fn set_sample_period(&self, period: u32) {
+        self.sampler.set_period(period);
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// freed allocations per lifetime bucket
+    pub fn histogram(&self) -> [usize; BUCKETS] {
+        core::array::from_fn(|i| self.histogram[i].get())
+    }
+
+    /// Write the non-empty buckets, one line per bucket.
+    pub fn report<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for (i, count) in self.histogram().into_iter().enumerate() {
+            if count > 0 {
+                let low = if i == 0 { 0 } else { 1u64 << i };
+                writeln!(w, "{}..{} ticks: {}", low, 1u128 << (i + 1), count)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Layout with the timestamp header, and the offset of the user block.
+    fn with_header(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        Layout::new::<u64>().extend(layout).map_err(|_| AllocError)
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for LifetimeTracker<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (full, offset) = Self::with_header(layout)?;
+        let p = self.inner.allocate(full)?.as_mut_ptr();
+        unsafe {
+            let start = if self.sampler.sample() {
+                (self.clock)()
+            } else {
+                NOT_SAMPLED
+            };
+            p.cast::<u64>().write(start);
+            Ok(NonNull::slice_from_raw_parts(
+                NonNull::new_unchecked(p.add(offset)),
+                layout.size(),
+            ))
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let (full, offset) = Self::with_header(layout).expect("layout");
+        let p = ptr.as_ptr().sub(offset);
+        let start = p.cast::<u64>().read();
+        if start != NOT_SAMPLED {
+            let lifetime = (self.clock)().saturating_sub(start);
+            let bucket = (u64::BITS - 1).saturating_sub(lifetime.leading_zeros()) as usize;
+            self.histogram[bucket].set(self.histogram[bucket].get() + 1);
+        }
+        self.inner.deallocate(NonNull::new_unchecked(p), full);
+    }
+}
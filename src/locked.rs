@@ -0,0 +1,239 @@
+//! Locked
+//! Make any allocator thread-safe behind a pluggable raw mutex.
+//!
+//! `RawMutex` follows `lock_api::RawMutex`, so an RTOS mutex or an
+//! IRQ-masking spinlock implementing one implements the other with the same
+//! methods. With the `lock_api` feature every `lock_api::RawMutex` is one,
+//! e.g. the spin or parking_lot mutexes. `RawSpinlock` is the default, a
+//! plain busy wait.
+//!
+//! `PreemptibleLocked` bounds how long a buddy heap holds the mutex, for
+//! interrupt masking locks on real-time systems.
+
+use {
+    crate::buddy_alloc::BuddyAlloc,
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        ptr::NonNull,
+        sync::atomic::{AtomicBool, Ordering},
+    },
+};
+
+const BUDGET_ERROR_MSG: &str = "merge budget must not be zero";
+
+/// A raw mutex, locked and unlocked without a guard
+///
+/// # Safety
+///
+/// `lock` and a successful `try_lock` must give exclusive access until `unlock`.
+pub unsafe trait RawMutex {
+    /// an unlocked mutex
+    const INIT: Self;
+
+    fn lock(&self);
+
+    fn try_lock(&self) -> bool;
+
+    /// # Safety
+    ///
+    /// The mutex must be held by the caller.
+    unsafe fn unlock(&self);
+}
+
+#[cfg(feature = "lock_api")]
+unsafe impl<M: lock_api::RawMutex> RawMutex for M {
+    const INIT: Self = <M as lock_api::RawMutex>::INIT;
+
+    fn lock(&self) {
+        lock_api::RawMutex::lock(self)
+    }
+
+    fn try_lock(&self) -> bool {
+        lock_api::RawMutex::try_lock(self)
+    }
+
+    unsafe fn unlock(&self) {
+        lock_api::RawMutex::unlock(self)
+    }
+}
+
+/// Busy waiting spinlock
+pub struct RawSpinlock(AtomicBool);
+
+unsafe impl RawMutex for RawSpinlock {
+    const INIT: Self = RawSpinlock(AtomicBool::new(false));
+
+    fn lock(&self) {
+        while !self.try_lock() {
+            while self.0.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.0
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+struct Unlock<'a, R: RawMutex>(&'a R);
+
+impl<R: RawMutex> Drop for Unlock<'_, R> {
+    fn drop(&mut self) {
+        unsafe { self.0.unlock() };
+    }
+}
+
+/// Locked
+/// an allocator wrapper serializing every call with a `R` mutex
+pub struct Locked<A, R: RawMutex = RawSpinlock> {
+    raw: R,
+    inner: A,
+}
+
+// the inner allocator is only touched with the mutex held,
+// from whichever thread holds it
+unsafe impl<A: Send, R: RawMutex + Sync> Sync for Locked<A, R> {}
+
+impl<A, R: RawMutex> Locked<A, R> {
+    pub const fn new(inner: A) -> Self {
+        Locked {
+            raw: R::INIT,
+            inner,
+        }
+    }
+
+    /// Run `f` on the wrapped allocator with the mutex held,
+    /// e.g. to read statistics or call inherent methods.
+    pub fn with<T>(&self, f: impl FnOnce(&A) -> T) -> T {
+        self.raw.lock();
+        let _unlock = Unlock(&self.raw);
+        f(&self.inner)
+    }
+}
+
+unsafe impl<A: Allocator, R: RawMutex> Allocator for Locked<A, R> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|inner| inner.allocate(layout))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|inner| inner.allocate_zeroed(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with(|inner| inner.deallocate(ptr, layout))
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|inner| inner.grow(ptr, old_layout, new_layout))
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|inner| inner.shrink(ptr, old_layout, new_layout))
+    }
+}
+
+unsafe impl<A: Allocator, R: RawMutex> GlobalAlloc for Locked<A, R> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
+
+/// PreemptibleLocked
+/// a locked buddy heap that merges at most `budget` buddies per critical
+/// section. Give the heap deferred coalescing, see
+/// BuddyAllocParam::with_deferred_coalescing, so frees take O(1) under the
+/// mutex. An allocation finding no block merges `budget` deferred frees,
+/// releases the mutex as a preemption point and retries, until it succeeds or
+/// nothing is left to merge.
+pub struct PreemptibleLocked<R: RawMutex = RawSpinlock> {
+    locked: Locked<BuddyAlloc, R>,
+    budget: usize,
+}
+
+impl<R: RawMutex> PreemptibleLocked<R> {
+    pub const fn new(inner: BuddyAlloc, budget: usize) -> Self {
+        assert!(budget != 0, "{}", BUDGET_ERROR_MSG);
+        PreemptibleLocked {
+            locked: Locked::new(inner),
+            budget,
+        }
+    }
+
+    /// merges done per critical section
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// see Locked::with
+    pub fn with<T>(&self, f: impl FnOnce(&BuddyAlloc) -> T) -> T {
+        self.locked.with(f)
+    }
+
+    /// Merge every deferred free, `budget` at a time with the mutex released
+    /// in between, e.g. from an idle task. Returns the number of merges.
+    pub fn coalesce(&self) -> usize {
+        let mut merged = 0;
+        loop {
+            let n = self.with(|heap| heap.coalesce_some(self.budget));
+            merged += n;
+            if n < self.budget {
+                return merged;
+            }
+        }
+    }
+}
+
+unsafe impl<R: RawMutex> Allocator for PreemptibleLocked<R> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        loop {
+            match self.with(|heap| heap.allocate_budgeted(layout, self.budget)) {
+                Ok(p) => return Ok(p),
+                // more to merge, let others in before the next round
+                Err(merged) if merged == self.budget => continue,
+                Err(_) => return Err(AllocError),
+            }
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.locked.deallocate(ptr, layout)
+    }
+}
+
+unsafe impl<R: RawMutex> GlobalAlloc for PreemptibleLocked<R> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
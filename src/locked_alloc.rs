@@ -0,0 +1,75 @@
+//! ThreadsafeAlloc
+//! NonThreadsafeAlloc behind a spinlock, usable as `#[global_allocator]`
+//! on multi-core targets. See `Locked` for other mutexes.
+
+use {
+    crate::{
+        buddy_alloc::BuddyAllocParam,
+        freelist_alloc::FreelistAllocParam,
+        locked::{Locked, RawSpinlock},
+        non_threadsafe_alloc::NonThreadsafeAlloc,
+    },
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        ptr::NonNull,
+    },
+};
+
+/// ThreadsafeAlloc
+/// the freelist and buddy heaps of NonThreadsafeAlloc, one caller at a time
+pub struct ThreadsafeAlloc {
+    inner: Locked<NonThreadsafeAlloc, RawSpinlock>,
+}
+
+impl ThreadsafeAlloc {
+    /// see NonThreadsafeAlloc::new
+    pub const fn new(
+        freelist_alloc_param: FreelistAllocParam,
+        buddy_alloc_param: BuddyAllocParam,
+    ) -> Self {
+        ThreadsafeAlloc {
+            inner: Locked::new(NonThreadsafeAlloc::new(
+                freelist_alloc_param,
+                buddy_alloc_param,
+            )),
+        }
+    }
+
+    /// see NonThreadsafeAlloc::init
+    pub fn init(&self) {
+        self.inner.with(NonThreadsafeAlloc::init)
+    }
+
+    /// Run `f` on the wrapped allocator with the lock held,
+    /// e.g. to refill or scrub it.
+    pub fn with<R>(&self, f: impl FnOnce(&NonThreadsafeAlloc) -> R) -> R {
+        self.inner.with(f)
+    }
+}
+
+unsafe impl Allocator for ThreadsafeAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.allocate(layout)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.deallocate(ptr, layout)
+    }
+}
+
+unsafe impl GlobalAlloc for ThreadsafeAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
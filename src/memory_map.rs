@@ -0,0 +1,221 @@
+//! Memory map
+//! A multi-region heap built straight from a bootloader memory map.
+//!
+//! The map is a list of `(start, len, usable)` entries as found in e820
+//! tables or device trees. Entries may come in any order and overlap,
+//! unusable entries win over usable ones. Every usable range left gets its
+//! own BuddyAlloc, ranges too small to hold a heap are skipped.
+//! BuddyAlloc::from_ranges builds a single heap over them instead.
+//!
+//! Memory handed over later, e.g. by a bootloader reporting regions one at
+//! a time, goes in through MultiRegionAlloc::add_region as a heap of its own,
+//! or through BuddyAlloc::add_region when it falls inside the heap's span.
+
+use {
+    crate::{
+        buddy_alloc::{holds_heap, BuddyAlloc, BuddyAllocParam},
+        error::Error,
+        inspect::{HeapInspect, HeapStats},
+        region_table::RegionTable,
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::{OnceCell, RefCell},
+        ptr::NonNull,
+    },
+};
+
+/// max entries of a memory map
+pub const MAP_CAPACITY: usize = 128;
+const MAP_SIZE_ERROR_MSG: &str = "memory map too large";
+
+/// Usable `(start, end)` ranges of `map`, sorted and with the unusable entries cut out.
+pub(crate) fn usable_ranges<I: IntoIterator<Item = (usize, usize, bool)>>(
+    map: I,
+) -> ([(usize, usize); MAP_CAPACITY], usize) {
+    let mut ranges = [(0, 0); MAP_CAPACITY];
+    let mut holes = [(0, 0); MAP_CAPACITY];
+    let (mut n, mut nholes) = (0, 0);
+    for (start, len, usable) in map {
+        let (list, count) = if usable {
+            (&mut ranges, &mut n)
+        } else {
+            (&mut holes, &mut nholes)
+        };
+        assert!(*count < MAP_CAPACITY, "{}", MAP_SIZE_ERROR_MSG);
+        list[*count] = (start, start.saturating_add(len));
+        *count += 1;
+    }
+
+    // merge overlapping and adjacent usable ranges
+    ranges[..n].sort_unstable();
+    let mut merged = 0;
+    for i in 0..n {
+        let (start, end) = ranges[i];
+        if merged > 0 && start <= ranges[merged - 1].1 {
+            ranges[merged - 1].1 = ranges[merged - 1].1.max(end);
+        } else {
+            ranges[merged] = (start, end);
+            merged += 1;
+        }
+    }
+    n = merged;
+
+    // cut the holes out, splitting ranges around them
+    for &(hole_start, hole_end) in &holes[..nholes] {
+        let mut i = 0;
+        while i < n {
+            let (start, end) = ranges[i];
+            if hole_end <= start || end <= hole_start {
+                i += 1;
+                continue;
+            }
+            let left = (start, hole_start.max(start));
+            let right = (hole_end.min(end), end);
+            match (left.0 < left.1, right.0 < right.1) {
+                (false, false) => {
+                    ranges.copy_within((i + 1)..n, i);
+                    n -= 1;
+                }
+                (true, false) => {
+                    ranges[i] = left;
+                    i += 1;
+                }
+                (false, true) => {
+                    ranges[i] = right;
+                    i += 1;
+                }
+                (true, true) => {
+                    assert!(n < MAP_CAPACITY, "{}", MAP_SIZE_ERROR_MSG);
+                    ranges.copy_within((i + 1)..n, i + 2);
+                    ranges[i] = left;
+                    ranges[i + 1] = right;
+                    n += 1;
+                    i += 2;
+                }
+            }
+        }
+    }
+    (ranges, n)
+}
+
+/// MultiRegionAlloc
+/// up to N BuddyAlloc heaps, one per usable memory range
+pub struct MultiRegionAlloc<const N: usize> {
+    heaps: [OnceCell<BuddyAlloc>; N],
+    regions: RefCell<RegionTable<N>>,
+}
+
+impl<const N: usize> Default for MultiRegionAlloc<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MultiRegionAlloc<N> {
+    /// no heaps yet, see add_region
+    pub const fn new() -> Self {
+        MultiRegionAlloc {
+            heaps: [const { OnceCell::new() }; N],
+            regions: RefCell::new(RegionTable::new()),
+        }
+    }
+
+    /// Build a heap over every usable range of `map`,
+    /// ranges beyond the first N are left unused.
+    ///
+    /// # Safety
+    ///
+    /// The usable memory must be mapped, exposed and used by nothing else.
+    pub unsafe fn from_memory_map<I: IntoIterator<Item = (usize, usize, bool)>>(
+        map: I,
+        leaf_size: usize,
+    ) -> Self {
+        let alloc = Self::new();
+        let (ranges, n) = usable_ranges(map);
+        let usable = ranges[..n]
+            .iter()
+            .filter(|&&(start, end)| holds_heap(end - start, leaf_size));
+        for &(start, end) in usable.take(N) {
+            let base = core::ptr::with_exposed_provenance::<u8>(start);
+            let _ = alloc.add_region(base, end - start, leaf_size);
+        }
+        alloc
+    }
+
+    /// Donate `len` bytes at `addr` as one more heap, see
+    /// BuddyAllocParam::new for `leaf_size`. Fails with SizeUnsupported if
+    /// the region can't hold a heap and QuotaExceeded once N heaps exist.
+    /// Panics if the region overlaps one added before.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for the lifetime of the allocator and used by nothing else.
+    pub unsafe fn add_region(
+        &self,
+        addr: *const u8,
+        len: usize,
+        leaf_size: usize,
+    ) -> Result<(), Error> {
+        if !holds_heap(len, leaf_size) {
+            return Err(Error::SizeUnsupported);
+        }
+        let mut regions = self.regions.borrow_mut();
+        let i = regions.len();
+        if i == N {
+            return Err(Error::QuotaExceeded);
+        }
+        let heap = BuddyAlloc::new(BuddyAllocParam::new(addr, len, leaf_size));
+        let start = addr as usize;
+        regions.insert(start..(start + len), i);
+        let _ = self.heaps[i].set(heap);
+        Ok(())
+    }
+
+    /// the heaps built, in the order they were added
+    pub fn heaps(&self) -> impl Iterator<Item = &BuddyAlloc> + '_ {
+        self.heaps.iter().map_while(OnceCell::get)
+    }
+
+    /// the heap owning p
+    pub fn owner(&self, p: *const u8) -> Option<&BuddyAlloc> {
+        self.regions
+            .borrow()
+            .find(p as usize)
+            .and_then(|i| self.heaps[i].get())
+    }
+
+    /// Stats of all heaps summed, the largest free block is the largest of
+    /// any heap. A request every heap turned down counts once per heap.
+    pub fn stats(&self) -> HeapStats {
+        self.heaps()
+            .map(|heap| heap.stats())
+            .fold(HeapStats::default(), |sum, s| HeapStats {
+                total_bytes: sum.total_bytes + s.total_bytes,
+                free_bytes: sum.free_bytes + s.free_bytes,
+                largest_free: sum.largest_free.max(s.largest_free),
+                free_blocks: sum.free_blocks + s.free_blocks,
+                used_bytes: sum.used_bytes + s.used_bytes,
+                peak_used: sum.peak_used + s.peak_used,
+                allocs: sum.allocs + s.allocs,
+                failed_allocs: sum.failed_allocs + s.failed_allocs,
+                frees: sum.frees + s.frees,
+            })
+    }
+}
+
+unsafe impl<const N: usize> Allocator for MultiRegionAlloc<N> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.heaps()
+            .find_map(|heap| heap.allocate(layout).ok())
+            .ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let owner = self.owner(ptr.as_ptr());
+        debug_assert!(owner.is_some(), "pointer not owned by any heap");
+        if let Some(heap) = owner {
+            heap.deallocate(ptr, layout);
+        }
+    }
+}
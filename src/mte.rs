@@ -0,0 +1,106 @@
+//! MTE alloc
+//! ARM memory tagging for any allocator.
+//!
+//! Every allocation gets a random tag, stored for each 16 byte granule of
+//! the block and carried in the top byte of the returned pointer. Freed
+//! blocks are retagged, so use-after-free and overflows into a neighbour
+//! trap in hardware. The heap must be mapped with `PROT_MTE` and tag checks
+//! enabled for the thread.
+//!
+//! On targets other than aarch64 the wrapper passes allocations through
+//! unchanged, so code using it builds everywhere.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    ptr::NonNull,
+};
+
+/// MTE tag granule
+pub const GRANULE_SIZE: usize = 16;
+const TAG_SHIFT: usize = 56;
+const TAG_MASK: usize = 0xf << TAG_SHIFT;
+
+/// Tag `p..(p + len)` with a fresh random tag different from `exclude`,
+/// returns `p` carrying the tag.
+#[cfg(target_arch = "aarch64")]
+unsafe fn tag_granules(p: *mut u8, len: usize, exclude: usize) -> *mut u8 {
+    let tagged: usize;
+    core::arch::asm!(
+        ".arch_extension memtag",
+        "irg {tagged}, {p}, {exclude}",
+        tagged = out(reg) tagged,
+        p = in(reg) p as usize,
+        exclude = in(reg) 1usize << exclude,
+        options(nomem, nostack),
+    );
+    let tagged = p.with_addr(tagged);
+    let mut granule = tagged;
+    for _ in 0..(len / GRANULE_SIZE) {
+        core::arch::asm!(
+            ".arch_extension memtag",
+            "stg {g}, [{g}]",
+            g = in(reg) granule,
+            options(nostack),
+        );
+        granule = granule.wrapping_add(GRANULE_SIZE);
+    }
+    tagged
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+unsafe fn tag_granules(p: *mut u8, _len: usize, _exclude: usize) -> *mut u8 {
+    p
+}
+
+fn tag_of(p: *mut u8) -> usize {
+    (p as usize & TAG_MASK) >> TAG_SHIFT
+}
+
+fn untagged(p: *mut u8) -> *mut u8 {
+    p.map_addr(|addr| addr & !TAG_MASK)
+}
+
+pub struct MteAlloc<A: Allocator> {
+    inner: A,
+}
+
+impl<A: Allocator> MteAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        MteAlloc { inner }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// layout of the inner block, whole granules
+    fn granule_layout(layout: Layout) -> Result<Layout, AllocError> {
+        let size = (layout.size() + GRANULE_SIZE - 1) & !(GRANULE_SIZE - 1);
+        Layout::from_size_align(size.max(GRANULE_SIZE), layout.align().max(GRANULE_SIZE))
+            .map_err(|_| AllocError)
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for MteAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let inner_layout = Self::granule_layout(layout)?;
+        let p = self.inner.allocate(inner_layout)?.as_mut_ptr();
+        unsafe {
+            let tagged = tag_granules(p, inner_layout.size(), 0);
+            Ok(NonNull::slice_from_raw_parts(
+                NonNull::new_unchecked(tagged),
+                layout.size(),
+            ))
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let inner_layout = Self::granule_layout(layout).expect("layout");
+        let p = untagged(ptr.as_ptr());
+        // stale pointers keep the old tag and trap from now on
+        tag_granules(p, inner_layout.size(), tag_of(ptr.as_ptr()));
+        self.inner
+            .deallocate(NonNull::new_unchecked(p), inner_layout);
+    }
+}
@@ -3,27 +3,38 @@
 
 use {
     crate::{
-        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam, MIN_LEAF_SIZE_ALIGN},
+        error::Error,
         freelist_alloc::{FreelistAlloc, FreelistAllocParam, BLOCK_SIZE},
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
     },
     core::{
         alloc::{AllocError, Allocator, GlobalAlloc, Layout},
-        cell::RefCell,
+        cell::OnceCell,
         ptr::NonNull,
     },
 };
 
+#[cfg(feature = "stats")]
+use core::cell::Cell;
+
 /// Use buddy allocator if request bytes is large than this,
 /// otherwise use freelist allocator
 const MAX_FREELIST_ALLOC_SIZE: usize = BLOCK_SIZE;
+const FREELIST_PERCENT_ERROR_MSG: &str = "freelist share must be 1 to 99 percent";
 
 /// NonThreadsafeAlloc
-/// perfect for single threaded devices
+/// perfect for single threaded devices,
+/// see ThreadsafeAlloc for multi-core targets
 pub struct NonThreadsafeAlloc {
     freelist_alloc_param: FreelistAllocParam,
-    inner_freelist_alloc: RefCell<Option<FreelistAlloc>>,
+    inner_freelist_alloc: OnceCell<FreelistAlloc>,
     buddy_alloc_param: BuddyAllocParam,
-    inner_buddy_alloc: RefCell<Option<BuddyAlloc>>,
+    inner_buddy_alloc: OnceCell<BuddyAlloc>,
+    /// small allocations the pool couldn't serve
+    #[cfg(feature = "stats")]
+    overflows: Cell<usize>,
 }
 
 impl NonThreadsafeAlloc {
@@ -33,27 +44,188 @@ impl NonThreadsafeAlloc {
         buddy_alloc_param: BuddyAllocParam,
     ) -> Self {
         NonThreadsafeAlloc {
-            inner_freelist_alloc: RefCell::new(None),
-            inner_buddy_alloc: RefCell::new(None),
+            inner_freelist_alloc: OnceCell::new(),
+            inner_buddy_alloc: OnceCell::new(),
             freelist_alloc_param,
             buddy_alloc_param,
+            #[cfg(feature = "stats")]
+            overflows: Cell::new(0),
+        }
+    }
+
+    /// Split `base..(base + len)` in two: about `freelist_percent` of it, in
+    /// whole blocks, for the freelist pool and the rest for the buddy heap,
+    /// with the smallest leaf size. Each part starts at the first address
+    /// aligned for its allocator, the pool at a BLOCK_SIZE and the buddy heap
+    /// at a leaf boundary, losing the bytes before it.
+    pub const fn from_buffer(base: *const u8, len: usize, freelist_percent: usize) -> Self {
+        assert!(
+            freelist_percent > 0 && freelist_percent < 100,
+            "{}",
+            FREELIST_PERCENT_ERROR_MSG
+        );
+        let pool_len = len / BLOCK_SIZE * freelist_percent / 100 * BLOCK_SIZE;
+        // addresses aren't known in const, both heaps round their base up once built
+        Self::new(
+            FreelistAllocParam::new(base, pool_len).with_rounded_base(true),
+            BuddyAllocParam::new(
+                base.wrapping_add(pool_len),
+                len - pool_len,
+                MIN_LEAF_SIZE_ALIGN,
+            ),
+        )
+    }
+
+    /// Build both heaps now instead of on the first allocation,
+    /// e.g. at boot so the first allocation doesn't pay for it.
+    pub fn init(&self) {
+        unsafe {
+            self.fetch_freelist_alloc(|_| ());
+            self.fetch_buddy_alloc(|_| ());
         }
     }
 
-    unsafe fn fetch_freelist_alloc<R, F: FnOnce(&mut FreelistAlloc) -> R>(&self, f: F) -> R {
-        let mut inner = self.inner_freelist_alloc.borrow_mut();
-        if inner.is_none() {
-            inner.replace(FreelistAlloc::new(self.freelist_alloc_param));
+    /// Top up the pool serving `size_class` bytes with `count` blocks taken
+    /// from the buddy heap, e.g. during idle time, so bursts of small
+    /// allocations don't fall back to the buddy heap. Refilled blocks return
+    /// to the buddy heap when freed. Fails for sizes no pool serves, or once
+    /// the buddy heap runs out, keeping the blocks added so far.
+    pub fn refill(&self, size_class: usize, count: usize) -> Result<(), Error> {
+        if size_class > MAX_FREELIST_ALLOC_SIZE {
+            return Err(Error::SizeUnsupported);
+        }
+        let layout = Layout::from_size_align(BLOCK_SIZE, 1).expect("layout");
+        for _ in 0..count {
+            let p = unsafe { self.fetch_buddy_alloc(|alloc| alloc.allocate(layout))? };
+            unsafe { self.fetch_freelist_alloc(|alloc| alloc.push_block(p.as_mut_ptr())) };
+        }
+        Ok(())
+    }
+
+    /// Zero the free memory of both heaps, returns the number of bytes zeroed.
+    /// see BuddyAlloc::scrub_free
+    pub fn scrub_free(&self) -> usize {
+        unsafe {
+            self.fetch_freelist_alloc(|alloc| alloc.scrub_free())
+                + self.fetch_buddy_alloc(|alloc| alloc.scrub_free())
         }
-        f(inner.as_mut().expect("nerver"))
     }
 
-    unsafe fn fetch_buddy_alloc<R, F: FnOnce(&mut BuddyAlloc) -> R>(&self, f: F) -> R {
-        let mut inner = self.inner_buddy_alloc.borrow_mut();
-        if inner.is_none() {
-            inner.replace(BuddyAlloc::new(self.buddy_alloc_param));
+    /// whether p points into either heap
+    pub fn owns(&self, p: *const u8) -> bool {
+        unsafe {
+            self.fetch_freelist_alloc(|alloc| alloc.contains_ptr(p.cast_mut()))
+                || self.fetch_buddy_alloc(|alloc| alloc.owns(p))
         }
-        f(inner.as_mut().expect("nerver"))
+    }
+
+    /// Check both heaps, the pool taking the blocks `refill` moved
+    /// from the buddy heap as its own.
+    pub fn validate(&self) -> Result<(), Corruption> {
+        unsafe {
+            self.fetch_buddy_alloc(|heap| {
+                heap.validate()?;
+                self.fetch_freelist_alloc(|alloc| {
+                    alloc.validate_with(|addr| heap.heap_range().contains(&addr))
+                })
+            })
+        }
+    }
+
+    /// usable size of the live allocation at p, like malloc_usable_size
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this allocator and not freed yet.
+    pub unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        let size = self.fetch_freelist_alloc(|alloc| {
+            alloc
+                .contains_ptr(p.cast_mut())
+                .then(|| alloc.alloc_size(p))
+        });
+        size.unwrap_or_else(|| self.fetch_buddy_alloc(|alloc| alloc.alloc_size(p)))
+    }
+
+    /// Statistics of both heaps added up, the largest free block is the
+    /// larger of the two and the peak the sum of their peaks. Blocks moved by
+    /// `refill` count as allocated by the buddy heap and free in the pool.
+    /// Allocations the pool falls back on aren't counted as failed.
+    pub fn stats(&self) -> HeapStats {
+        let pool = unsafe { self.fetch_freelist_alloc(|alloc| alloc.stats()) };
+        let heap = unsafe { self.fetch_buddy_alloc(|alloc| alloc.stats()) };
+        HeapStats {
+            total_bytes: pool.total_bytes + heap.total_bytes,
+            free_bytes: pool.free_bytes + heap.free_bytes,
+            largest_free: pool.largest_free.max(heap.largest_free),
+            free_blocks: pool.free_blocks + heap.free_blocks,
+            used_bytes: pool.used_bytes + heap.used_bytes,
+            peak_used: pool.peak_used + heap.peak_used,
+            allocs: pool.allocs + heap.allocs,
+            failed_allocs: heap.failed_allocs,
+            frees: pool.frees + heap.frees,
+        }
+    }
+
+    /// statistics of the freelist pool alone
+    pub fn pool_stats(&self) -> HeapStats {
+        unsafe { self.fetch_freelist_alloc(|alloc| alloc.stats()) }
+    }
+
+    /// statistics of the buddy heap alone
+    pub fn buddy_stats(&self) -> HeapStats {
+        unsafe { self.fetch_buddy_alloc(|alloc| alloc.stats()) }
+    }
+
+    /// small allocations that fell back on the buddy heap because the pool
+    /// was empty, zero without the `stats` feature
+    pub fn pool_overflows(&self) -> usize {
+        #[cfg(feature = "stats")]
+        return self.overflows.get();
+        #[cfg(not(feature = "stats"))]
+        0
+    }
+
+    /// the pool failed `layout`, fall back on the buddy heap
+    fn overflow(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        #[cfg(feature = "stats")]
+        self.overflows.set(self.overflows.get() + 1);
+        unsafe {
+            self.fetch_buddy_alloc(|alloc| {
+                if zeroed {
+                    alloc.allocate_zeroed(layout)
+                } else {
+                    alloc.allocate(layout)
+                }
+            })
+        }
+    }
+
+    unsafe fn fetch_freelist_alloc<R, F: FnOnce(&FreelistAlloc) -> R>(&self, f: F) -> R {
+        match self.inner_freelist_alloc.get() {
+            Some(alloc) => f(alloc),
+            None => f(self.init_freelist_alloc()),
+        }
+    }
+
+    unsafe fn fetch_buddy_alloc<R, F: FnOnce(&BuddyAlloc) -> R>(&self, f: F) -> R {
+        match self.inner_buddy_alloc.get() {
+            Some(alloc) => f(alloc),
+            None => f(self.init_buddy_alloc()),
+        }
+    }
+
+    /// first use of the pool, kept out of the allocation path
+    #[cold]
+    unsafe fn init_freelist_alloc(&self) -> &FreelistAlloc {
+        self.inner_freelist_alloc
+            .get_or_init(|| FreelistAlloc::new(self.freelist_alloc_param))
+    }
+
+    /// first use of the buddy heap, kept out of the allocation path
+    #[cold]
+    unsafe fn init_buddy_alloc(&self) -> &BuddyAlloc {
+        self.inner_buddy_alloc
+            .get_or_init(|| BuddyAlloc::new(self.buddy_alloc_param))
     }
 }
 
@@ -68,7 +240,19 @@ unsafe impl Allocator for NonThreadsafeAlloc {
             // try freelist alloc, fallback to BuddyAlloc if failed
             unsafe {
                 self.fetch_freelist_alloc(|alloc| alloc.allocate(layout))
-                    .or_else(|_| self.fetch_buddy_alloc(|alloc| alloc.allocate(layout)))
+                    .or_else(|_| self.overflow(layout, false))
+            }
+        }
+    }
+
+    /// With the `free-fill` feature, buddy blocks known to be zero are not cleared again.
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() > MAX_FREELIST_ALLOC_SIZE {
+            unsafe { self.fetch_buddy_alloc(|alloc| alloc.allocate_zeroed(layout)) }
+        } else {
+            unsafe {
+                self.fetch_freelist_alloc(|alloc| alloc.allocate_zeroed(layout))
+                    .or_else(|_| self.overflow(layout, true))
             }
         }
     }
@@ -89,6 +273,8 @@ unsafe impl Allocator for NonThreadsafeAlloc {
 }
 
 // ==== GlobalAlloc api ====
+// only sound without other threads or interrupts touching the heap,
+// lets it be a #[global_allocator] on single core targets
 unsafe impl Sync for NonThreadsafeAlloc {}
 
 unsafe impl GlobalAlloc for NonThreadsafeAlloc {
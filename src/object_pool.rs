@@ -0,0 +1,104 @@
+//! Object pool
+//! Typed objects in FreelistAlloc blocks, freed when their handle drops.
+//!
+//! Every `T` takes one block, so `T` must fit into BLOCK_SIZE bytes with an
+//! alignment of at most BLOCK_SIZE. Use a FreelistAlloc of its own for a
+//! dedicated region, or share one.
+
+use {
+    crate::freelist_alloc::{FreelistAlloc, BLOCK_SIZE},
+    core::{
+        alloc::{Allocator, Layout},
+        fmt,
+        marker::PhantomData,
+        mem::{align_of, size_of},
+        ops::{Deref, DerefMut},
+        ptr::NonNull,
+    },
+};
+
+const GUARD_ERROR_MSG: &str = "object doesn't fit a block next to the guard word";
+
+pub struct ObjectPool<'a, T> {
+    freelist: &'a FreelistAlloc,
+    _marker: PhantomData<T>,
+}
+
+/// PoolBox
+/// an object of an ObjectPool, dropped and freed with the handle
+pub struct PoolBox<'a, T> {
+    ptr: NonNull<T>,
+    freelist: &'a FreelistAlloc,
+}
+
+impl<'a, T> ObjectPool<'a, T> {
+    /// Objects larger or more aligned than a block fail to compile,
+    /// objects that don't fit next to a guard word panic.
+    pub fn new(freelist: &'a FreelistAlloc) -> Self {
+        const {
+            assert!(
+                size_of::<T>() <= BLOCK_SIZE && align_of::<T>() <= BLOCK_SIZE,
+                "object larger or more aligned than a block"
+            )
+        };
+        assert!(
+            size_of::<T>() <= freelist.max_alloc_size(),
+            "{}",
+            GUARD_ERROR_MSG
+        );
+        ObjectPool {
+            freelist,
+            _marker: PhantomData,
+        }
+    }
+
+    /// the freelist the objects live in
+    pub fn freelist(&self) -> &'a FreelistAlloc {
+        self.freelist
+    }
+
+    /// Move `value` into a free block, hands it back if there is none.
+    pub fn alloc(&self, value: T) -> Result<PoolBox<'a, T>, T> {
+        match self.freelist.allocate(Layout::new::<T>()) {
+            Ok(p) => {
+                let ptr = p.as_non_null_ptr().cast::<T>();
+                unsafe { ptr.write(value) };
+                Ok(PoolBox {
+                    ptr,
+                    freelist: self.freelist,
+                })
+            }
+            Err(_) => Err(value),
+        }
+    }
+}
+
+impl<T> Deref for PoolBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for PoolBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PoolBox<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T> Drop for PoolBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ptr.drop_in_place();
+            self.freelist
+                .deallocate(self.ptr.cast(), Layout::new::<T>());
+        }
+    }
+}
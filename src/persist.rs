@@ -0,0 +1,146 @@
+//! Persist
+//! A versioned image of the buddy tree, to keep a heap across resets or
+//! firmware upgrades, e.g. in retained RAM with the image in flash.
+//!
+//! The image holds the bitmaps only, the heap memory must be kept by other
+//! means. Loading rebuilds the free lists and rejects images of another
+//! version or heap layout, or failing their checksum. Fields are little endian:
+//!
+//! | bytes | field |
+//! |-------|-------|
+//! | 4 | `IMAGE_MAGIC` |
+//! | 2 | `IMAGE_VERSION` |
+//! | 2 | number of orders n |
+//! | 4 | leaf size |
+//! | 8 | heap length, up to the end of the last block |
+//! | 8 | offset of the first block |
+//! | 4 | bitmap bytes m |
+//! | 4 | CRC-32 of the other bytes |
+//! | m | alloc bitmaps of orders 0..n, then split bitmaps of orders 1..n |
+
+use crate::buddy_alloc::{BuddyAlloc, BuddyAllocParam};
+
+pub const IMAGE_MAGIC: [u8; 4] = *b"BDYT";
+pub const IMAGE_VERSION: u16 = 1;
+pub const IMAGE_HEADER_SIZE: usize = 36;
+const CHECKSUM_OFFSET: usize = IMAGE_HEADER_SIZE - 4;
+
+/// Why an image was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// the buffer is too small for the image
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u16),
+    BadChecksum,
+    /// made for a heap of another length, leaf size or layout
+    Incompatible,
+}
+
+/// CRC-32 (IEEE), bitwise to stay small
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in bytes {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn bitmaps_len(heap: &BuddyAlloc) -> usize {
+    (0..heap.entries_size())
+        .map(|k| heap.bitmap_len(k) * if k == 0 { 1 } else { 2 })
+        .sum()
+}
+
+/// orders of the bitmaps in image order, and whether it's the split one
+fn bitmap_order(orders: usize) -> impl Iterator<Item = (usize, bool)> {
+    (0..orders)
+        .map(|k| (k, false))
+        .chain((1..orders).map(|k| (k, true)))
+}
+
+fn header(heap: &BuddyAlloc) -> [u8; IMAGE_HEADER_SIZE] {
+    let mut header = [0; IMAGE_HEADER_SIZE];
+    header[0..4].copy_from_slice(&IMAGE_MAGIC);
+    header[4..6].copy_from_slice(&IMAGE_VERSION.to_le_bytes());
+    header[6..8].copy_from_slice(&(heap.entries_size() as u16).to_le_bytes());
+    header[8..12].copy_from_slice(&(heap.leaf_size() as u32).to_le_bytes());
+    header[12..20].copy_from_slice(&(heap.region_len() as u64).to_le_bytes());
+    header[20..28].copy_from_slice(&(heap.base_offset() as u64).to_le_bytes());
+    header[28..32].copy_from_slice(&(bitmaps_len(heap) as u32).to_le_bytes());
+    header
+}
+
+/// bytes of the image of `heap`
+pub fn image_size(heap: &BuddyAlloc) -> usize {
+    IMAGE_HEADER_SIZE + bitmaps_len(heap)
+}
+
+/// Write the image of `heap` to `buf`, returns the number of bytes written.
+pub fn save(heap: &BuddyAlloc, buf: &mut [u8]) -> Result<usize, ImageError> {
+    let size = image_size(heap);
+    let image = buf.get_mut(..size).ok_or(ImageError::Truncated)?;
+    let (head, mut rest) = image.split_at_mut(IMAGE_HEADER_SIZE);
+    head.copy_from_slice(&header(heap));
+    let mut crc = crc32(0, &head[..CHECKSUM_OFFSET]);
+    for (k, split) in bitmap_order(heap.entries_size()) {
+        let (alloc_bitmap, split_bitmap) = heap.bitmaps(k);
+        let bitmap = if split { split_bitmap } else { alloc_bitmap };
+        let (dst, tail) = core::mem::take(&mut rest).split_at_mut(bitmap.len());
+        dst.copy_from_slice(bitmap);
+        crc = crc32(crc, bitmap);
+        rest = tail;
+    }
+    head[CHECKSUM_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    Ok(size)
+}
+
+/// Build the heap `param` describes in the state an image recorded,
+/// over memory that kept its contents since.
+///
+/// # Safety
+///
+/// As BuddyAlloc::new, and the heap memory must hold the blocks the image
+/// records as allocated, as they were when it was saved.
+pub unsafe fn load(param: BuddyAllocParam, image: &[u8]) -> Result<BuddyAlloc, ImageError> {
+    let head = image
+        .get(..IMAGE_HEADER_SIZE)
+        .ok_or(ImageError::Truncated)?;
+    if head[0..4] != IMAGE_MAGIC {
+        return Err(ImageError::BadMagic);
+    }
+    let version = u16::from_le_bytes([head[4], head[5]]);
+    if version != IMAGE_VERSION {
+        return Err(ImageError::UnsupportedVersion(version));
+    }
+    let bitmaps_size = u32::from_le_bytes(head[28..32].try_into().unwrap()) as usize;
+    let body = image
+        .get(IMAGE_HEADER_SIZE..IMAGE_HEADER_SIZE + bitmaps_size)
+        .ok_or(ImageError::Truncated)?;
+    let crc = crc32(crc32(0, &head[..CHECKSUM_OFFSET]), body);
+    if head[CHECKSUM_OFFSET..] != crc.to_le_bytes() {
+        return Err(ImageError::BadChecksum);
+    }
+
+    let mut heap = BuddyAlloc::new_unlinked(param);
+    if head[..CHECKSUM_OFFSET] != header(&heap)[..CHECKSUM_OFFSET] {
+        return Err(ImageError::Incompatible);
+    }
+    let mut rest = body;
+    for (k, split) in bitmap_order(heap.entries_size()) {
+        let (alloc_bitmap, split_bitmap) = heap.bitmaps_mut(k);
+        let bitmap = if split { split_bitmap } else { alloc_bitmap };
+        let (src, tail) = rest.split_at(bitmap.len());
+        bitmap.copy_from_slice(src);
+        rest = tail;
+    }
+    heap.rebuild_free_lists();
+    Ok(heap)
+}
@@ -0,0 +1,87 @@
+//! Pin table
+//! Bookkeeping of blocks that must not be relocated.
+//!
+//! Code holding raw pointers into a block pins it; compactors and
+//! defragmenters consult the table before moving anything. Pins nest,
+//! a block stays pinned until every `pin` got its `unpin`.
+
+use {crate::error::Error, core::cell::Cell};
+
+#[derive(Clone, Copy)]
+struct Pin {
+    addr: usize,
+    count: usize,
+}
+
+/// PinTable
+/// tracks up to N pinned blocks
+pub struct PinTable<const N: usize> {
+    pins: [Cell<Pin>; N],
+}
+
+impl<const N: usize> Default for PinTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PinTable<N> {
+    pub const fn new() -> Self {
+        PinTable {
+            pins: [const { Cell::new(Pin { addr: 0, count: 0 }) }; N],
+        }
+    }
+
+    fn find(&self, addr: usize) -> Option<&Cell<Pin>> {
+        self.pins
+            .iter()
+            .find(|pin| pin.get().count > 0 && pin.get().addr == addr)
+    }
+
+    /// Pin the block at `ptr`, fails if the table is full.
+    pub fn pin(&self, ptr: *const u8) -> Result<(), Error> {
+        let addr = ptr as usize;
+        let slot = self
+            .find(addr)
+            .or_else(|| self.pins.iter().find(|pin| pin.get().count == 0))
+            .ok_or(Error::QuotaExceeded)?;
+        let count = slot.get().count + 1;
+        slot.set(Pin { addr, count });
+        Ok(())
+    }
+
+    /// Drop one pin of the block at `ptr`.
+    pub fn unpin(&self, ptr: *const u8) {
+        let slot = self.find(ptr as usize);
+        debug_assert!(slot.is_some(), "unpin of a block that is not pinned");
+        if let Some(slot) = slot {
+            let mut pin = slot.get();
+            pin.count -= 1;
+            slot.set(pin);
+        }
+    }
+
+    pub fn is_pinned(&self, ptr: *const u8) -> bool {
+        self.find(ptr as usize).is_some()
+    }
+
+    /// number of pins held on the block at `ptr`
+    pub fn pin_count(&self, ptr: *const u8) -> usize {
+        self.find(ptr as usize).map_or(0, |pin| pin.get().count)
+    }
+
+    /// addresses of all pinned blocks
+    pub fn pinned(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pins
+            .iter()
+            .map(Cell::get)
+            .filter(|pin| pin.count > 0)
+            .map(|pin| pin.addr)
+    }
+
+    /// Panics in debug builds if the block at `ptr` is pinned,
+    /// call it before relocating or advising to relocate a block.
+    pub fn debug_assert_movable(&self, ptr: *const u8) {
+        debug_assert!(!self.is_pinned(ptr), "pinned block chosen for relocation");
+    }
+}
@@ -0,0 +1,90 @@
+//! Rc alloc
+//! Reference counted allocator handles.
+//!
+//! Collections like `Vec<T, A>` own their allocator. Wrapping one heap in
+//! `ArcAlloc` or `RcAlloc` lets many collections share it, the heap lives
+//! until the last handle is dropped.
+
+use {
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        ops::Deref,
+        ptr::NonNull,
+    },
+    std::{rc::Rc, sync::Arc},
+};
+
+macro_rules! shared_handle {
+    ($name:ident, $rc:ident) => {
+        pub struct $name<A: Allocator>($rc<A>);
+
+        impl<A: Allocator> $name<A> {
+            pub fn new(inner: A) -> Self {
+                $name($rc::new(inner))
+            }
+
+            /// the shared allocator
+            pub fn inner(&self) -> &A {
+                &self.0
+            }
+        }
+
+        impl<A: Allocator> Clone for $name<A> {
+            fn clone(&self) -> Self {
+                $name($rc::clone(&self.0))
+            }
+        }
+
+        impl<A: Allocator> Deref for $name<A> {
+            type Target = A;
+
+            fn deref(&self) -> &A {
+                &self.0
+            }
+        }
+
+        unsafe impl<A: Allocator> Allocator for $name<A> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.allocate(layout)
+            }
+
+            fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.allocate_zeroed(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.deallocate(ptr, layout)
+            }
+
+            unsafe fn grow(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.grow(ptr, old_layout, new_layout)
+            }
+
+            unsafe fn grow_zeroed(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.grow_zeroed(ptr, old_layout, new_layout)
+            }
+
+            unsafe fn shrink(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.shrink(ptr, old_layout, new_layout)
+            }
+        }
+    };
+}
+
+shared_handle!(ArcAlloc, Arc);
+shared_handle!(RcAlloc, Rc);
@@ -0,0 +1,116 @@
+//! Redzone alloc
+//! An allocator wrapper surrounding every allocation with canary bytes.
+//!
+//! Each allocation gets at least REDZONE bytes of CANARY right before and
+//! after it, checked on deallocate. An overrun is reported at its free,
+//! instead of silently destroying the heap metadata next to the block.
+
+use {
+    crate::inspect::Corruption,
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+/// Canary bytes on each side of an allocation
+pub const REDZONE: usize = 16;
+/// Pattern of the canary bytes
+pub const CANARY: u8 = 0xfd;
+
+fn panic_on_corruption(corruption: Corruption) {
+    panic!("redzone overwritten at {:#x}", corruption.addr);
+}
+
+/// RedzoneAlloc
+/// an allocator wrapper checking canaries around every allocation
+pub struct RedzoneAlloc<A> {
+    inner: A,
+    on_corruption: fn(Corruption),
+}
+
+impl<A> RedzoneAlloc<A> {
+    /// Overwritten canaries panic, see with_handler
+    pub const fn new(inner: A) -> Self {
+        RedzoneAlloc {
+            inner,
+            on_corruption: panic_on_corruption,
+        }
+    }
+
+    /// Call `handler` with the first overwritten canary instead of panicking,
+    /// the block is freed once it returns
+    pub const fn with_handler(mut self, handler: fn(Corruption)) -> Self {
+        self.on_corruption = handler;
+        self
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Check the canaries of the live allocation at ptr,
+    /// `Corruption` has the address of the first overwritten byte.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this allocator made with `layout`.
+    pub unsafe fn check(&self, ptr: NonNull<u8>, layout: Layout) -> Result<(), Corruption> {
+        let before = core::slice::from_raw_parts(ptr.as_ptr().sub(REDZONE), REDZONE);
+        let after = core::slice::from_raw_parts(ptr.as_ptr().add(layout.size()), REDZONE);
+        let bad = |zone: &[u8]| zone.iter().position(|&b| b != CANARY);
+        if let Some(i) = bad(before) {
+            return Err(Corruption {
+                addr: before.as_ptr() as usize + i,
+            });
+        }
+        if let Some(i) = bad(after) {
+            return Err(Corruption {
+                addr: after.as_ptr() as usize + i,
+            });
+        }
+        Ok(())
+    }
+
+    /// layout of the inner block and the offset of the allocation in it
+    fn outer(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        let front = REDZONE.next_multiple_of(layout.align());
+        let size = front
+            .checked_add(layout.size())
+            .and_then(|size| size.checked_add(REDZONE))
+            .ok_or(AllocError)?;
+        let outer = Layout::from_size_align(size, layout.align()).map_err(|_| AllocError)?;
+        Ok((outer, front))
+    }
+
+    /// write the canaries around the allocation in `block`
+    fn arm(block: NonNull<[u8]>, layout: Layout, front: usize) -> NonNull<[u8]> {
+        let p = block.as_mut_ptr();
+        unsafe {
+            p.add(front - REDZONE).write_bytes(CANARY, REDZONE);
+            p.add(front + layout.size()).write_bytes(CANARY, REDZONE);
+            NonNull::slice_from_raw_parts(NonNull::new_unchecked(p.add(front)), layout.size())
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for RedzoneAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (outer, front) = Self::outer(layout)?;
+        Ok(Self::arm(self.inner.allocate(outer)?, layout, front))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (outer, front) = Self::outer(layout)?;
+        Ok(Self::arm(self.inner.allocate_zeroed(outer)?, layout, front))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if let Err(corruption) = self.check(ptr, layout) {
+            (self.on_corruption)(corruption);
+        }
+        let (outer, front) = Self::outer(layout).expect("layout allocated before");
+        self.inner.deallocate(ptr.sub(front), outer)
+    }
+}
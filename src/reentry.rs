@@ -0,0 +1,123 @@
+//! Reentry
+//! Fail allocator calls made from within another call on the same allocator.
+//!
+//! Hooks running inside an allocation, like the `LifetimeTracker` clock,
+//! must not allocate from the heap they're attached to: a nested call would
+//! double borrow a NonThreadsafeAlloc or deadlock a `Locked` heap. Put the
+//! guard outermost, e.g. `ReentryGuard<LifetimeTracker<NonThreadsafeAlloc>>`,
+//! and such calls fail with `AllocFailure::Reentered` instead. Nested frees
+//! can't fail, they leak the block and are counted.
+//!
+//! Like NonThreadsafeAlloc the flag is not thread-safe, it's meant for
+//! single threaded targets and is only Sync around NonThreadsafeAlloc or
+//! FreelistGlobalAlloc.
+
+use {
+    crate::{freelist_global::FreelistGlobalAlloc, non_threadsafe_alloc::NonThreadsafeAlloc},
+    core::{
+        alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+        cell::Cell,
+        ptr::NonNull,
+    },
+};
+
+/// Why an allocation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocFailure {
+    /// the inner allocator failed
+    OutOfMemory,
+    /// called from within another call on this allocator
+    Reentered,
+}
+
+/// ReentryGuard
+/// an allocator wrapper rejecting nested calls
+pub struct ReentryGuard<A> {
+    inner: A,
+    active: Cell<bool>,
+    rejected: Cell<usize>,
+    leaked: Cell<usize>,
+}
+
+/// clears the active flag when the outer call returns
+struct Exit<'a>(&'a Cell<bool>);
+
+impl Drop for Exit<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl<A> ReentryGuard<A> {
+    pub const fn new(inner: A) -> Self {
+        ReentryGuard {
+            inner,
+            active: Cell::new(false),
+            rejected: Cell::new(0),
+            leaked: Cell::new(0),
+        }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// number of nested calls rejected, frees included
+    pub fn rejected(&self) -> usize {
+        self.rejected.get()
+    }
+
+    /// number of blocks leaked by nested frees
+    pub fn leaked(&self) -> usize {
+        self.leaked.get()
+    }
+
+    /// Run `f` unless a call is already running
+    fn enter<T>(&self, f: impl FnOnce(&A) -> T) -> Result<T, AllocFailure> {
+        if self.active.replace(true) {
+            self.rejected.set(self.rejected.get() + 1);
+            return Err(AllocFailure::Reentered);
+        }
+        let _exit = Exit(&self.active);
+        Ok(f(&self.inner))
+    }
+}
+
+impl<A: Allocator> ReentryGuard<A> {
+    /// Like allocate, telling reentered calls apart from running out of memory
+    pub fn try_allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocFailure> {
+        self.enter(|inner| inner.allocate(layout))?
+            .map_err(|_| AllocFailure::OutOfMemory)
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for ReentryGuard<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.try_allocate(layout).map_err(|_| AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.enter(|inner| inner.deallocate(ptr, layout)).is_err() {
+            self.leaked.set(self.leaked.get() + 1);
+        }
+    }
+}
+
+// the flags are plain cells, Sync only over the single threaded heaps,
+// lets them be a #[global_allocator] on single core targets
+unsafe impl Sync for ReentryGuard<NonThreadsafeAlloc> {}
+unsafe impl Sync for ReentryGuard<FreelistGlobalAlloc> {}
+
+unsafe impl<A: Allocator> GlobalAlloc for ReentryGuard<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ptr.is_null() {
+            self.deallocate(NonNull::new_unchecked(ptr), layout)
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Region table
+//! Address ranges sorted by start, for O(log n) pointer to owner lookup.
+
+use core::ops::Range;
+
+#[derive(Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    owner: usize,
+}
+
+/// RegionTable
+/// holds up to N non-overlapping ranges, each tagged with its owner index
+pub struct RegionTable<const N: usize> {
+    regions: [Region; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for RegionTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RegionTable<N> {
+    pub const fn new() -> Self {
+        RegionTable {
+            regions: [Region {
+                start: 0,
+                end: 0,
+                owner: 0,
+            }; N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add `range` owned by `owner`, keeping the table sorted.
+    /// Panics if the table is full or the range overlaps another one.
+    pub fn insert(&mut self, range: Range<usize>, owner: usize) {
+        assert!(self.len < N, "region table full");
+        let i = self.regions[..self.len].partition_point(|r| r.start < range.start);
+        assert!(
+            (i == 0 || self.regions[i - 1].end <= range.start)
+                && (i == self.len || range.end <= self.regions[i].start),
+            "overlapping regions"
+        );
+        self.regions.copy_within(i..self.len, i + 1);
+        self.regions[i] = Region {
+            start: range.start,
+            end: range.end,
+            owner,
+        };
+        self.len += 1;
+    }
+
+    /// owner of the range holding addr
+    pub fn find(&self, addr: usize) -> Option<usize> {
+        let i = self.regions[..self.len].partition_point(|r| r.start <= addr);
+        let region = self.regions[..i].last()?;
+        (addr < region.end).then_some(region.owner)
+    }
+}
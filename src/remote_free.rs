@@ -0,0 +1,126 @@
+//! Remote free
+//! An allocator wrapper taking frees from any context, allocations from one.
+//!
+//! The owner allocates and frees through the wrapper as usual. Other cores
+//! and interrupt handlers free through a `RemoteFreer`, which pushes the block
+//! onto an atomic list without touching the heap. The owner hands the list
+//! back to the heap, merging buddies, on its next allocation or on `drain`.
+//! Blocks are at least a list node, rounded up from the requested layout.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    mem::{align_of, size_of},
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// a block freed remotely, waiting for the owner
+struct Node {
+    next: *mut Node,
+    layout: Layout,
+}
+
+pub struct RemoteFreeAlloc<A: Allocator> {
+    inner: A,
+    pending: AtomicPtr<Node>,
+}
+
+/// RemoteFreer
+/// frees blocks of a RemoteFreeAlloc from any context, it can't allocate
+#[derive(Clone, Copy)]
+pub struct RemoteFreer<'a> {
+    pending: &'a AtomicPtr<Node>,
+}
+
+/// the layout blocks are allocated with, room for a node included
+fn block_layout(layout: Layout) -> Result<Layout, AllocError> {
+    let layout = layout
+        .align_to(align_of::<Node>())
+        .map_err(|_| AllocError)?;
+    Layout::from_size_align(layout.size().max(size_of::<Node>()), layout.align())
+        .map_err(|_| AllocError)
+}
+
+impl<A: Allocator> RemoteFreeAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        RemoteFreeAlloc {
+            inner,
+            pending: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    /// the wrapped allocator, remote frees not drained yet are still in use
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// a handle for freeing from other contexts
+    pub fn freer(&self) -> RemoteFreer<'_> {
+        RemoteFreer {
+            pending: &self.pending,
+        }
+    }
+
+    /// Give the remotely freed blocks back to the wrapped allocator,
+    /// returns the number of blocks.
+    pub fn drain(&self) -> usize {
+        // taking the whole list at once leaves no room for ABA
+        let mut node = self.pending.swap(null_mut(), Ordering::Acquire);
+        let mut count = 0;
+        while let Some(p) = NonNull::new(node) {
+            unsafe {
+                let Node { next, layout } = p.read();
+                self.inner.deallocate(p.cast(), layout);
+                node = next;
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+impl RemoteFreer<'_> {
+    /// # Safety
+    ///
+    /// `ptr` must be allocated by the RemoteFreeAlloc of this freer with `layout`,
+    /// and not be used after.
+    pub unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let layout = block_layout(layout).expect("layout");
+        let node = ptr.cast::<Node>().as_ptr();
+        let mut head = self.pending.load(Ordering::Relaxed);
+        loop {
+            node.write(Node { next: head, layout });
+            match self.pending.compare_exchange_weak(
+                head,
+                node,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for RemoteFreeAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.drain();
+        let p = self.inner.allocate(block_layout(layout)?)?;
+        Ok(NonNull::slice_from_raw_parts(
+            p.as_non_null_ptr(),
+            layout.size(),
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner
+            .deallocate(ptr, block_layout(layout).expect("layout"));
+    }
+}
+
+impl<A: Allocator> Drop for RemoteFreeAlloc<A> {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
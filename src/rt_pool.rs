@@ -0,0 +1,146 @@
+//! RtPool
+//! Preallocated per-size pools for real-time paths.
+//!
+//! Blocks are taken from a parent allocator ahead of time by `prefill`.
+//! Afterwards `allocate` and `deallocate` only scan a fixed number of slots
+//! and swap pointers atomically: they never split blocks, never lock and
+//! never call the parent, so every call finishes in a bounded number of steps.
+
+use {
+    crate::error::Error,
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        ptr::NonNull,
+        sync::atomic::{AtomicPtr, Ordering},
+    },
+};
+
+struct Class<const DEPTH: usize> {
+    layout: Layout,
+    slots: [AtomicPtr<u8>; DEPTH],
+}
+
+/// RtPool
+/// `CLASSES` size classes, each holding up to `DEPTH` blocks
+pub struct RtPool<'a, A: Allocator, const CLASSES: usize, const DEPTH: usize> {
+    parent: &'a A,
+    classes: [Class<DEPTH>; CLASSES],
+}
+
+impl<'a, A: Allocator, const CLASSES: usize, const DEPTH: usize> RtPool<'a, A, CLASSES, DEPTH> {
+    /// `classes` must be sorted by size, a request is served by the first
+    /// class whose size and alignment are large enough.
+    pub fn new(parent: &'a A, classes: [Layout; CLASSES]) -> Self {
+        debug_assert!(
+            classes.windows(2).all(|w| w[0].size() <= w[1].size()),
+            "classes must be sorted by size"
+        );
+        RtPool {
+            parent,
+            classes: classes.map(|layout| Class {
+                layout,
+                slots: core::array::from_fn(|_| AtomicPtr::new(core::ptr::null_mut())),
+            }),
+        }
+    }
+
+    /// Top up the class at `index` to `depth` blocks from the parent.
+    /// Not real-time safe, call it outside the hot path.
+    pub fn prefill(&self, index: usize, depth: usize) -> Result<(), Error> {
+        assert!(depth <= DEPTH, "depth exceeds pool capacity");
+        let class = &self.classes[index];
+        for slot in &class.slots[..depth] {
+            if !slot.load(Ordering::Acquire).is_null() {
+                continue;
+            }
+            let p = self.parent.allocate(class.layout)?.as_mut_ptr();
+            if slot
+                .compare_exchange(
+                    core::ptr::null_mut(),
+                    p,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                // a block was freed into the slot meanwhile
+                unsafe {
+                    self.parent
+                        .deallocate(NonNull::new_unchecked(p), class.layout)
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// number of blocks ready in the class at `index`
+    pub fn available(&self, index: usize) -> usize {
+        self.classes[index]
+            .slots
+            .iter()
+            .filter(|slot| !slot.load(Ordering::Relaxed).is_null())
+            .count()
+    }
+
+    fn class_for(&self, layout: Layout) -> Option<&Class<DEPTH>> {
+        self.classes.iter().find(|class| {
+            class.layout.size() >= layout.size() && class.layout.align() >= layout.align()
+        })
+    }
+}
+
+unsafe impl<A: Allocator, const CLASSES: usize, const DEPTH: usize> Allocator
+    for RtPool<'_, A, CLASSES, DEPTH>
+{
+    /// Take a prefilled block, fails rather than fall back to the parent.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let class = self.class_for(layout).ok_or(AllocError)?;
+        for slot in &class.slots {
+            let p = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+            if let Some(p) = NonNull::new(p) {
+                return Ok(NonNull::slice_from_raw_parts(p, class.layout.size()));
+            }
+        }
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let class = self
+            .class_for(layout)
+            .expect("layout not served by this pool");
+        for slot in &class.slots {
+            if slot
+                .compare_exchange(
+                    core::ptr::null_mut(),
+                    ptr.as_ptr(),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return;
+            }
+        }
+        unreachable!("more blocks returned than the pool holds");
+    }
+}
+
+// slots are only touched through atomics
+unsafe impl<A: Allocator + Sync, const CLASSES: usize, const DEPTH: usize> Sync
+    for RtPool<'_, A, CLASSES, DEPTH>
+{
+}
+
+impl<A: Allocator, const CLASSES: usize, const DEPTH: usize> Drop
+    for RtPool<'_, A, CLASSES, DEPTH>
+{
+    fn drop(&mut self) {
+        for class in &self.classes {
+            for slot in &class.slots {
+                if let Some(p) = NonNull::new(slot.swap(core::ptr::null_mut(), Ordering::Acquire)) {
+                    unsafe { self.parent.deallocate(p, class.layout) };
+                }
+            }
+        }
+    }
+}
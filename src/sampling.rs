@@ -0,0 +1,54 @@
+//! Sampling
+//! Deterministic 1-in-N selection bounding instrumentation overhead.
+//!
+//! Detailed telemetry like LifetimeTracker and EventRing can be sampled,
+//! so it stays affordable enough to leave enabled in production builds.
+
+use core::cell::Cell;
+
+/// Sampler
+/// selects every `period`-th event, a period of 1 selects all of them
+pub struct Sampler {
+    period: Cell<u32>,
+    countdown: Cell<u32>,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Sampler {
+    /// Panics if period is 0.
+    pub const fn new(period: u32) -> Self {
+        assert!(period > 0, "sample period must be at least 1");
+        Sampler {
+            period: Cell::new(period),
+            countdown: Cell::new(1),
+        }
+    }
+
+    pub fn period(&self) -> u32 {
+        self.period.get()
+    }
+
+    /// Change the period, the next event is selected.
+    pub fn set_period(&self, period: u32) {
+        assert!(period > 0, "sample period must be at least 1");
+        self.period.set(period);
+        self.countdown.set(1);
+    }
+
+    /// whether the current event is selected
+    pub fn sample(&self) -> bool {
+        let countdown = self.countdown.get();
+        if countdown <= 1 {
+            self.countdown.set(self.period.get());
+            true
+        } else {
+            self.countdown.set(countdown - 1);
+            false
+        }
+    }
+}
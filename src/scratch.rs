@@ -0,0 +1,62 @@
+//! Scratch
+//! A per-thread bump arena for temporary allocations (std only).
+//!
+//! `scratch` lends the calling thread's arena to a closure and frees
+//! everything the closure allocated once it returns. Calls can't be nested,
+//! an inner call would rewind blocks the outer closure still holds.
+
+use {
+    crate::bump_alloc::{BumpAlloc, BumpAllocParam},
+    core::{cell::Cell, mem::MaybeUninit},
+};
+
+const NESTED_ERROR_MSG: &str = "scratch called from within scratch";
+
+/// Size of each thread's scratch arena
+pub const SCRATCH_SIZE: usize = 64 * 1024;
+
+struct ScratchArena {
+    alloc: BumpAlloc,
+    /// set while a closure holds the arena
+    busy: Cell<bool>,
+    _buf: Box<[MaybeUninit<u8>]>,
+}
+
+impl ScratchArena {
+    fn new() -> Self {
+        let buf = Box::new_uninit_slice(SCRATCH_SIZE);
+        let param = BumpAllocParam::new(buf.as_ptr().cast(), buf.len());
+        ScratchArena {
+            alloc: unsafe { BumpAlloc::new(param) },
+            busy: Cell::new(false),
+            _buf: buf,
+        }
+    }
+}
+
+std::thread_local! {
+    static SCRATCH: ScratchArena = ScratchArena::new();
+}
+
+/// Run `f` with the thread's scratch arena,
+/// everything `f` allocates from it is freed when `f` returns.
+///
+/// Panics when called from within another `scratch` closure.
+pub fn scratch<R, F: FnOnce(&BumpAlloc) -> R>(f: F) -> R {
+    SCRATCH.with(|arena| {
+        assert!(!arena.busy.replace(true), "{}", NESTED_ERROR_MSG);
+        let _busy = BusyGuard(&arena.busy);
+        // only `f` can reach the thread's arena and no other scope runs on it,
+        // nothing it allocates outlives it
+        unsafe { arena.alloc.scope(f) }
+    })
+}
+
+/// clears the busy flag when the closure returns or unwinds
+struct BusyGuard<'a>(&'a Cell<bool>);
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
@@ -0,0 +1,83 @@
+//! Seal
+//! Enforce "no heap allocation after initialization".
+//!
+//! Once `seal` is called every new allocation fails, growing included,
+//! while frees keep working. Rejected attempts are counted for audits.
+
+use core::{
+    alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// SealAlloc
+/// an allocator wrapper refusing allocations once sealed
+pub struct SealAlloc<A> {
+    inner: A,
+    sealed: AtomicBool,
+    rejected: AtomicUsize,
+}
+
+impl<A> SealAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        SealAlloc {
+            inner,
+            sealed: AtomicBool::new(false),
+            rejected: AtomicUsize::new(0),
+        }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Refuse every allocation from now on, there's no unsealing.
+    pub fn seal(&self) {
+        self.sealed.store(true, Ordering::Release);
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.load(Ordering::Acquire)
+    }
+
+    /// allocations refused since sealing
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// count the attempt and return true if sealed
+    fn reject(&self) -> bool {
+        let sealed = self.is_sealed();
+        if sealed {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        sealed
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for SealAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.reject() {
+            return Err(AllocError);
+        }
+        self.inner.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.deallocate(ptr, layout)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for SealAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.reject() {
+            return core::ptr::null_mut();
+        }
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+}
@@ -0,0 +1,470 @@
+//! Shared alloc
+//! A buddy allocator whose metadata lives entirely inside the managed region
+//! and refers to other parts of the region by offset instead of by address.
+//!
+//! The same region can be mapped at different addresses, by several processes
+//! or from a persistent file, and every mapping can operate on the heap
+//! through its own `SharedAlloc` handle. A lock word stored in the region
+//! serializes heap operations across all handles.
+
+use {
+    crate::{
+        buddy_alloc::{
+            bit_clear, bit_isset, bit_set, block_size_2base, first_up_k, log2, nblock, roundup,
+            LEAF_ALIGN_ERROR_MSG, MIN_LEAF_SIZE_ALIGN,
+        },
+        error::Error,
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        ptr::NonNull,
+        sync::atomic::{AtomicU32, Ordering},
+    },
+};
+
+const OOM_MSG: &str = "requires more memory space to initialize SharedAlloc";
+const BASE_ALIGN_ERROR_MSG: &str = "region base must be aligned to leaf size";
+const MAGIC: u32 = 0x4255_4459;
+const VERSION: u32 = 1;
+
+/// Free list node, links are offsets from the region base.
+#[repr(C)]
+struct Node {
+    next: usize,
+    prev: usize,
+}
+
+#[repr(C)]
+struct Entry {
+    /// list head of free blocks
+    free: Node,
+    /// offset of the bit array to keep tracking alloc
+    alloc: usize,
+    /// offset of the bit array to keep tracking split
+    split: usize,
+}
+
+/// Stored at the region base.
+#[repr(C)]
+struct Header {
+    magic: u32,
+    version: u32,
+    /// 1 while a handle operates on the heap
+    lock: AtomicU32,
+    /// region length
+    len: usize,
+    /// offset of the first block
+    heap_offset: usize,
+    /// unavailable bytes at the region end
+    unavailable: usize,
+    entries_size: usize,
+    /// min size of a block, represent in 1 << leaf2base
+    leaf2base: usize,
+}
+
+/// Location of an allocation relative to the region base,
+/// stays valid in every mapping of the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeapOffset(usize);
+
+impl HeapOffset {
+    /// bytes from the region base
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+const ENTRIES_OFFSET: usize = core::mem::size_of::<Header>();
+
+/// How a handle waits while another handle holds the heap lock.
+pub trait WaitStrategy {
+    /// Called after each failed attempt, `attempts` counts from 0.
+    fn wait(&self, attempts: u32);
+}
+
+/// Busy wait
+#[derive(Clone, Copy, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    fn wait(&self, _attempts: u32) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Spin for a while, then yield the thread to the OS scheduler
+#[cfg(any(test, feature = "std"))]
+#[derive(Clone, Copy, Default)]
+pub struct Yield;
+
+#[cfg(any(test, feature = "std"))]
+impl WaitStrategy for Yield {
+    fn wait(&self, attempts: u32) {
+        if attempts < 64 {
+            core::hint::spin_loop();
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
+
+struct LockGuard<'a>(&'a AtomicU32);
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(0, Ordering::Release);
+    }
+}
+
+/// A handle to a position independent buddy heap.
+/// Handles are cheap, one per mapping of the region.
+pub struct SharedAlloc<W: WaitStrategy = Spin> {
+    base: NonNull<u8>,
+    wait: W,
+}
+
+// heap operations are serialized by the in-region lock
+unsafe impl<W: WaitStrategy + Send> Send for SharedAlloc<W> {}
+unsafe impl<W: WaitStrategy + Sync> Sync for SharedAlloc<W> {}
+
+impl SharedAlloc {
+    /// Format `base..(base + len)` as an empty heap.
+    ///
+    /// # Safety
+    ///
+    /// The memory range must be allocated and writable, and nothing else may
+    /// write to it while any handle to the heap is in use.
+    /// The function panics if memory space not enough for initialize the heap,
+    /// or if `base` is not aligned to `leaf_size`.
+    pub unsafe fn init(base: NonNull<u8>, len: usize, leaf_size: usize) -> Self {
+        assert!(
+            leaf_size.is_multiple_of(MIN_LEAF_SIZE_ALIGN) && leaf_size != 0,
+            "{}",
+            LEAF_ALIGN_ERROR_MSG
+        );
+        assert_eq!(
+            base.as_ptr() as usize % leaf_size,
+            0,
+            "{}",
+            BASE_ALIGN_ERROR_MSG
+        );
+        let leaf2base = log2(leaf_size);
+        let entries_size = log2(len >> leaf2base) + 2;
+        let alloc = SharedAlloc { base, wait: Spin };
+
+        let mut offset = ENTRIES_OFFSET + core::mem::size_of::<Entry>() * entries_size;
+        assert!(len >= offset, "{}", OOM_MSG);
+        for k in 0..entries_size {
+            Node::init(&alloc, Self::free_list(k));
+        }
+
+        // init alloc
+        for k in 0..entries_size {
+            // use one bit for per memory block
+            let used_bytes = roundup(nblock(k, entries_size), 3) >> 3;
+            assert!(len >= offset + used_bytes, "{}", OOM_MSG);
+            (*alloc.entry_ptr(k)).alloc = offset;
+            core::ptr::write_bytes(alloc.at::<u8>(offset), 0, used_bytes);
+            offset += used_bytes;
+        }
+
+        // init split
+        for k in 1..entries_size {
+            let used_bytes = roundup(nblock(k, entries_size), 3) >> 3;
+            assert!(len >= offset + used_bytes, "{}", OOM_MSG);
+            (*alloc.entry_ptr(k)).split = offset;
+            core::ptr::write_bytes(alloc.at::<u8>(offset), 0, used_bytes);
+            offset += used_bytes;
+        }
+
+        let heap_offset = roundup(offset, leaf2base);
+        assert!(len >= heap_offset, "{}", OOM_MSG);
+        alloc.at::<Header>(0).write(Header {
+            magic: MAGIC,
+            version: VERSION,
+            lock: AtomicU32::new(0),
+            len,
+            heap_offset,
+            unavailable: 0,
+            entries_size,
+            leaf2base,
+        });
+        alloc.init_free_list();
+        alloc
+    }
+
+    /// Attach to a heap formatted by `init`, possibly mapped at another address.
+    /// Returns `None` if the region does not hold a heap of this version.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to a mapping of the whole region,
+    /// see `init` for the other requirements.
+    pub unsafe fn attach(base: NonNull<u8>) -> Option<Self> {
+        let alloc = SharedAlloc { base, wait: Spin };
+        let header = alloc.header();
+        if header.magic != MAGIC || header.version != VERSION {
+            return None;
+        }
+        if !(base.as_ptr() as usize).is_multiple_of(1 << header.leaf2base) {
+            return None;
+        }
+        Some(alloc)
+    }
+}
+
+impl<W: WaitStrategy> SharedAlloc<W> {
+    /// Use `wait` while the heap is locked by another handle.
+    pub fn with_wait_strategy<V: WaitStrategy>(self, wait: V) -> SharedAlloc<V> {
+        SharedAlloc {
+            base: self.base,
+            wait,
+        }
+    }
+
+    fn lock(&self) -> LockGuard<'_> {
+        let lock = &self.header().lock;
+        let mut attempts = 0;
+        while lock
+            .compare_exchange_weak(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.wait.wait(attempts);
+            attempts = attempts.saturating_add(1);
+        }
+        LockGuard(lock)
+    }
+
+    fn init_free_list(&self) {
+        let header = self.header();
+        let mut offset = header.heap_offset;
+        let end = header.len;
+        let entries_size = header.entries_size;
+
+        // try alloc blocks
+        for k in (0..(entries_size - 1)).rev() {
+            let block_size = block_size_2base(k, header.leaf2base);
+            let entry = self.entry(k);
+            let parent_entry = self.entry(k + 1);
+
+            // alloc free blocks
+            while offset + block_size <= end {
+                Node::push(self, Self::free_list(k), offset);
+                // mark parent's split and alloc
+                let block_index = self.block_index(k, offset);
+                if block_index & 1 == 0 {
+                    let parent_index = self.block_index(k + 1, offset);
+                    bit_set(self.at(parent_entry.alloc), parent_index);
+                    bit_set(self.at(parent_entry.split), parent_index);
+                }
+                offset += block_size;
+            }
+
+            // mark unavailable blocks as allocated
+            let unavailable_block_index = self.block_index(k, offset);
+            debug_assert!(unavailable_block_index < nblock(k, entries_size));
+            bit_set(self.at(entry.alloc), unavailable_block_index);
+        }
+
+        unsafe { (*self.at::<Header>(0)).unavailable = end - offset };
+    }
+
+    /// region base of this mapping
+    pub fn base(&self) -> NonNull<u8> {
+        self.base
+    }
+
+    /// available bytes
+    pub fn available_bytes(&self) -> usize {
+        let header = self.header();
+        header.len - header.unavailable - header.heap_offset
+    }
+
+    /// Allocate a block and return its offset instead of its address.
+    pub fn allocate_offset(&self, layout: Layout) -> Result<HeapOffset, Error> {
+        let _guard = self.lock();
+        self.alloc_offset(layout).map(HeapOffset)
+    }
+
+    /// Free a block returned by `allocate_offset`, through any mapping.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must denote a live allocation of this heap.
+    pub unsafe fn deallocate_offset(&self, offset: HeapOffset) {
+        let _guard = self.lock();
+        self.free_offset(offset.0)
+    }
+
+    /// Address of `offset` in this mapping.
+    pub fn resolve(&self, offset: HeapOffset) -> NonNull<u8> {
+        debug_assert!(offset.0 < self.header().len, "offset out of range");
+        unsafe { NonNull::new_unchecked(self.at(offset.0)) }
+    }
+
+    /// Offset of `ptr` in this mapping, `None` if it lies outside the region.
+    pub fn offset_of(&self, ptr: NonNull<u8>) -> Option<HeapOffset> {
+        let offset = (ptr.as_ptr() as usize).checked_sub(self.base.as_ptr() as usize)?;
+        (offset < self.header().len).then_some(HeapOffset(offset))
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*self.at::<Header>(0) }
+    }
+
+    fn at<T>(&self, offset: usize) -> *mut T {
+        unsafe { self.base.as_ptr().add(offset).cast() }
+    }
+
+    /// offset of the k-th free list head
+    fn free_list(k: usize) -> usize {
+        ENTRIES_OFFSET + k * core::mem::size_of::<Entry>()
+    }
+
+    fn entry_ptr(&self, k: usize) -> *mut Entry {
+        self.at(Self::free_list(k))
+    }
+
+    fn entry(&self, k: usize) -> &Entry {
+        debug_assert!(k < self.header().entries_size, "index out of range");
+        unsafe { &*self.entry_ptr(k) }
+    }
+
+    /// find k for the block at offset
+    fn find_k(&self, offset: usize) -> usize {
+        for k in 0..(self.header().entries_size - 1) {
+            if bit_isset(
+                self.at(self.entry(k + 1).split),
+                self.block_index(k + 1, offset),
+            ) {
+                return k;
+            }
+        }
+        0
+    }
+
+    /// block index of offset under k
+    fn block_index(&self, k: usize, offset: usize) -> usize {
+        let header = self.header();
+        debug_assert!(offset >= header.heap_offset);
+        ((offset - header.heap_offset) >> k) >> header.leaf2base
+    }
+
+    /// block offset of index under k
+    fn block_offset(&self, k: usize, i: usize) -> usize {
+        let header = self.header();
+        header.heap_offset + ((i << k) << header.leaf2base)
+    }
+
+    /// Blocks are aligned to their size relative to the heap start, alignments
+    /// the heap start of this mapping doesn't have are unsupported.
+    fn alloc_offset(&self, layout: Layout) -> Result<usize, Error> {
+        let header = self.header();
+        let leaf2base = header.leaf2base;
+        if !(self.base.as_ptr() as usize + header.heap_offset).is_multiple_of(layout.align()) {
+            return Err(Error::AlignmentUnsupported);
+        }
+        let fk = first_up_k(layout.size().max(layout.align()), 1 << leaf2base);
+        let mut k = (fk..header.entries_size)
+            .find(|&k| !Node::is_empty(self, Self::free_list(k)))
+            .ok_or(Error::OutOfMemory)?;
+        let p = Node::pop(self, Self::free_list(k));
+        bit_set(self.at(self.entry(k).alloc), self.block_index(k, p));
+        while k > fk {
+            let q = p + block_size_2base(k - 1, leaf2base);
+            bit_set(self.at(self.entry(k).split), self.block_index(k, p));
+            bit_set(self.at(self.entry(k - 1).alloc), self.block_index(k - 1, p));
+            Node::push(self, Self::free_list(k - 1), q);
+            k -= 1;
+        }
+        Ok(p)
+    }
+
+    fn free_offset(&self, mut p: usize) {
+        let entries_size = self.header().entries_size;
+        let mut k = self.find_k(p);
+        while k < (entries_size - 1) {
+            let block_index = self.block_index(k, p);
+            let alloc = self.at(self.entry(k).alloc);
+            bit_clear(alloc, block_index);
+            let is_head = block_index & 1 == 0;
+            let buddy = if is_head {
+                block_index + 1
+            } else {
+                block_index - 1
+            };
+            if bit_isset(alloc, buddy) {
+                break;
+            }
+            // merge buddy since its free
+            let q = self.block_offset(k, buddy);
+            Node::remove(self, q);
+            if !is_head {
+                p = q;
+            }
+            bit_clear(self.at(self.entry(k + 1).split), self.block_index(k + 1, p));
+            k += 1;
+        }
+        Node::push(self, Self::free_list(k), p);
+    }
+}
+
+impl Node {
+    fn get<W: WaitStrategy>(alloc: &SharedAlloc<W>, offset: usize) -> *mut Node {
+        alloc.at(offset)
+    }
+
+    fn init<W: WaitStrategy>(alloc: &SharedAlloc<W>, list: usize) {
+        unsafe {
+            let n = Self::get(alloc, list);
+            (*n).next = list;
+            (*n).prev = list;
+        }
+    }
+
+    fn remove<W: WaitStrategy>(alloc: &SharedAlloc<W>, list: usize) {
+        unsafe {
+            let n = Self::get(alloc, list);
+            (*Self::get(alloc, (*n).prev)).next = (*n).next;
+            (*Self::get(alloc, (*n).next)).prev = (*n).prev;
+        }
+    }
+
+    fn pop<W: WaitStrategy>(alloc: &SharedAlloc<W>, list: usize) -> usize {
+        debug_assert!(!Self::is_empty(alloc, list));
+        let n_list = unsafe { (*Self::get(alloc, list)).next };
+        Self::remove(alloc, n_list);
+        n_list
+    }
+
+    fn push<W: WaitStrategy>(alloc: &SharedAlloc<W>, list: usize, p: usize) {
+        unsafe {
+            let head = Self::get(alloc, list);
+            Self::get(alloc, p).write(Node {
+                prev: list,
+                next: (*head).next,
+            });
+            (*Self::get(alloc, (*head).next)).prev = p;
+            (*head).next = p;
+        }
+    }
+
+    fn is_empty<W: WaitStrategy>(alloc: &SharedAlloc<W>, list: usize) -> bool {
+        unsafe { (*Self::get(alloc, list)).next == list }
+    }
+}
+
+unsafe impl<W: WaitStrategy> Allocator for SharedAlloc<W> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let offset = self.allocate_offset(layout)?;
+        Ok(NonNull::slice_from_raw_parts(
+            self.resolve(offset),
+            layout.size(),
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        let offset = self.offset_of(ptr).expect("pointer outside the region");
+        self.deallocate_offset(offset);
+    }
+}
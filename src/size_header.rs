@@ -0,0 +1,155 @@
+//! Size header alloc
+//! An allocator wrapper storing each layout in a header in front of it.
+//!
+//! Frees and resizes read the layout back from the header, so callers don't
+//! have to pass the one they allocated with, e.g. C `free()` or code that
+//! frees with a sloppy layout. The header costs HEADER bytes per
+//! allocation, more for alignments above it.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    mem::{align_of, size_of},
+    ptr::NonNull,
+};
+
+/// Bytes of the header, the size and the alignment of the allocation
+pub const HEADER: usize = 2 * size_of::<usize>();
+
+/// SizeHeaderAlloc
+/// an allocator wrapper freeing by the layout stored in front of each allocation
+pub struct SizeHeaderAlloc<A> {
+    inner: A,
+}
+
+impl<A: Allocator> SizeHeaderAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        SizeHeaderAlloc { inner }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// the layout `ptr` was allocated with
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this allocator.
+    pub unsafe fn layout_of(&self, ptr: NonNull<u8>) -> Layout {
+        let header = ptr.as_ptr().sub(HEADER).cast::<[usize; 2]>().read();
+        Layout::from_size_align_unchecked(header[0], header[1])
+    }
+
+    /// Free `ptr` whatever layout it has.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this allocator.
+    pub unsafe fn free(&self, ptr: NonNull<u8>) {
+        let (outer, front) = Self::outer(self.layout_of(ptr)).expect("layout allocated before");
+        self.inner.deallocate(ptr.sub(front), outer)
+    }
+
+    /// Move `ptr` to a block of `new_size` bytes with the same alignment,
+    /// keeping the contents up to the smaller size. On failure `ptr` stays live.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation of this allocator.
+    pub unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        new_size: usize,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new_layout = Layout::from_size_align(new_size, self.layout_of(ptr).align())
+            .map_err(|_| AllocError)?;
+        self.resize(ptr, new_layout)
+    }
+
+    unsafe fn resize(
+        &self,
+        ptr: NonNull<u8>,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = self.allocate(new_layout)?;
+        let size = self.layout_of(ptr).size().min(new_layout.size());
+        new.as_mut_ptr()
+            .copy_from_nonoverlapping(ptr.as_ptr(), size);
+        self.free(ptr);
+        Ok(new)
+    }
+
+    /// layout of the inner block and the offset of the allocation in it
+    fn outer(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        let front = HEADER.next_multiple_of(layout.align());
+        let size = front.checked_add(layout.size()).ok_or(AllocError)?;
+        let align = layout.align().max(align_of::<usize>());
+        let outer = Layout::from_size_align(size, align).map_err(|_| AllocError)?;
+        Ok((outer, front))
+    }
+
+    /// write the header of the allocation in `block`
+    fn stamp(block: NonNull<[u8]>, layout: Layout, front: usize) -> NonNull<[u8]> {
+        unsafe {
+            let p = block.as_mut_ptr().add(front);
+            p.sub(HEADER)
+                .cast::<[usize; 2]>()
+                .write([layout.size(), layout.align()]);
+            NonNull::slice_from_raw_parts(NonNull::new_unchecked(p), layout.size())
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for SizeHeaderAlloc<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (outer, front) = Self::outer(layout)?;
+        Ok(Self::stamp(self.inner.allocate(outer)?, layout, front))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (outer, front) = Self::outer(layout)?;
+        Ok(Self::stamp(
+            self.inner.allocate_zeroed(outer)?,
+            layout,
+            front,
+        ))
+    }
+
+    /// `layout` is ignored, the header has the real one
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.free(ptr)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        _old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.resize(ptr, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        _old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let old_size = self.layout_of(ptr).size();
+        let new = self.resize(ptr, new_layout)?;
+        new.as_mut_ptr()
+            .add(old_size)
+            .write_bytes(0, new_layout.size() - old_size);
+        Ok(new)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        _old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.resize(ptr, new_layout)
+    }
+}
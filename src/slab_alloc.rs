@@ -0,0 +1,177 @@
+//! Slab alloc
+//! Several fixed size classes, each with its own free list of objects.
+//!
+//! Empty classes are refilled a slab at a time from a parent allocator, the
+//! slab is carved into objects of that class only. Successive slabs of a
+//! class start at different cache colors, see SlabColorer. Requests larger
+//! than every class go to the parent directly. Slabs stay with their class
+//! until the SlabAlloc is dropped.
+
+use {
+    crate::slab_color::{SlabColorer, CACHE_LINE_SIZE},
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        mem::size_of,
+        ptr::{null_mut, NonNull},
+    },
+};
+
+const CLASS_SIZE_ERROR_MSG: &str = "class sizes must be sorted multiples of the pointer size";
+const SLAB_SIZE_ERROR_MSG: &str = "slab too small for the largest class";
+
+struct Node {
+    next: *mut Node,
+}
+
+struct Class {
+    /// object size
+    size: usize,
+    /// slabs are allocated with this layout, objects are aligned to it
+    slab_layout: Layout,
+    free: Cell<*mut Node>,
+    /// slabs of this class, linked through their first word
+    slabs: Cell<*mut u8>,
+    nslabs: Cell<usize>,
+    nfree: Cell<usize>,
+    colorer: SlabColorer,
+}
+
+/// SlabAlloc
+/// `CLASSES` size classes refilled from a parent allocator
+pub struct SlabAlloc<'a, A: Allocator, const CLASSES: usize> {
+    parent: &'a A,
+    classes: [Class; CLASSES],
+}
+
+impl<'a, A: Allocator, const CLASSES: usize> SlabAlloc<'a, A, CLASSES> {
+    /// `sizes` are the object sizes of the classes, sorted, multiples of the
+    /// pointer size and much smaller than `slab_size`. An object is aligned
+    /// to the largest power of two dividing its size.
+    pub fn new(parent: &'a A, sizes: [usize; CLASSES], slab_size: usize) -> Self {
+        assert!(
+            sizes.windows(2).all(|w| w[0] < w[1])
+                && sizes
+                    .iter()
+                    .all(|&s| s > 0 && s.is_multiple_of(size_of::<Node>())),
+            "{}",
+            CLASS_SIZE_ERROR_MSG
+        );
+        assert!(
+            sizes
+                .iter()
+                .all(|&s| (1 << s.trailing_zeros()) + s <= slab_size),
+            "{}",
+            SLAB_SIZE_ERROR_MSG
+        );
+        SlabAlloc {
+            parent,
+            classes: sizes.map(|size| {
+                let align = 1 << size.trailing_zeros();
+                // the slab link takes the first `align` bytes
+                let span = slab_size.saturating_sub(align);
+                Class {
+                    size,
+                    slab_layout: Layout::from_size_align(slab_size, align).expect("slab size"),
+                    free: Cell::new(null_mut()),
+                    slabs: Cell::new(null_mut()),
+                    nslabs: Cell::new(0),
+                    nfree: Cell::new(0),
+                    colorer: SlabColorer::new(span, size, CACHE_LINE_SIZE.next_multiple_of(align)),
+                }
+            }),
+        }
+    }
+
+    /// the parent allocator
+    pub fn parent(&self) -> &'a A {
+        self.parent
+    }
+
+    /// object size of the class at `index`
+    pub fn class_size(&self, index: usize) -> usize {
+        self.classes[index].size
+    }
+
+    /// slabs taken from the parent by the class at `index`
+    pub fn slabs(&self, index: usize) -> usize {
+        self.classes[index].nslabs.get()
+    }
+
+    /// free objects in the class at `index`
+    pub fn free_objects(&self, index: usize) -> usize {
+        self.classes[index].nfree.get()
+    }
+
+    fn class_for(&self, layout: Layout) -> Option<&Class> {
+        self.classes.iter().find(|class| {
+            class.size >= layout.size() && class.slab_layout.align() >= layout.align()
+        })
+    }
+
+    /// carve a new slab into the free list of `class`
+    fn refill(&self, class: &Class) -> Result<(), AllocError> {
+        let slab = self.parent.allocate(class.slab_layout)?.as_mut_ptr();
+        unsafe {
+            slab.cast::<*mut u8>().write(class.slabs.get());
+            class.slabs.set(slab);
+            class.nslabs.set(class.nslabs.get() + 1);
+            let first = class.slab_layout.align() + class.colorer.next_offset();
+            let span = class.slab_layout.size() - first;
+            let n = SlabColorer::objects_per_slab(span, class.size, 0);
+            for i in 0..n {
+                let node = slab.add(first + i * class.size).cast::<Node>();
+                node.write(Node {
+                    next: class.free.get(),
+                });
+                class.free.set(node);
+            }
+            class.nfree.set(class.nfree.get() + n);
+        }
+        Ok(())
+    }
+}
+
+unsafe impl<A: Allocator, const CLASSES: usize> Allocator for SlabAlloc<'_, A, CLASSES> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let Some(class) = self.class_for(layout) else {
+            return self.parent.allocate(layout);
+        };
+        if class.free.get().is_null() {
+            self.refill(class)?;
+        }
+        let node = class.free.get();
+        class.free.set(unsafe { (*node).next });
+        class.nfree.set(class.nfree.get() - 1);
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(node.cast()) },
+            class.size,
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let Some(class) = self.class_for(layout) else {
+            return self.parent.deallocate(ptr, layout);
+        };
+        let node = ptr.as_ptr().cast::<Node>();
+        node.write(Node {
+            next: class.free.get(),
+        });
+        class.free.set(node);
+        class.nfree.set(class.nfree.get() + 1);
+    }
+}
+
+impl<A: Allocator, const CLASSES: usize> Drop for SlabAlloc<'_, A, CLASSES> {
+    fn drop(&mut self) {
+        for class in &self.classes {
+            let mut slab = class.slabs.get();
+            while let Some(p) = NonNull::new(slab) {
+                unsafe {
+                    slab = p.as_ptr().cast::<*mut u8>().read();
+                    self.parent.deallocate(p, class.slab_layout);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Slab coloring
+//! Offsets for successive slabs so their objects spread over cache sets.
+//!
+//! A span carved into same-size objects usually has some slack left at its
+//! end. Instead of leaving it there, each new slab starts its first object
+//! at a different multiple of the cache line ("color") within that slack.
+//! Hot objects of different slabs then no longer map to the same cache sets.
+
+use core::cell::Cell;
+
+/// Typical cache line size
+pub const CACHE_LINE_SIZE: usize = 64;
+
+pub struct SlabColorer {
+    /// distance between two colors
+    step: usize,
+    /// number of distinct colors
+    colors: usize,
+    next: Cell<usize>,
+}
+
+impl SlabColorer {
+    /// Colors for spans of `span_size` bytes holding objects of `object_size`
+    /// bytes, with colors `step` bytes apart (usually the cache line size,
+    /// and a multiple of the objects' alignment).
+    pub const fn new(span_size: usize, object_size: usize, step: usize) -> Self {
+        let slack = if object_size == 0 || object_size > span_size {
+            0
+        } else {
+            span_size % object_size
+        };
+        SlabColorer {
+            step,
+            colors: slack / step + 1,
+            next: Cell::new(0),
+        }
+    }
+
+    /// number of distinct colors
+    pub fn colors(&self) -> usize {
+        self.colors
+    }
+
+    /// Offset of the first object in the next slab, cycles through all colors.
+    pub fn next_offset(&self) -> usize {
+        let color = self.next.get();
+        self.next.set((color + 1) % self.colors);
+        color * self.step
+    }
+
+    /// objects fitting into a span that starts at `offset`
+    pub fn objects_per_slab(span_size: usize, object_size: usize, offset: usize) -> usize {
+        (span_size - offset) / object_size
+    }
+}
@@ -0,0 +1,94 @@
+//! SmallAlloc
+//! N bytes of inline storage, falling back to a backing allocator.
+//!
+//! Small workloads are bump allocated out of the struct itself and never touch
+//! the backing allocator, e.g. a `Vec::new_in(&small)` that stays short.
+//! The inline storage is reclaimed once every inline allocation is freed,
+//! the most recent one is reclaimed right away.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+    ptr::NonNull,
+};
+
+pub struct SmallAlloc<'a, A: Allocator, const N: usize> {
+    storage: UnsafeCell<MaybeUninit<[u8; N]>>,
+    /// offset of the first unused inline byte
+    next: Cell<usize>,
+    /// inline allocations not yet deallocated
+    live: Cell<usize>,
+    backing: &'a A,
+}
+
+impl<'a, A: Allocator, const N: usize> SmallAlloc<'a, A, N> {
+    pub const fn new(backing: &'a A) -> Self {
+        SmallAlloc {
+            storage: UnsafeCell::new(MaybeUninit::uninit()),
+            next: Cell::new(0),
+            live: Cell::new(0),
+            backing,
+        }
+    }
+
+    /// the backing allocator
+    pub fn backing(&self) -> &'a A {
+        self.backing
+    }
+
+    /// inline bytes in use, including alignment padding
+    pub fn inline_used(&self) -> usize {
+        self.next.get()
+    }
+
+    /// whether p points into the inline storage
+    pub fn is_inline(&self, p: *const u8) -> bool {
+        let start = self.start().addr();
+        (start..start + N).contains(&p.addr())
+    }
+
+    fn start(&self) -> *mut u8 {
+        self.storage.get().cast()
+    }
+
+    fn allocate_inline(&self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let next = self.next.get();
+        let offset = next + self.start().wrapping_add(next).align_offset(layout.align());
+        let end = offset.checked_add(layout.size())?;
+        if end > N {
+            return None;
+        }
+        self.next.set(end);
+        self.live.set(self.live.get() + 1);
+        let p = self.start().wrapping_add(offset);
+        Some(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(p) },
+            layout.size(),
+        ))
+    }
+}
+
+unsafe impl<A: Allocator, const N: usize> Allocator for SmallAlloc<'_, A, N> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match self.allocate_inline(layout) {
+            Some(p) => Ok(p),
+            None => self.backing.allocate(layout),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if !self.is_inline(ptr.as_ptr()) {
+            return self.backing.deallocate(ptr, layout);
+        }
+        let live = self.live.get() - 1;
+        self.live.set(live);
+        let offset = ptr.as_ptr().addr() - self.start().addr();
+        if live == 0 {
+            self.next.set(0);
+        } else if offset + layout.size() == self.next.get() {
+            // the most recent allocation, its padding stays used
+            self.next.set(offset);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Snapshot
+//! A copy of the bitmaps of a BuddyAlloc, analysed off the allocation path.
+//!
+//! Taking a snapshot only copies the bitmaps, e.g. in a brief critical section
+//! with `Locked::with`. Fragmentation reports and dumps then run on the copy
+//! while the heap stays in use.
+
+use crate::{
+    buddy_alloc::{block_size, nblock, roundup, BuddyAlloc},
+    heap_registry::HeapRange,
+    inspect::HeapStats,
+};
+
+/// Snapshot
+/// the alloc and split bitmaps of a heap at one point in time
+pub struct Snapshot<'a> {
+    /// alloc bitmaps from order 0, then split bitmaps from order 1
+    bits: &'a [u8],
+    entries_size: usize,
+    leaf_size: usize,
+    base_addr: usize,
+    total_bytes: usize,
+}
+
+/// bytes of the order k bitmaps of a heap with `entries_size` orders
+fn bitmap_len(k: usize, entries_size: usize) -> usize {
+    roundup(nblock(k, entries_size), 3) >> 3
+}
+
+impl<'a> Snapshot<'a> {
+    /// bytes of the buffer a snapshot of `heap` takes
+    pub fn size(heap: &BuddyAlloc) -> usize {
+        let entries_size = heap.entries_size();
+        (0..entries_size)
+            .map(|k| bitmap_len(k, entries_size) * if k == 0 { 1 } else { 2 })
+            .sum()
+    }
+
+    /// Copy the bitmaps of `heap` into `buf`,
+    /// returns None if `buf` is shorter than `size`.
+    pub fn take(heap: &BuddyAlloc, buf: &'a mut [u8]) -> Option<Self> {
+        let buf = buf.get_mut(..Self::size(heap))?;
+        let entries_size = heap.entries_size();
+        let split_start = (0..entries_size)
+            .map(|k| bitmap_len(k, entries_size))
+            .sum::<usize>();
+        let (alloc_bits, split_bits) = buf.split_at_mut(split_start);
+        let (mut alloc_at, mut split_at) = (0, 0);
+        for k in 0..entries_size {
+            let (alloc, split) = heap.bitmaps(k);
+            alloc_bits[alloc_at..alloc_at + alloc.len()].copy_from_slice(alloc);
+            split_bits[split_at..split_at + split.len()].copy_from_slice(split);
+            alloc_at += alloc.len();
+            split_at += split.len();
+        }
+        Some(Snapshot {
+            bits: buf,
+            entries_size,
+            leaf_size: heap.leaf_size(),
+            base_addr: heap.heap_range().start,
+            total_bytes: heap.available_bytes(),
+        })
+    }
+
+    /// whether the order k block at index i was free,
+    /// it isn't allocated and its parent is split
+    fn is_free(&self, k: usize, i: usize) -> bool {
+        let bit = |addr: usize, i: usize| self.bits[addr + (i >> 3)] & (1 << (i % 8)) != 0;
+        !bit(self.alloc_addr(k), i) && bit(self.split_addr(k + 1), i >> 1)
+    }
+
+    fn alloc_addr(&self, k: usize) -> usize {
+        (0..k).map(|j| bitmap_len(j, self.entries_size)).sum()
+    }
+
+    fn split_addr(&self, k: usize) -> usize {
+        self.alloc_addr(self.entries_size)
+            + (1..k)
+                .map(|j| bitmap_len(j, self.entries_size))
+                .sum::<usize>()
+    }
+
+    /// call `f` with the address and size of every free block,
+    /// from the smallest order to the largest, in address order within one
+    pub fn for_each_free_block<F: FnMut(usize, usize)>(&self, mut f: F) {
+        for k in 0..(self.entries_size - 1) {
+            let size = block_size(k, self.leaf_size);
+            for i in 0..nblock(k, self.entries_size) {
+                if self.is_free(k, i) {
+                    f(self.base_addr + i * size, size);
+                }
+            }
+        }
+    }
+
+    /// number of free blocks of order k
+    pub fn free_blocks(&self, k: usize) -> usize {
+        if k + 1 >= self.entries_size {
+            return 0;
+        }
+        (0..nblock(k, self.entries_size))
+            .filter(|&i| self.is_free(k, i))
+            .count()
+    }
+
+    /// the heap statistics at the time of the snapshot, without the peak and counters
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            total_bytes: self.total_bytes,
+            ..HeapStats::default()
+        };
+        self.for_each_free_block(|_, size| {
+            stats.free_bytes += size;
+            stats.largest_free = stats.largest_free.max(size);
+            stats.free_blocks += 1;
+        });
+        stats.used_bytes = stats.total_bytes.saturating_sub(stats.free_bytes);
+        stats
+    }
+}
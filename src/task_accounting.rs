@@ -0,0 +1,124 @@
+//! Task accounting
+//! An allocator wrapper charging allocations to the task making them.
+//!
+//! The current task comes from a user supplied hook, e.g. the RTOS task id.
+//! Every allocation carries a small header with the task it was charged to,
+//! so a block freed by another task still credits its owner. Allocations
+//! past a task's limit fail.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    cell::Cell,
+    ptr::NonNull,
+};
+
+/// Heap use of one task, in requested bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaskUsage {
+    pub used: usize,
+    pub peak: usize,
+    pub limit: usize,
+    /// allocations refused for going past the limit
+    pub rejected: usize,
+}
+
+struct Account {
+    used: Cell<usize>,
+    peak: Cell<usize>,
+    limit: Cell<usize>,
+    rejected: Cell<usize>,
+}
+
+pub struct TaskAccounting<A: Allocator, const TASKS: usize> {
+    inner: A,
+    current_task_id: fn() -> usize,
+    accounts: [Account; TASKS],
+}
+
+impl<A: Allocator, const TASKS: usize> TaskAccounting<A, TASKS> {
+    /// `current_task_id` returns the id of the running task, ids from
+    /// `TASKS - 1` up share the last account. It runs inside allocator calls
+    /// and must not allocate from this heap, see ReentryGuard.
+    pub const fn new(inner: A, current_task_id: fn() -> usize) -> Self {
+        TaskAccounting {
+            inner,
+            current_task_id,
+            accounts: [const {
+                Account {
+                    used: Cell::new(0),
+                    peak: Cell::new(0),
+                    limit: Cell::new(usize::MAX),
+                    rejected: Cell::new(0),
+                }
+            }; TASKS],
+        }
+    }
+
+    /// the wrapped allocator
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Refuse allocations taking `task` past `bytes` in use,
+    /// pass `usize::MAX` to lift the limit.
+    pub fn set_limit(&self, task: usize, bytes: usize) {
+        self.account(task).limit.set(bytes);
+    }
+
+    /// heap use of `task`
+    pub fn usage(&self, task: usize) -> TaskUsage {
+        let account = self.account(task);
+        TaskUsage {
+            used: account.used.get(),
+            peak: account.peak.get(),
+            limit: account.limit.get(),
+            rejected: account.rejected.get(),
+        }
+    }
+
+    fn slot(task: usize) -> usize {
+        task.min(TASKS - 1)
+    }
+
+    fn account(&self, task: usize) -> &Account {
+        &self.accounts[Self::slot(task)]
+    }
+
+    /// Layout with the task header, and the offset of the user block.
+    fn with_header(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        Layout::new::<usize>()
+            .extend(layout)
+            .map_err(|_| AllocError)
+    }
+}
+
+unsafe impl<A: Allocator, const TASKS: usize> Allocator for TaskAccounting<A, TASKS> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (full, offset) = Self::with_header(layout)?;
+        let task = Self::slot((self.current_task_id)());
+        let account = &self.accounts[task];
+        let used = account.used.get() + layout.size();
+        if used > account.limit.get() {
+            account.rejected.set(account.rejected.get() + 1);
+            return Err(AllocError);
+        }
+        let p = self.inner.allocate(full)?.as_mut_ptr();
+        account.used.set(used);
+        account.peak.set(account.peak.get().max(used));
+        unsafe {
+            p.cast::<usize>().write(task);
+            Ok(NonNull::slice_from_raw_parts(
+                NonNull::new_unchecked(p.add(offset)),
+                layout.size(),
+            ))
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let (full, offset) = Self::with_header(layout).expect("layout");
+        let p = ptr.as_ptr().sub(offset);
+        let account = &self.accounts[p.cast::<usize>().read()];
+        account.used.set(account.used.get() - layout.size());
+        self.inner.deallocate(NonNull::new_unchecked(p), full);
+    }
+}
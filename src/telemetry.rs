@@ -0,0 +1,152 @@
+//! Telemetry
+//! Compact binary heap snapshots for live dashboards during bring-up.
+//!
+//! The reporter writes snapshots to a `TelemetrySink`, usually an RTT up
+//! channel or a semihosting file handle, and the host side reads them back
+//! with `Snapshot::decode`. A frame is little endian:
+//!
+//! | bytes | field |
+//! |-------|-------|
+//! | 1 | `FRAME_MAGIC` |
+//! | 1 | `FRAME_VERSION` |
+//! | 1 | number of orders n |
+//! | 4 | timestamp |
+//! | 4 | free bytes |
+//! | 4 | largest free block |
+//! | 2 * n | free blocks per order |
+
+use {crate::buddy_alloc::BuddyAlloc, core::cell::Cell};
+
+pub const FRAME_MAGIC: u8 = 0xb7;
+pub const FRAME_VERSION: u8 = 1;
+/// orders carried by a frame
+pub const MAX_ORDERS: usize = 32;
+const HEADER_SIZE: usize = 15;
+/// size of the largest frame
+pub const MAX_FRAME_SIZE: usize = HEADER_SIZE + 2 * MAX_ORDERS;
+
+/// Byte channel frames are written to
+pub trait TelemetrySink {
+    fn write(&mut self, frame: &[u8]);
+}
+
+/// One heap snapshot, counts saturate at the field width
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub timestamp: u32,
+    pub free_bytes: u32,
+    pub largest_free: u32,
+    pub orders: usize,
+    pub free_blocks: [u16; MAX_ORDERS],
+}
+
+fn saturate_u32(n: usize) -> u32 {
+    u32::try_from(n).unwrap_or(u32::MAX)
+}
+
+impl Snapshot {
+    pub fn capture(heap: &BuddyAlloc, timestamp: u32) -> Self {
+        let mut free_bytes = 0;
+        let mut largest_free = 0;
+        heap.for_each_free_block(|_, size| {
+            free_bytes += size;
+            largest_free = largest_free.max(size);
+        });
+        let mut free_blocks = [0; MAX_ORDERS];
+        let mut orders = 0;
+        for (k, count) in free_blocks.iter_mut().enumerate() {
+            let n = heap.free_blocks(k);
+            *count = u16::try_from(n).unwrap_or(u16::MAX);
+            if n > 0 {
+                orders = k + 1;
+            }
+        }
+        Snapshot {
+            timestamp,
+            free_bytes: saturate_u32(free_bytes),
+            largest_free: saturate_u32(largest_free),
+            orders,
+            free_blocks,
+        }
+    }
+
+    /// Encode into `buf`, returns the frame length.
+    pub fn encode(&self, buf: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        buf[0] = FRAME_MAGIC;
+        buf[1] = FRAME_VERSION;
+        buf[2] = self.orders as u8;
+        buf[3..7].copy_from_slice(&self.timestamp.to_le_bytes());
+        buf[7..11].copy_from_slice(&self.free_bytes.to_le_bytes());
+        buf[11..15].copy_from_slice(&self.largest_free.to_le_bytes());
+        for (k, count) in self.free_blocks[..self.orders].iter().enumerate() {
+            let i = HEADER_SIZE + 2 * k;
+            buf[i..(i + 2)].copy_from_slice(&count.to_le_bytes());
+        }
+        HEADER_SIZE + 2 * self.orders
+    }
+
+    /// Decode a frame at the start of `buf`,
+    /// returns the snapshot and the frame length.
+    pub fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        let header = buf.get(..HEADER_SIZE)?;
+        if header[0] != FRAME_MAGIC || header[1] != FRAME_VERSION {
+            return None;
+        }
+        let orders = header[2] as usize;
+        if orders > MAX_ORDERS {
+            return None;
+        }
+        let len = HEADER_SIZE + 2 * orders;
+        let counts = buf.get(HEADER_SIZE..len)?;
+        let word = |i: usize| u32::from_le_bytes(header[i..(i + 4)].try_into().unwrap());
+        let mut free_blocks = [0; MAX_ORDERS];
+        for (count, bytes) in free_blocks.iter_mut().zip(counts.chunks_exact(2)) {
+            *count = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        let snapshot = Snapshot {
+            timestamp: word(3),
+            free_bytes: word(7),
+            largest_free: word(11),
+            orders,
+            free_blocks,
+        };
+        Some((snapshot, len))
+    }
+}
+
+/// Reporter
+/// writes a snapshot every `interval` ticks of the caller's clock
+pub struct Reporter {
+    interval: u32,
+    last: Cell<Option<u32>>,
+}
+
+impl Reporter {
+    pub const fn new(interval: u32) -> Self {
+        Reporter {
+            interval,
+            last: Cell::new(None),
+        }
+    }
+
+    /// Report if `interval` ticks passed since the last report,
+    /// returns whether a frame was written.
+    pub fn poll<S: TelemetrySink>(&self, heap: &BuddyAlloc, sink: &mut S, now: u32) -> bool {
+        let due = self
+            .last
+            .get()
+            .is_none_or(|last| now.wrapping_sub(last) >= self.interval);
+        if due {
+            self.report(heap, sink, now);
+        }
+        due
+    }
+
+    /// Report now
+    pub fn report<S: TelemetrySink>(&self, heap: &BuddyAlloc, sink: &mut S, now: u32) {
+        let mut buf = [0; MAX_FRAME_SIZE];
+        let len = Snapshot::capture(heap, now).encode(&mut buf);
+        sink.write(&buf[..len]);
+        self.last.set(Some(now));
+    }
+}
@@ -0,0 +1,109 @@
+use {
+    crate::buddy_alloc::atomic::{AtomicBuddyAlloc, AtomicBuddyAllocParam},
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 1024 * 1024;
+
+fn new_heap(buf: &[u8], leaf_size: usize) -> AtomicBuddyAlloc {
+    unsafe {
+        AtomicBuddyAlloc::new(AtomicBuddyAllocParam::new(
+            buf.as_ptr(),
+            buf.len(),
+            leaf_size,
+        ))
+    }
+}
+
+#[test]
+fn test_split_and_merge() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = new_heap(&buf, 16);
+    let available = heap.available_bytes();
+    assert_eq!(heap.free_bytes(), available);
+    let small = Layout::from_size_align(10, 1).unwrap();
+    let p = heap.allocate(small).unwrap();
+    let q = heap.allocate(small).unwrap();
+    assert_eq!(heap.free_bytes(), available - 32);
+    assert_ne!(p.as_mut_ptr(), q.as_mut_ptr());
+    unsafe {
+        heap.deallocate(p.as_non_null_ptr(), small);
+        heap.deallocate(q.as_non_null_ptr(), small);
+    }
+    assert_eq!(heap.free_bytes(), available);
+    // everything merged back into the largest block
+    let largest = 1 << (usize::BITS - 1 - available.leading_zeros());
+    let big = Layout::from_size_align(largest, 1).unwrap();
+    let p = heap.allocate(big).unwrap();
+    unsafe { heap.deallocate(p.as_non_null_ptr(), big) };
+}
+
+#[test]
+fn test_alignment_and_oom() {
+    let buf = vec![0u8; 64 * 1024];
+    let heap = new_heap(&buf, 64);
+    let layout = Layout::from_size_align(100, 64).unwrap();
+    let p = heap.allocate(layout).unwrap();
+    assert_eq!(p.as_mut_ptr().align_offset(64), 0);
+    unsafe { heap.deallocate(p.as_non_null_ptr(), layout) };
+    assert!(heap
+        .allocate(Layout::from_size_align(128 * 1024, 1).unwrap())
+        .is_err());
+}
+
+#[test]
+fn test_concurrent_alloc_free() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = new_heap(&buf, 16);
+    let available = heap.available_bytes();
+    std::thread::scope(|s| {
+        for t in 0..4u8 {
+            let heap = &heap;
+            s.spawn(move || {
+                let mut live = Vec::new();
+                for i in 0..2000usize {
+                    let layout = Layout::from_size_align(16 << (i % 4), 16).unwrap();
+                    let p = heap.allocate(layout).unwrap();
+                    unsafe { p.as_mut_ptr().write_bytes(t, layout.size()) };
+                    live.push((p, layout));
+                    if live.len() > 8 {
+                        let (p, layout) = live.swap_remove(i % live.len());
+                        assert!(unsafe { p.as_ref() }.iter().all(|&b| b == t));
+                        unsafe { heap.deallocate(p.as_non_null_ptr(), layout) };
+                    }
+                }
+                for (p, layout) in live {
+                    unsafe { heap.deallocate(p.as_non_null_ptr(), layout) };
+                }
+            });
+        }
+    });
+    assert_eq!(heap.free_bytes(), available);
+    // buddies freed at the same time merged too
+    let largest = 1 << (usize::BITS - 1 - available.leading_zeros());
+    let big = Layout::from_size_align(largest, 1).unwrap();
+    let p = heap.allocate(big).unwrap();
+    unsafe { heap.deallocate(p.as_non_null_ptr(), big) };
+}
+
+#[test]
+fn test_scan_wraps_around() {
+    let buf = vec![0u8; 64 * 1024];
+    let heap = new_heap(&buf, 16);
+    let leaf = Layout::from_size_align(16, 1).unwrap();
+    let mut ptrs = Vec::new();
+    while let Ok(p) = heap.allocate(leaf) {
+        ptrs.push(p);
+    }
+    assert!(ptrs.len() > 64 * 8);
+    // the hint moved past the front words, a block freed there is still found
+    let first = ptrs.swap_remove(0);
+    unsafe { heap.deallocate(first.as_non_null_ptr(), leaf) };
+    let p = heap.allocate(leaf).unwrap();
+    assert_eq!(p.as_mut_ptr(), first.as_mut_ptr());
+    ptrs.push(p);
+    for p in ptrs {
+        unsafe { heap.deallocate(p.as_non_null_ptr(), leaf) };
+    }
+    assert_eq!(heap.free_bytes(), heap.available_bytes());
+}
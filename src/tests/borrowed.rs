@@ -0,0 +1,30 @@
+use crate::{
+    borrowed::{BorrowedBuddyAlloc, BorrowedFreelistAlloc},
+    freelist_alloc::BLOCK_SIZE,
+    inspect::HeapInspect,
+};
+
+#[test]
+fn test_borrowed_buddy() {
+    let mut buf = vec![0u8; 64 * 1024];
+    let heap = BorrowedBuddyAlloc::new(&mut buf, 64);
+    let free = heap.stats().free_bytes;
+    {
+        let mut v = Vec::new_in(&heap);
+        v.extend(0..1000u32);
+        assert_eq!(v.iter().sum::<u32>(), 999 * 500);
+    }
+    assert_eq!(heap.stats().free_bytes, free);
+    heap.validate().unwrap();
+}
+
+#[test]
+fn test_borrowed_freelist() {
+    let mut buf = vec![0u8; BLOCK_SIZE * 16];
+    let heap = BorrowedFreelistAlloc::new(&mut buf);
+    let b = Box::new_in([7u8; BLOCK_SIZE], &heap);
+    assert!(heap.owns(b.as_ptr()));
+    drop(b);
+    // the borrow ends with the last use of the heap
+    buf[0] = 1;
+}
@@ -1,6 +1,18 @@
 use {
-    crate::buddy_alloc::{block_size, BuddyAlloc, BuddyAllocParam, MIN_LEAF_SIZE_ALIGN},
-    core::alloc::{Allocator, Layout},
+    crate::{
+        buddy_alloc::{
+            block_size, metadata_size, BlockState, BuddyAlloc, BuddyAllocParam, StaticBuddyHeap,
+            MIN_LEAF_SIZE_ALIGN, STATIC_HEAP_ALIGN,
+        },
+        error::Error,
+        heap_registry::HeapRange,
+        inspect::HeapInspect,
+        SliceSize,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
 };
 
 const HEAP_SIZE: usize = 1024 * 1024;
@@ -138,21 +150,6 @@ fn test_malloc_and_free() {
     }
 }
 
-#[test]
-fn test_free_bug() {
-    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
-        let layout = Layout::from_size_align(32, 1).unwrap();
-        let p1 = allocator.allocate(layout).unwrap();
-        unsafe { allocator.deallocate(p1.cast(), layout) };
-        let layout = Layout::from_size_align(40961, 1).unwrap();
-        let layout2 = Layout::from_size_align(1381, 1).unwrap();
-        let p2 = allocator.allocate(layout).unwrap();
-        let p3 = allocator.allocate(layout2).unwrap();
-        unsafe { allocator.deallocate(p2.cast(), layout) };
-        unsafe { allocator.deallocate(p3.cast(), layout2) };
-    });
-}
-
 #[test]
 fn test_malloc_and_free_gap() {
     // malloc 1 k and 2 k alternately, then consumes remain memory
@@ -216,91 +213,1029 @@ fn test_malloc_and_free_gap() {
 }
 
 #[test]
-fn test_example_bug() {
-    #[allow(clippy::vec_init_then_push)]
-    // simulate example bug
+fn test_alignment() {
+    let data = [0u8; 4 << 16];
+    println!("Buffer data: {:p}", data.as_ptr());
+    let allocator = unsafe { BuddyAlloc::new(BuddyAllocParam::new(data.as_ptr(), 4 << 16, 4096)) };
+    let p = allocator
+        .allocate(Layout::from_size_align(4, 1).unwrap())
+        .unwrap();
+    println!("Allocated pointer: {:p}", p);
+    // FIXME what does it test??
+}
+
+#[test]
+#[cfg(feature = "stats")]
+fn test_align_stats() {
+    // blocks of an aligned base are aligned to their size, unaligned bases
+    // take aligned addresses inside blocks rounded up less predictably
+    let heap_layout = Layout::from_size_align(HEAP_SIZE, 4096).unwrap();
+    let heap = unsafe { std::alloc::alloc(heap_layout) };
+    let metadata = vec![0usize; metadata_size(HEAP_SIZE, LEAF_SIZE).div_ceil(size_of::<usize>())];
+    let param = BuddyAllocParam::new_with_metadata(
+        heap,
+        HEAP_SIZE,
+        LEAF_SIZE,
+        metadata.as_ptr().cast(),
+        metadata.len() * size_of::<usize>(),
+    );
+    {
+        let allocator = unsafe { BuddyAlloc::new(param) };
+        allocator
+            .allocate(Layout::from_size_align(32, 8).unwrap())
+            .unwrap();
+        assert_eq!(allocator.align_stats().total_rounded(), 0);
+        allocator
+            .allocate(Layout::from_size_align(32, 256).unwrap())
+            .unwrap();
+        let k = first_down_k(256).unwrap();
+        assert_eq!(allocator.align_stats().rounded(k), 1);
+        assert_eq!(allocator.align_stats().wasted_bytes(k), 256 - 32);
+        assert_eq!(allocator.align_stats().total_wasted_bytes(), 256 - 32);
+    }
+    unsafe { std::alloc::dealloc(heap, heap_layout) };
+}
+
+#[test]
+fn test_reserve_blocks() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let k = 2;
+        assert!(allocator.free_blocks(k) <= 1);
+        allocator.reserve_blocks(k, 9).unwrap();
+        assert!(allocator.free_blocks(k) >= 9);
+        // reserved blocks are served without touching larger orders
+        let larger: Vec<usize> = (k + 1..20).map(|j| allocator.free_blocks(j)).collect();
+        let layout = Layout::from_size_align(block_size(k, LEAF_SIZE), 1).unwrap();
+        let ptrs: Vec<_> = (0..9)
+            .map(|_| allocator.allocate(layout).unwrap())
+            .collect();
+        let after: Vec<usize> = (k + 1..20).map(|j| allocator.free_blocks(j)).collect();
+        assert_eq!(larger, after);
+        for p in ptrs {
+            unsafe { allocator.deallocate(p.cast(), layout) };
+        }
+        // everything merged back
+        assert!(allocator
+            .allocate(Layout::from_size_align(HEAP_SIZE / 2, 1).unwrap())
+            .is_ok());
+        assert!(allocator.reserve_blocks(0, HEAP_SIZE).is_err());
+    });
+}
+
+#[test]
+fn test_allocate_up_to() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let min = Layout::from_size_align(100, 1).unwrap();
+        // plenty of memory, the preferred size is served
+        let p = allocator.allocate_up_to(min, 4000).unwrap();
+        assert_eq!(p.len(), 4000);
+        // drain all large blocks
+        let big = Layout::from_size_align(4096, 1).unwrap();
+        while allocator.allocate(big).is_ok() {}
+        let p = allocator.allocate_up_to(min, 4000).unwrap();
+        assert!(p.len() >= 100 && p.len() < 4000);
+        // nothing fits the minimum
+        while allocator.allocate(min).is_ok() {}
+        assert!(allocator.allocate_up_to(min, 4000).is_err());
+    });
+}
+
+#[test]
+fn test_allocate_up_to_past_heap() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let min = Layout::from_size_align(100, 1).unwrap();
+        let _small = allocator.allocate(min).unwrap();
+        let largest = allocator.allocate_largest().unwrap();
+        unsafe { allocator.deallocate(largest.cast(), min) };
+        for preferred in [2 * HEAP_SIZE, usize::MAX] {
+            let p = allocator.allocate_up_to(min, preferred).unwrap();
+            assert_eq!(p.len(), largest.len());
+            unsafe { allocator.deallocate(p.cast(), min) };
+        }
+    });
+}
+
+#[test]
+fn test_allocate_largest() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let largest = allocator.allocate_largest().unwrap();
+        assert!(largest.len().is_power_of_two());
+        assert!(largest.len() >= allocator.available_bytes() / 2);
+        let next = allocator.allocate_largest().unwrap();
+        assert!(next.len() <= largest.len());
+        unsafe { allocator.deallocate(largest.cast(), Layout::from_size_align(1, 1).unwrap()) };
+        assert_eq!(allocator.allocate_largest().unwrap().len(), largest.len());
+        while allocator.allocate_largest().is_ok() {}
+        assert!(allocator
+            .allocate(Layout::from_size_align(1, 1).unwrap())
+            .is_err());
+    });
+}
+
+#[test]
+fn test_free_regions() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let total: usize = allocator.free_regions().map(|(_, len)| len).sum();
+        assert_eq!(total, allocator.available_bytes());
+        assert_eq!(allocator.free_regions().count(), 1);
+
+        let layout = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+        let ptrs: Vec<_> = (0..4)
+            .map(|_| allocator.allocate(layout).unwrap())
+            .collect();
+        // punch a hole between two allocations
+        unsafe { allocator.deallocate(ptrs[1].cast(), layout) };
+        let regions: Vec<_> = allocator.free_regions().collect();
+        let hole = ptrs[1].as_mut_ptr() as usize;
+        assert!(regions
+            .iter()
+            .any(|&(addr, len)| addr <= hole && hole + LEAF_SIZE <= addr + len));
+        let total: usize = regions.iter().map(|(_, len)| len).sum();
+        assert_eq!(total, allocator.available_bytes() - 3 * LEAF_SIZE);
+        // regions are sorted, disjoint and not adjacent
+        assert!(regions.windows(2).all(|w| w[0].0 + w[0].1 < w[1].0));
+        for p in ptrs.into_iter().filter(|p| p.as_mut_ptr() as usize != hole) {
+            unsafe { allocator.deallocate(p.cast(), layout) };
+        }
+        assert_eq!(allocator.free_regions().count(), 1);
+    });
+}
+
+#[test]
+fn test_slice_size() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    assert_eq!(allocator.allocate(layout).unwrap().len(), 100);
+    let allocator = unsafe { BuddyAlloc::new(param.with_slice_size(SliceSize::Block)) };
+    assert_eq!(allocator.allocate(layout).unwrap().len(), 128);
+}
+
+#[test]
+fn test_alloc_size() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let small = allocator
+        .allocate(Layout::from_size_align(10, 1).unwrap())
+        .unwrap();
+    let large = allocator
+        .allocate(Layout::from_size_align(1000, 1).unwrap())
+        .unwrap();
+    unsafe {
+        assert_eq!(allocator.alloc_size(small.as_mut_ptr()), 64);
+        assert_eq!(allocator.alloc_size(large.as_mut_ptr()), 1024);
+    }
+}
+
+#[test]
+fn test_resize_in_place() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let free_bytes = || {
+        let mut n = 0;
+        allocator.for_each_free_block(|_, size| n += size);
+        n
+    };
+    let available_bytes = free_bytes();
+    let p = allocator
+        .allocate(Layout::from_size_align(4096, 1).unwrap())
+        .unwrap()
+        .as_mut_ptr();
+    unsafe {
+        assert!(allocator.resize_in_place(p, 100));
+        assert_eq!(allocator.alloc_size(p), 128);
+        assert_eq!(free_bytes(), available_bytes - 128);
+        assert!(allocator.resize_in_place(p, 4000));
+        assert_eq!(allocator.alloc_size(p), 4096);
+        assert_eq!(free_bytes(), available_bytes - 4096);
+        // once the buddy is taken p can't grow past it
+        assert!(allocator.resize_in_place(p, 100));
+        let q = allocator
+            .allocate(Layout::from_size_align(128, 1).unwrap())
+            .unwrap()
+            .as_mut_ptr();
+        assert_eq!(q as usize, p as usize + 128);
+        assert!(!allocator.resize_in_place(p, 256));
+        assert_eq!(allocator.alloc_size(p), 128);
+        allocator.deallocate(NonNull::new_unchecked(q), Layout::new::<u8>());
+        allocator.deallocate(NonNull::new_unchecked(p), Layout::new::<u8>());
+    }
+    assert_eq!(free_bytes(), available_bytes);
+}
+
+#[test]
+#[should_panic(expected = "leaf size must be aligned to 16 bytes")]
+fn test_invalid_leaf_size() {
+    BuddyAllocParam::new(core::ptr::null(), HEAP_SIZE, 24);
+}
+
+#[test]
+#[should_panic(expected = "heap too small to hold its metadata and one leaf")]
+fn test_heap_too_small() {
+    BuddyAllocParam::new(core::ptr::null(), 64, 64);
+}
+
+#[test]
+fn test_metadata_size() {
+    // the smallest heap accepted by the param checks
+    let leaf_size = 64;
+    let mut heap_size = leaf_size;
+    while heap_size < metadata_size(heap_size, leaf_size) + 3 * leaf_size {
+        heap_size += 1;
+    }
+    // misaligned, so both paddings are needed, with a canary past the end
+    let mut buf = vec![0xeeu8; heap_size + 1 + 64];
+    let param = BuddyAllocParam::new(buf.as_ptr().wrapping_add(1), heap_size, leaf_size);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    assert!(allocator
+        .allocate(Layout::from_size_align(leaf_size, 1).unwrap())
+        .is_ok());
+    assert!(buf.split_off(heap_size + 1).iter().all(|&b| b == 0xee));
+}
+
+#[test]
+fn test_external_metadata() {
+    let leaf_size = 64;
+    let heap_size = 4 * 1024;
+    let buf: Vec<u8> = Vec::with_capacity(heap_size + leaf_size);
+    let heap = buf
+        .as_ptr()
+        .wrapping_add(buf.as_ptr().align_offset(leaf_size));
+    let metadata = vec![0usize; metadata_size(heap_size, leaf_size).div_ceil(8)];
+    let param = BuddyAllocParam::new_with_metadata(
+        heap,
+        heap_size,
+        leaf_size,
+        metadata.as_ptr().cast(),
+        metadata.len() * size_of::<usize>(),
+    );
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    // the whole heap is left for blocks
+    let p = allocator
+        .allocate(Layout::from_size_align(heap_size, 1).unwrap())
+        .unwrap();
+    assert_eq!(p.as_mut_ptr().cast_const(), heap);
+}
+
+#[test]
+fn test_buddy_heap_macro() {
+    let param = crate::buddy_heap!(64 * 1024, 64);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    // the buffer is aligned, the whole heap is one block
+    assert_eq!(allocator.stats().largest_free, 64 * 1024);
+    let layout = Layout::from_size_align(64 * 1024, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+}
+
+#[test]
+fn test_static_heap() {
+    const STATIC_HEAP_SIZE: usize = 64 * 1024;
+    static mut HEAP: StaticBuddyHeap<STATIC_HEAP_SIZE> =
+        unsafe { StaticBuddyHeap::new(core::ptr::addr_of_mut!(HEAP), 64) };
+    let allocator = unsafe { &*core::ptr::addr_of!(HEAP) }.alloc();
+
+    // same blocks as a heap built at runtime over an equally aligned buffer
+    let buf_layout = Layout::from_size_align(STATIC_HEAP_SIZE, STATIC_HEAP_ALIGN).unwrap();
+    let buf = unsafe { std::alloc::alloc(buf_layout) };
+    let runtime = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf, STATIC_HEAP_SIZE, 64)) };
+    let blocks = |alloc: &BuddyAlloc| {
+        let base = alloc.heap_range().start;
+        let mut blocks = Vec::new();
+        alloc.for_each_free_block(|addr, size| blocks.push((addr - base, size)));
+        blocks
+    };
+    assert_eq!(blocks(allocator), blocks(&runtime));
+    assert_eq!(allocator.available_bytes(), runtime.available_bytes());
+
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    let ptrs: Vec<_> = (0..64)
+        .map(|_| allocator.allocate(layout).unwrap().as_non_null_ptr())
+        .collect();
+    for p in ptrs {
+        assert!(allocator.heap_range().contains(&(p.as_ptr() as usize)));
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    assert_eq!(blocks(allocator), blocks(&runtime));
+    unsafe { std::alloc::dealloc(buf, buf_layout) };
+}
+
+#[test]
+fn test_scrub_free() {
+    let buf = vec![0x5au8; HEAP_SIZE];
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let live = allocator
+        .allocate(Layout::from_size_align(256, 1).unwrap())
+        .unwrap()
+        .as_mut_ptr();
+    let mut free = Vec::new();
+    allocator.for_each_free_block(|addr, size| free.push((addr, size)));
+    assert!(allocator.scrub_free() > 0);
+    for (addr, size) in free {
+        let block = unsafe { core::slice::from_raw_parts(live.with_addr(addr), size) };
+        assert!(block[16..].iter().all(|&b| b == 0));
+    }
+    let live = unsafe { core::slice::from_raw_parts(live, 256) };
+    // past the node it held while free
+    assert!(live[16..].iter().all(|&b| b == 0x5a));
+}
+
+#[test]
+fn test_no_instrumentation_overhead() {
+    // the bookkeeping fields, plus only what the enabled features add
+    struct Bare {
+        _pointers: [*mut u8; 4],
+        _words: [usize; 3],
+        _slice_size: SliceSize,
+        _deterministic2base: Option<u8>,
+        _deferred_coalescing: bool,
+        #[cfg(feature = "free-fill")]
+        _free_fill: Option<u8>,
+        #[cfg(feature = "free-fill")]
+        _clean_from: core::cell::Cell<usize>,
+        #[cfg(feature = "double-free")]
+        _double_free: crate::inspect::DoubleFreeHandler,
+        #[cfg(feature = "stats")]
+        _align_stats: crate::buddy_alloc::AlignStats,
+        #[cfg(feature = "stats")]
+        _counters: crate::inspect::Counters,
+    }
+    assert_eq!(
+        core::mem::size_of::<BuddyAlloc>(),
+        core::mem::size_of::<Bare>()
+    );
+}
+
+#[test]
+fn test_large_alignment() {
+    const LARGE_HEAP_SIZE: usize = 8 << 20;
+    let buf: Vec<u8> = Vec::with_capacity(LARGE_HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), LARGE_HEAP_SIZE, 64);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let free = allocator.stats().free_bytes;
+    // superpage aligned blocks, far larger than the leaf or the base alignment
+    for align in [64 << 10, 1 << 20, 2 << 20] {
+        let layout = Layout::from_size_align(4096, align).unwrap();
+        let ptrs: Vec<_> = (0..2)
+            .map(|_| allocator.allocate(layout).unwrap())
+            .collect();
+        for p in &ptrs {
+            assert!(p.as_mut_ptr().addr().is_multiple_of(align));
+            assert!(unsafe { allocator.alloc_size(p.as_mut_ptr()) } >= 4096);
+        }
+        allocator.validate().unwrap();
+        for p in ptrs {
+            unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+        }
+        allocator.validate().unwrap();
+        assert_eq!(allocator.stats().free_bytes, free);
+    }
+    // no heap address has this alignment
+    let layout = Layout::from_size_align(4096, 1 << (usize::BITS - 2)).unwrap();
+    assert!(allocator.allocate(layout).is_err());
+}
+
+#[test]
+fn test_coalesce() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param =
+        BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE).with_deferred_coalescing(true);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let fresh = allocator.stats();
+    let layout = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+    let mut ptrs = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        ptrs.push(p.as_non_null_ptr());
+    }
+    // every other leaf free, no buddies to merge
+    ptrs.sort();
+    for p in ptrs.iter().step_by(2) {
+        unsafe { allocator.deallocate(*p, layout) };
+    }
+    assert_eq!(allocator.coalesce(), 0);
+    for p in ptrs.iter().skip(1).step_by(2) {
+        unsafe { allocator.deallocate(*p, layout) };
+    }
+    let bytes = allocator.coalesce();
+    allocator.validate().unwrap();
+    let stats = allocator.stats();
+    assert!(bytes > 4 * LEAF_SIZE && bytes <= stats.free_bytes);
+    assert_eq!(
+        (stats.free_bytes, stats.largest_free, stats.free_blocks),
+        (fresh.free_bytes, fresh.largest_free, fresh.free_blocks)
+    );
+    assert_eq!(allocator.coalesce(), 0);
+}
+
+#[test]
+fn test_deferred_coalescing() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param =
+        BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE).with_deferred_coalescing(true);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let fresh = allocator.stats();
+    let layout = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+    let mut ptrs = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        ptrs.push(p.as_non_null_ptr());
+    }
+    for p in ptrs {
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    // nothing merged yet
+    allocator.validate().unwrap();
+    assert_eq!(allocator.stats().free_bytes, fresh.free_bytes);
+    assert_eq!(allocator.stats().largest_free, LEAF_SIZE);
+    assert_eq!(allocator.coalesce_some(3), 3);
+    allocator.validate().unwrap();
+    let mut rounds = 1;
+    while allocator.coalesce_some(100) == 100 {
+        rounds += 1;
+        allocator.validate().unwrap();
+    }
+    assert!(rounds > 10);
+    assert_eq!(allocator.coalesce_some(100), 0);
+    let stats = allocator.stats();
+    assert_eq!(
+        (stats.free_bytes, stats.largest_free, stats.free_blocks),
+        (fresh.free_bytes, fresh.largest_free, fresh.free_blocks)
+    );
+
+    // an allocation finding no large block merges first
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    let big = Layout::from_size_align(fresh.largest_free, 1).unwrap();
+    let p = allocator.allocate(big).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), big) };
+}
+
+#[test]
+fn test_assume_owned() {
     with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let free = allocator.stats().free_bytes;
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let p = allocator.allocate(layout).unwrap();
+        // only the address makes it back, as through C
+        let addr = p.as_mut_ptr().addr();
+        let p = unsafe { allocator.assume_owned(core::ptr::without_provenance(addr), layout) };
+        assert_eq!(p.as_mut_ptr().addr(), addr);
+        let p = unsafe {
+            allocator.grow(
+                p.as_non_null_ptr(),
+                layout,
+                Layout::from_size_align(200, 8).unwrap(),
+            )
+        }
+        .unwrap();
+        unsafe {
+            allocator.deallocate(
+                p.as_non_null_ptr(),
+                Layout::from_size_align(200, 8).unwrap(),
+            )
+        };
+        assert_eq!(allocator.stats().free_bytes, free);
+    });
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "not an allocated block")]
+fn test_assume_owned_free_block() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let p = allocator.allocate(layout).unwrap();
+        unsafe {
+            allocator.deallocate(p.as_non_null_ptr(), layout);
+            allocator.assume_owned(p.as_mut_ptr(), layout);
+        }
+    });
+}
+
+#[test]
+fn test_failure_reasons() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let leaf = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
         let mut ptrs = Vec::new();
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(4, 1).unwrap())
-                .unwrap(),
-        );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(5, 1).unwrap())
-                .unwrap(),
-        );
-        unsafe { allocator.deallocate(ptrs[0].cast(), Layout::from_size_align(1, 1).unwrap()) };
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(40, 1).unwrap())
-                .unwrap(),
-        );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(48, 1).unwrap())
-                .unwrap(),
-        );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(80, 1).unwrap())
-                .unwrap(),
-        );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(42, 1).unwrap())
-                .unwrap(),
+        while let Ok(p) = allocator.allocate(leaf) {
+            ptrs.push(p.as_non_null_ptr());
+        }
+        assert_eq!(allocator.allocate_largest(), Err(Error::OutOfMemory));
+        // every other leaf free, plenty of bytes but no two together
+        for p in ptrs.iter().step_by(2) {
+            unsafe { allocator.deallocate(*p, leaf) };
+        }
+        let pair = Layout::from_size_align(2 * LEAF_SIZE, 1).unwrap();
+        assert_eq!(
+            allocator.allocate_up_to(pair, 4 * LEAF_SIZE),
+            Err(Error::FragmentationLimit)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(13, 1).unwrap())
-                .unwrap(),
+        assert!(Error::FragmentationLimit.is_recoverable());
+        assert_eq!(
+            allocator.try_allocate(pair).map(|_| ()),
+            Err(Error::FragmentationLimit)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(8, 1).unwrap())
-                .unwrap(),
+        assert!(allocator.try_allocate(leaf).is_ok());
+        let huge = Layout::from_size_align(2 * HEAP_SIZE, 1).unwrap();
+        assert_eq!(
+            allocator.try_allocate(huge).map(|_| ()),
+            Err(Error::SizeUnsupported)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(24, 1).unwrap())
-                .unwrap(),
+        assert_eq!(
+            allocator.reserve_blocks(usize::MAX >> 1, 1),
+            Err(Error::SizeUnsupported)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(16, 1).unwrap())
-                .unwrap(),
+    });
+}
+
+#[test]
+fn test_page_tables_from_small_leaves() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE + 4096);
+    // a base off the page alignment, so blocks aren't page aligned on their own
+    let base = buf
+        .as_ptr()
+        .wrapping_add(buf.as_ptr().align_offset(4096) + 16);
+    let allocator = unsafe { BuddyAlloc::new(BuddyAllocParam::new(base, HEAP_SIZE, 16)) };
+    let free = allocator.stats().free_bytes;
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    let tables: Vec<_> = (0..8)
+        .map(|_| allocator.allocate(layout).unwrap())
+        .collect();
+    for table in &tables {
+        assert!(table.as_mut_ptr().addr().is_multiple_of(4096));
+    }
+    allocator.validate().unwrap();
+    for table in tables {
+        unsafe { allocator.deallocate(table.as_non_null_ptr(), layout) };
+    }
+    assert_eq!(allocator.stats().free_bytes, free);
+}
+
+#[test]
+fn test_deterministic_offsets() {
+    const MAX_ALIGN: usize = 4096;
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE + MAX_ALIGN);
+    // one operation sequence over heaps at differently aligned addresses
+    let run = |shift: usize| {
+        let param = BuddyAllocParam::new(buf.as_ptr().wrapping_add(shift), HEAP_SIZE, 16)
+            .with_deterministic_offsets(MAX_ALIGN);
+        let allocator = unsafe { BuddyAlloc::new(param) };
+        let mut offsets = Vec::new();
+        let mut ptrs = Vec::new();
+        for (i, align) in [1, 64, 16, 4096, 8, 256]
+            .into_iter()
+            .cycle()
+            .take(24)
+            .enumerate()
+        {
+            let layout = Layout::from_size_align(24 + i * 40, align).unwrap();
+            let p = allocator.allocate(layout).unwrap().as_non_null_ptr();
+            assert!(p.as_ptr().addr().is_multiple_of(align));
+            offsets.push(allocator.offset_of(p.as_ptr()));
+            ptrs.push((p, layout));
+        }
+        for (p, layout) in ptrs.into_iter().step_by(3) {
+            unsafe { allocator.deallocate(p, layout) };
+        }
+        let p = allocator
+            .allocate(Layout::from_size_align(100, 1).unwrap())
+            .unwrap();
+        offsets.push(allocator.offset_of(p.as_mut_ptr()));
+        assert_eq!(allocator.ptr_at(*offsets.last().unwrap()), p.as_mut_ptr());
+        // alignments past the deterministic one would depend on the address
+        assert!(allocator
+            .allocate(Layout::from_size_align(16, 2 * MAX_ALIGN).unwrap())
+            .is_err());
+        offsets
+    };
+    let expected = run(0);
+    for shift in [16, 48, 1024, 4080] {
+        assert_eq!(run(shift), expected);
+    }
+}
+
+#[test]
+fn test_migrate() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let mut ptrs: Vec<_> = (0..8)
+            .map(|_| allocator.allocate(layout).unwrap().as_non_null_ptr())
+            .collect();
+        ptrs.sort();
+        for &p in &ptrs[..4] {
+            unsafe { allocator.deallocate(p, layout) };
+        }
+        let mut lowest = usize::MAX;
+        allocator.for_each_free_block(|addr, size| {
+            if size >= 128 {
+                lowest = lowest.min(addr);
+            }
+        });
+        let high = ptrs[7];
+        assert!(lowest < high.as_ptr() as usize);
+        unsafe { high.as_ptr().write_bytes(0xab, layout.size()) };
+        let p = unsafe { allocator.migrate(high, layout) }.unwrap();
+        assert_eq!(p.as_mut_ptr() as usize, lowest);
+        assert_eq!(p.len(), layout.size());
+        let moved = unsafe { core::slice::from_raw_parts(p.as_mut_ptr(), layout.size()) };
+        assert!(moved.iter().all(|&b| b == 0xab));
+        allocator.validate().unwrap();
+
+        // already lowest, no block to move to, p stays valid
+        assert_eq!(
+            unsafe { allocator.migrate(p.as_non_null_ptr(), layout) },
+            Err(Error::OutOfMemory)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(1024, 1).unwrap())
-                .unwrap(),
+        assert!(moved.iter().all(|&b| b == 0xab));
+        allocator.validate().unwrap();
+
+        unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+        for &p in &ptrs[4..7] {
+            unsafe { allocator.deallocate(p, layout) };
+        }
+        allocator.validate().unwrap();
+        assert_eq!(allocator.free_regions().count(), 1);
+    });
+}
+
+#[test]
+fn test_inherent_malloc_free() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let available = allocator.available_bytes();
+        let p = allocator.malloc(100);
+        assert!(!p.is_null());
+        unsafe { p.write_bytes(1, 100) };
+        assert!(allocator.malloc(HEAP_SIZE * 2).is_null());
+        unsafe {
+            allocator.free(p);
+            allocator.free(core::ptr::null_mut());
+        }
+        assert_eq!(allocator.available_bytes(), available);
+    });
+}
+
+#[test]
+fn test_grow_shrink_in_place() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let available = allocator.available_bytes();
+        let small = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+        let large = Layout::from_size_align(LEAF_SIZE * 8, 1).unwrap();
+        let p = allocator.allocate(large).unwrap().as_non_null_ptr();
+        unsafe {
+            p.as_ptr().write(42);
+            // shrinking hands the upper halves back
+            let q = allocator.shrink(p, large, small).unwrap();
+            assert_eq!(q.as_non_null_ptr(), p);
+            assert_eq!(allocator.alloc_size(p.as_ptr()), LEAF_SIZE);
+            // the buddies are free again, p grows without moving
+            let q = allocator.grow(p, small, large).unwrap();
+            assert_eq!(q.as_non_null_ptr(), p);
+            assert_eq!(allocator.alloc_size(p.as_ptr()), LEAF_SIZE * 8);
+            // with the buddy taken, growing moves and keeps the contents
+            let q = allocator.shrink(p, large, small).unwrap();
+            assert_eq!(q.as_non_null_ptr(), p);
+            let r = allocator.allocate(small).unwrap().as_non_null_ptr();
+            assert_eq!(r.as_ptr(), p.as_ptr().add(LEAF_SIZE));
+            let q = allocator.grow(p, small, large).unwrap();
+            assert_ne!(q.as_non_null_ptr(), p);
+            assert_eq!(q.as_mut_ptr().read(), 42);
+            allocator.deallocate(q.as_non_null_ptr(), large);
+            allocator.deallocate(r, small);
+        }
+        assert_eq!(allocator.available_bytes(), available);
+    });
+}
+
+/// allocate_zeroed hands out zeroes while every allocation gets dirtied
+fn check_allocate_zeroed(allocator: &BuddyAlloc) {
+    let mut live = Vec::new();
+    let mut seed = 12345u32;
+    for i in 0..2000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let size = 1 + (seed >> 16) as usize % 3000;
+        let align = 1 << ((seed >> 8) % 8);
+        let layout = Layout::from_size_align(size, align).unwrap();
+        if let Ok(p) = allocator.allocate_zeroed(layout) {
+            assert!(unsafe { p.as_ref() }.iter().all(|&b| b == 0));
+            unsafe { p.as_mut_ptr().write_bytes(0xa5, p.len()) };
+            live.push((p, layout));
+        }
+        if i % 3 == 0 && !live.is_empty() {
+            let (p, layout) = live.swap_remove(seed as usize % live.len());
+            unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+        }
+    }
+    for (p, layout) in live {
+        unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    }
+}
+
+#[test]
+fn test_allocate_zeroed() {
+    #[cfg(feature = "free-fill")]
+    {
+        let buf = vec![0u8; HEAP_SIZE];
+        let param =
+            BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE).with_zeroed_memory(true);
+        check_allocate_zeroed(&unsafe { BuddyAlloc::new(param) });
+
+        // zero on free clears a dirty heap up front
+        let buf = vec![0xffu8; HEAP_SIZE];
+        let param =
+            BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE).with_zero_on_free(true);
+        let allocator = unsafe { BuddyAlloc::new(param) };
+        check_allocate_zeroed(&allocator);
+        let mut dirty = 0;
+        allocator.for_each_free_block(|addr, size| {
+            let block =
+                unsafe { core::slice::from_raw_parts(allocator.ptr_at(0).with_addr(addr), size) };
+            dirty += block[16..].iter().filter(|&&b| b != 0).count();
+        });
+        assert_eq!(dirty, 0);
+    }
+
+    // nothing known, everything is cleared
+    let buf = vec![0xffu8; HEAP_SIZE];
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE);
+    check_allocate_zeroed(&unsafe { BuddyAlloc::new(param) });
+}
+
+#[test]
+#[cfg(feature = "free-fill")]
+fn test_allocate_zeroed_after_merge() {
+    // merged buddies leave their nodes inside the larger block
+    let buf = vec![0u8; 64 * 1024];
+    let param = BuddyAllocParam::new(buf.as_ptr(), buf.len(), LEAF_SIZE).with_zeroed_memory(true);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    allocator.reserve_blocks(0, 64).unwrap();
+    assert!(allocator.coalesce() > 0);
+    let layout = Layout::from_size_align(4096, 1).unwrap();
+    let p = allocator.allocate_zeroed(layout).unwrap();
+    assert!(unsafe { p.as_ref() }.iter().all(|&b| b == 0));
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+
+    // and so do the merges on free
+    let buf = vec![0u8; 64 * 1024];
+    let param = BuddyAllocParam::new(buf.as_ptr(), buf.len(), LEAF_SIZE).with_zeroed_memory(true);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    allocator.reserve_blocks(0, 64).unwrap();
+    let leaf = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+    let p = allocator.allocate(leaf).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), leaf) };
+    let p = allocator.allocate_zeroed(layout).unwrap();
+    assert!(unsafe { p.as_ref() }.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_assert_layout() {
+    use BlockState::{Allocated, Free};
+    let heap_layout = Layout::from_size_align(4096, 4096).unwrap();
+    let heap = unsafe { std::alloc::alloc(heap_layout) };
+    let metadata = vec![0usize; metadata_size(4096, 64).div_ceil(size_of::<usize>())];
+    let param = BuddyAllocParam::new_with_metadata(
+        heap,
+        4096,
+        64,
+        metadata.as_ptr().cast(),
+        metadata.len() * size_of::<usize>(),
+    );
+    {
+        let allocator = unsafe { BuddyAlloc::new(param) };
+        allocator.assert_layout(&[(0, 4096, Free)]);
+        allocator
+            .allocate(Layout::from_size_align(100, 1).unwrap())
+            .unwrap();
+        allocator
+            .allocate(Layout::from_size_align(64, 1).unwrap())
+            .unwrap();
+        let expected = [
+            (0, 128, Allocated),
+            (128, 64, Allocated),
+            (192, 64, Free),
+            (256, 256, Free),
+            (512, 512, Free),
+            (1024, 1024, Free),
+            (2048, 2048, Free),
+        ];
+        allocator.assert_layout(&expected);
+        let mut wrong = expected;
+        wrong[1].2 = Free;
+        let diff = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            allocator.assert_layout(&wrong)
+        }))
+        .unwrap_err();
+        let diff = diff.downcast_ref::<String>().unwrap();
+        assert!(diff.contains("- 0x80 0x40 Free\n+ 0x80 0x40 Allocated\n"));
+        assert!(diff.contains("  0x0 0x80 Allocated\n"));
+    }
+    unsafe { std::alloc::dealloc(heap, heap_layout) };
+}
+
+#[test]
+#[cfg(feature = "double-free")]
+fn test_double_free() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE)
+        .with_double_free_handler(|p| REPORTED.store(p as usize, Ordering::Relaxed));
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let free = allocator.stats().free_bytes;
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_non_null_ptr();
+    unsafe { allocator.deallocate(p, layout) };
+    unsafe { allocator.deallocate(p, layout) };
+    assert_eq!(REPORTED.load(Ordering::Relaxed), p.as_ptr() as usize);
+    allocator.validate().unwrap();
+    assert_eq!(allocator.stats().free_bytes, free);
+}
+
+#[test]
+#[cfg(feature = "free-fill")]
+fn test_poison_on_free() {
+    use crate::fill::FREE_FILL;
+    for deferred in [false, true] {
+        let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+        let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE)
+            .with_poison_on_free(true)
+            .with_deferred_coalescing(deferred);
+        let allocator = unsafe { BuddyAlloc::new(param) };
+        check_allocate_zeroed(&allocator);
+        allocator.coalesce_some(usize::MAX);
+        let p = allocator.malloc(4096);
+        unsafe {
+            p.write_bytes(1, 4096);
+            assert!(allocator.resize_in_place(p, 100));
+            assert!(allocator.resize_in_place(p, 2048));
+            allocator.free(p);
+        }
+        allocator.for_each_free_block(|addr, size| {
+            let block =
+                unsafe { core::slice::from_raw_parts(allocator.ptr_at(0).with_addr(addr), size) };
+            assert!(block[16..].iter().all(|&b| b == FREE_FILL));
+        });
+    }
+
+    // a write after free is caught by the next allocation of the block
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE).with_poison_on_free(true);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let p = allocator.malloc(256);
+    unsafe {
+        allocator.free(p);
+        p.add(100).write(0);
+    }
+    let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| allocator.malloc(256)));
+    let msg = caught.unwrap_err();
+    let msg = msg.downcast_ref::<String>().unwrap();
+    assert!(msg.ends_with(&format!("at {:#x}", p as usize + 100)));
+}
+
+#[test]
+fn test_from_static() {
+    let buf = Box::leak(vec![core::mem::MaybeUninit::uninit(); 64 * 1024].into_boxed_slice());
+    let allocator = BuddyAlloc::from_static(buf, 64);
+    let free = allocator.stats().free_bytes;
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    assert!(allocator.owns(p.as_mut_ptr()));
+    assert!(!allocator.owns(&free as *const usize as *const u8));
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    assert_eq!(allocator.stats().free_bytes, free);
+}
+
+#[test]
+fn test_allocate_at() {
+    with_allocator(HEAP_SIZE, LEAF_SIZE, |allocator| {
+        let free = allocator.stats().free_bytes;
+        let base = allocator.heap_range().start;
+        let layout = Layout::from_size_align(3 * LEAF_SIZE, 8).unwrap();
+        let addr = base + 40 * LEAF_SIZE;
+        let p = allocator.allocate_at(addr, layout).unwrap();
+        assert_eq!(p.as_mut_ptr() as usize, addr);
+        allocator.validate().unwrap();
+        // taken, misaligned and out of the heap
+        let leaf = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+        assert_eq!(
+            allocator.allocate_at(addr + LEAF_SIZE, leaf),
+            Err(Error::OutOfMemory)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(104, 1).unwrap())
-                .unwrap(),
+        assert_eq!(
+            allocator.allocate_at(addr + 1, layout),
+            Err(Error::AlignmentUnsupported)
         );
-        ptrs.push(
-            allocator
-                .allocate(Layout::from_size_align(8, 1).unwrap())
-                .unwrap(),
+        assert_eq!(
+            allocator.allocate_at(base + HEAP_SIZE, leaf),
+            Err(Error::SizeUnsupported)
         );
-        for ptr in ptrs.into_iter().skip(1) {
-            unsafe { allocator.deallocate(ptr.cast(), Layout::from_size_align(1, 1).unwrap()) };
+        // its neighbours stay free
+        let q = allocator.allocate_at(addr + 4 * LEAF_SIZE, leaf).unwrap();
+        unsafe {
+            allocator.deallocate(p.as_non_null_ptr(), layout);
+            allocator.deallocate(q.as_non_null_ptr(), leaf);
         }
+        allocator.validate().unwrap();
+        assert_eq!(allocator.stats().free_bytes, free);
+        let p = allocator.allocate_at(addr, layout).unwrap();
+        unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
     });
 }
 
 #[test]
-fn test_alignment() {
-    let data = [0u8; 4 << 16];
-    println!("Buffer data: {:p}", data.as_ptr());
-    let allocator = unsafe { BuddyAlloc::new(BuddyAllocParam::new(data.as_ptr(), 4 << 16, 4096)) };
-    let p = allocator
-        .allocate(Layout::from_size_align(4, 1).unwrap())
-        .unwrap();
-    println!("Allocated pointer: {:p}", p);
-    // FIXME what does it test??
+fn test_from_ranges() {
+    const GAP: u8 = 0x5a;
+    let buf = vec![GAP; 256 * 1024];
+    let base = buf.as_ptr().expose_provenance();
+    let ranges = [
+        (base + 160 * 1024, 96 * 1024),
+        (base, 64 * 1024),
+        (base + 96 * 1024, 40 * 1024),
+        (base + 100 * 1024, 8 * 1024),
+    ];
+    let allocator = unsafe { BuddyAlloc::from_ranges(ranges, 64) };
+    allocator.validate().unwrap();
+    let stats = allocator.stats();
+    assert!(stats.free_bytes > 180 * 1024 && stats.free_bytes <= 200 * 1024);
+    let gaps = [(64 * 1024)..(96 * 1024), (136 * 1024)..(160 * 1024)];
+    let in_gap = |addr: usize, len: usize| {
+        gaps.iter()
+            .any(|gap| addr - base < gap.end && gap.start < addr - base + len)
+    };
+    let layout = Layout::from_size_align(1000, 8).unwrap();
+    let mut ps = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        assert!(!in_gap(p.as_mut_ptr() as usize, layout.size()));
+        unsafe { p.as_mut_ptr().write_bytes(0, layout.size()) };
+        ps.push(p.as_non_null_ptr());
+    }
+    assert!(ps.len() > 150);
+    for p in ps {
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    allocator.validate().unwrap();
+    assert_eq!(allocator.stats().free_bytes, stats.free_bytes);
+    for gap in gaps {
+        assert!(buf[gap].iter().all(|&b| b == GAP));
+    }
+}
+
+#[test]
+fn test_add_region() {
+    let buf = vec![0u128; 64 * 1024 / 16];
+    let base = buf.as_ptr().expose_provenance();
+    // the heap spans all of buf, only its first 16K usable for now
+    let allocator =
+        unsafe { BuddyAlloc::from_ranges([(base, 16 * 1024), (base + 64 * 1024, 0)], 64) };
+    let free = allocator.stats().free_bytes;
+    assert!(free < 16 * 1024);
+    let donated = (base + 16 * 1024 + 10)..(base + 56 * 1024 + 10);
+    unsafe {
+        let addr = core::ptr::with_exposed_provenance(donated.start);
+        allocator.add_region(addr, donated.len()).unwrap();
+    }
+    allocator.validate().unwrap();
+    // the leaves cut by the range ends stay reserved
+    assert_eq!(allocator.stats().free_bytes, free + 40 * 1024 - 64);
+    let layout = Layout::from_size_align(64, 64).unwrap();
+    let mut ps = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        let addr = p.as_mut_ptr() as usize;
+        assert!(addr < base + 16 * 1024 || donated.contains(&addr));
+        ps.push(p.as_non_null_ptr());
+    }
+    for p in ps {
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    allocator.validate().unwrap();
+    assert_eq!(allocator.stats().free_bytes, free + 40 * 1024 - 64);
+    let past = core::ptr::with_exposed_provenance(base + 60 * 1024);
+    assert_eq!(
+        unsafe { allocator.add_region(past, 8 * 1024) },
+        Err(Error::SizeUnsupported)
+    );
+}
+
+#[test]
+fn test_from_ranges_unaligned_gap() {
+    // ranges ending and starting inside a leaf
+    let buf = vec![0u128; 16464 / 16];
+    let base = buf.as_ptr().expose_provenance();
+    let ranges = [(base, 100), (base + 180, 16284)];
+    let allocator = unsafe { BuddyAlloc::from_ranges(ranges, 16) };
+    allocator.validate().unwrap();
+    let layout = Layout::from_size_align(16, 16).unwrap();
+    let mut ps = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        let offset = p.as_mut_ptr() as usize - base;
+        assert!(offset + layout.size() <= 100 || offset >= 180, "{offset}");
+        ps.push(p.as_non_null_ptr());
+    }
+    assert!(!ps.is_empty());
+    for p in ps {
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    allocator.validate().unwrap();
 }
@@ -0,0 +1,122 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam, MIN_LEAF_SIZE_ALIGN},
+        inspect::HeapInspect,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    A(usize, usize),
+    F(usize),
+}
+
+use Op::{A, F};
+
+struct Case {
+    name: &'static str,
+    heap_size: usize,
+    leaf_size: usize,
+    ops: &'static [Op],
+}
+
+// Operation sequences that once broke BuddyAlloc. To lock in a new bug add
+// the heap it ran on and its operations: `A(size, align)` allocates into the
+// next slot, `F(slot)` frees the block of an earlier `A`, counting from 0.
+const CASES: &[Case] = &[
+    Case {
+        name: "free_bug",
+        heap_size: 1024 * 1024,
+        leaf_size: MIN_LEAF_SIZE_ALIGN,
+        ops: &[A(32, 1), F(0), A(40961, 1), A(1381, 1), F(1), F(2)],
+    },
+    Case {
+        name: "example_bug",
+        heap_size: 1024 * 1024,
+        leaf_size: MIN_LEAF_SIZE_ALIGN,
+        ops: &[
+            A(4, 1),
+            A(5, 1),
+            F(0),
+            A(40, 1),
+            A(48, 1),
+            A(80, 1),
+            A(42, 1),
+            A(13, 1),
+            A(8, 1),
+            A(24, 1),
+            A(16, 1),
+            A(1024, 1),
+            A(104, 1),
+            A(8, 1),
+            F(1),
+            F(2),
+            F(3),
+            F(4),
+            F(5),
+            F(6),
+            F(7),
+            F(8),
+            F(9),
+            F(10),
+            F(11),
+            F(12),
+        ],
+    },
+];
+
+/// Replay `case`, checking the heap after every step and that every byte
+/// is free again once the live blocks are released.
+fn run(case: &Case) {
+    let buf: Vec<u8> = Vec::with_capacity(case.heap_size);
+    let param = BuddyAllocParam::new(buf.as_ptr(), case.heap_size, case.leaf_size);
+    let allocator = unsafe { BuddyAlloc::new(param) };
+    let free = allocator.stats().free_bytes;
+    let mut slots: Vec<Option<(NonNull<u8>, Layout)>> = Vec::new();
+    for (step, &op) in case.ops.iter().enumerate() {
+        match op {
+            A(size, align) => {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let p = allocator
+                    .allocate(layout)
+                    .unwrap_or_else(|_| panic!("{} step {step}: {op:?} failed", case.name));
+                let slot = slots.len() as u8;
+                unsafe { p.as_mut_ptr().write_bytes(slot, size) };
+                slots.push(Some((p.as_non_null_ptr(), layout)));
+            }
+            F(slot) => {
+                let (p, layout) = slots[slot]
+                    .take()
+                    .unwrap_or_else(|| panic!("{} step {step}: slot {slot} not live", case.name));
+                let block = unsafe { core::slice::from_raw_parts(p.as_ptr(), layout.size()) };
+                assert!(
+                    block.iter().all(|&b| b == slot as u8),
+                    "{} step {step}: slot {slot} overwritten",
+                    case.name
+                );
+                unsafe { allocator.deallocate(p, layout) };
+            }
+        }
+        if let Err(e) = allocator.validate() {
+            panic!(
+                "{} step {step}: {op:?} corrupted the heap at {:#x}",
+                case.name, e.addr
+            );
+        }
+    }
+    for (p, layout) in slots.into_iter().flatten() {
+        unsafe { allocator.deallocate(p, layout) };
+    }
+    assert_eq!(allocator.stats().free_bytes, free, "{} leaked", case.name);
+}
+
+#[test]
+fn test_corpus() {
+    for case in CASES {
+        run(case);
+    }
+}
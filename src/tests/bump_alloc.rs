@@ -0,0 +1,76 @@
+use {
+    crate::bump_alloc::{BumpAlloc, BumpAllocParam},
+    core::alloc::{Allocator, Layout},
+};
+
+fn with_allocator<F: FnOnce(BumpAlloc)>(f: F, buf: &[u8]) {
+    let allocator = unsafe { BumpAlloc::new(BumpAllocParam::new(buf.as_ptr(), buf.len())) };
+    f(allocator);
+}
+
+#[test]
+fn test_basic_malloc() {
+    let buf = [0u8; 4096];
+    with_allocator(
+        |allocator| {
+            let p = allocator
+                .allocate(Layout::from_size_align(3, 1).unwrap())
+                .unwrap();
+            unsafe { p.as_mut_ptr().write(42) };
+            let q = allocator
+                .allocate(Layout::from_size_align(8, 8).unwrap())
+                .unwrap();
+            assert_eq!(q.as_mut_ptr() as usize % 8, 0);
+            assert!(q.as_mut_ptr() as usize > p.as_mut_ptr() as usize);
+            assert_eq!(allocator.live_allocations(), 2);
+        },
+        &buf,
+    );
+}
+
+#[test]
+fn test_reset() {
+    let buf = [0u8; 4096];
+    with_allocator(
+        |allocator| {
+            let layout = Layout::from_size_align(1024, 1).unwrap();
+            while allocator.allocate(layout).is_ok() {}
+            assert!(allocator.available_bytes() < 1024);
+            unsafe { allocator.reset() };
+            assert_eq!(allocator.available_bytes(), buf.len());
+            assert_eq!(allocator.live_allocations(), 0);
+            assert!(allocator.allocate(layout).is_ok());
+        },
+        &buf,
+    );
+}
+
+#[test]
+fn test_scope_rewinds_on_panic() {
+    let buf = [0u8; 4096];
+    with_allocator(
+        |allocator| {
+            let layout = Layout::from_size_align(64, 8).unwrap();
+            allocator.allocate(layout).unwrap();
+            let used = allocator.used_bytes();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+                allocator.scope(|alloc| {
+                    alloc.allocate(layout).unwrap();
+                    panic!("boom");
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(allocator.used_bytes(), used);
+            assert_eq!(allocator.live_allocations(), 1);
+            let n = unsafe {
+                allocator.scope(|alloc| {
+                    alloc.allocate(layout).unwrap();
+                    alloc.live_allocations()
+                })
+            };
+            assert_eq!(n, 2);
+            assert_eq!(allocator.used_bytes(), used);
+        },
+        &buf,
+    );
+}
@@ -0,0 +1,38 @@
+use crate::{
+    buddy_alloc::BuddyAllocParam,
+    c_api::{calloc, free, malloc, realloc, MALLOC_ALIGN},
+    global_buddy::GlobalBuddyAlloc,
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_c_api() {
+    let buf = vec![0xaau8; HEAP_SIZE];
+    let heap: GlobalBuddyAlloc =
+        GlobalBuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64));
+    unsafe {
+        let p = malloc(&heap, 100);
+        assert!(!p.is_null());
+        assert_eq!(p.align_offset(MALLOC_ALIGN), 0);
+        p.write_bytes(7, 100);
+
+        // in place first, then moved with the contents
+        let p = realloc(&heap, p, 120);
+        let q = malloc(&heap, 64);
+        let r = realloc(&heap, p, 4096);
+        assert!(!r.is_null());
+        assert!((0..100).all(|i| r.add(i).read() == 7));
+        assert!(realloc(&heap, r, HEAP_SIZE * 2).is_null());
+
+        let z = calloc(&heap, 16, 32);
+        assert!((0..512).all(|i| z.add(i).read() == 0));
+        assert!(calloc(&heap, usize::MAX, 2).is_null());
+
+        assert!(realloc(&heap, z, 0).is_null());
+        free(&heap, q);
+        free(&heap, r);
+        free(&heap, core::ptr::null_mut());
+        assert!(!realloc(&heap, core::ptr::null_mut(), 10).is_null());
+    }
+}
@@ -0,0 +1,38 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        child_heap::ChildHeap,
+        inspect::HeapInspect,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_child_isolated() {
+    let buf = vec![0u8; 256 * 1024];
+    let parent = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), buf.len(), 16)) };
+    let parent_free = parent.stats().free_bytes;
+    let child = ChildHeap::new(&parent, 16 * 1024, 16).unwrap();
+    assert!(parent.stats().free_bytes < parent_free);
+    let layout = Layout::from_size_align(1024, 8).unwrap();
+    let mut blocks = Vec::new();
+    while let Ok(p) = child.allocate(layout) {
+        assert!(child.owns(p.as_mut_ptr()));
+        blocks.push(p.as_non_null_ptr());
+    }
+    // the child ran out while the parent still has room
+    assert_eq!(child.failures(), 1);
+    assert!(blocks.len() < 16);
+    assert_eq!(child.used_bytes(), blocks.len() * 1024);
+    assert!(parent
+        .allocate(layout)
+        .is_ok_and(|p| !child.owns(p.as_mut_ptr())));
+    for p in blocks {
+        unsafe { child.deallocate(p, layout) };
+    }
+    assert_eq!(child.used_bytes(), 0);
+    child.validate().unwrap();
+    drop(child);
+    // everything but the parent allocation above went back
+    assert_eq!(parent.stats().free_bytes, parent_free - 1024);
+}
@@ -0,0 +1,63 @@
+use {
+    crate::{cortex_m_heap::CortexMHeap, error::Error},
+    core::alloc::{GlobalAlloc, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_uninit_heap() {
+    let heap = CortexMHeap::empty();
+    let layout = Layout::from_size_align(16, 4).unwrap();
+    assert!(unsafe { heap.alloc(layout) }.is_null());
+    assert_eq!(heap.free(), 0);
+    assert_eq!(heap.try_allocate(layout), Err(Error::NotInitialized));
+}
+
+#[test]
+fn test_alloc() {
+    let mut buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let heap = CortexMHeap::empty();
+    let start = buf.as_mut_ptr();
+    unsafe { heap.init_range(start, start.add(HEAP_SIZE)) };
+    let free = heap.free();
+    assert!(free > 0);
+    let layout = Layout::from_size_align(100, 4).unwrap();
+    let p = unsafe { heap.alloc(layout) };
+    assert!(!p.is_null());
+    assert!(heap.free() < free);
+    unsafe { heap.dealloc(p, layout) };
+    assert_eq!(heap.free(), free);
+}
+
+#[test]
+fn test_init_uninit() {
+    let heap = CortexMHeap::empty();
+    heap.init_uninit(unsafe { crate::uninit_region!(HEAP_SIZE) });
+    let layout = Layout::from_size_align(1000, 8).unwrap();
+    let p = unsafe { heap.alloc(layout) };
+    assert!(!p.is_null());
+    unsafe { heap.dealloc(p, layout) };
+}
+
+#[test]
+fn test_threads() {
+    let mut buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let heap = CortexMHeap::empty();
+    let start = buf.as_mut_ptr();
+    unsafe { heap.init_range(start, start.add(HEAP_SIZE)) };
+    let free = heap.free();
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                let layout = Layout::from_size_align(256, 8).unwrap();
+                for _ in 0..100 {
+                    let p = unsafe { heap.alloc(layout) };
+                    assert!(!p.is_null());
+                    unsafe { heap.dealloc(p, layout) };
+                }
+            });
+        }
+    });
+    assert_eq!(heap.free(), free);
+}
@@ -0,0 +1,36 @@
+use crate::{
+    buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    descriptors::DescriptorArray,
+    inspect::HeapInspect,
+};
+
+#[allow(dead_code)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+}
+
+#[test]
+fn test_descriptor_stride() {
+    let buf = vec![0xffu8; 64 * 1024];
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), buf.len(), 16)) };
+    let free = heap.stats().free_bytes;
+    {
+        let ring = DescriptorArray::<Descriptor, _>::new(&heap, 8, 64).unwrap();
+        assert_eq!(ring.len(), 8);
+        assert_eq!(ring.stride(), 64);
+        assert_eq!(ring.get(0), Some(ring.as_ptr()));
+        assert!(ring.get(8).is_none());
+        for (i, d) in ring.iter().enumerate() {
+            assert!(d.as_ptr().addr().is_multiple_of(64));
+            assert_eq!(d.as_ptr().addr() - ring.as_ptr().as_ptr().addr(), i * 64);
+            let d = unsafe { d.as_ref() };
+            assert_eq!((d.addr, d.len, d.flags), (0, 0, 0));
+        }
+        // a small alignment still keeps the descriptor's own
+        let packed = DescriptorArray::<Descriptor, _>::new(&heap, 3, 1).unwrap();
+        assert_eq!(packed.stride(), 16);
+    }
+    assert_eq!(heap.stats().free_bytes, free);
+}
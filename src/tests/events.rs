@@ -0,0 +1,48 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        events::{EventRing, Op},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 16 * 1024;
+
+#[test]
+fn test_event_ring() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator: EventRing<_, 3> = EventRing::new(unsafe { BuddyAlloc::new(param) });
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    allocator.set_tag(7);
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    assert!(allocator
+        .allocate(Layout::from_size_align(HEAP_SIZE, 1).unwrap())
+        .is_err());
+    let _ = allocator.allocate(layout).unwrap();
+    // the first alloc fell out of the ring
+    let ops: Vec<_> = allocator.events().map(|e| (e.op, e.tag)).collect();
+    assert_eq!(ops, [(Op::Free, 7), (Op::Oom, 7), (Op::Alloc, 7)]);
+    let mut report = String::new();
+    allocator.report(&mut report).unwrap();
+    assert_eq!(report.lines().count(), 3);
+    assert!(report.starts_with("Free size 100"));
+}
+
+#[test]
+fn test_sampled_events() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator: EventRing<_, 16> = EventRing::new(unsafe { BuddyAlloc::new(param) });
+    allocator.set_sample_period(2);
+    let layout = Layout::from_size_align(64, 1).unwrap();
+    for _ in 0..4 {
+        allocator.allocate(layout).unwrap();
+    }
+    assert!(allocator
+        .allocate(Layout::from_size_align(HEAP_SIZE, 1).unwrap())
+        .is_err());
+    let ops: Vec<_> = allocator.events().map(|e| e.op).collect();
+    assert_eq!(ops, [Op::Alloc, Op::Alloc, Op::Oom]);
+}
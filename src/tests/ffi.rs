@@ -0,0 +1,28 @@
+use crate::ffi::{buddy_free, buddy_init, buddy_malloc, buddy_realloc_in_place, buddy_usable_size};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_realloc_in_place() {
+    let mut buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    unsafe {
+        let heap = buddy_init(buf.as_mut_ptr(), HEAP_SIZE, 64);
+        assert!(!heap.is_null());
+        let p = buddy_malloc(heap, 1024);
+        assert!(!p.is_null());
+        assert!(buddy_realloc_in_place(heap, p, 10));
+        assert_eq!(buddy_usable_size(heap, p), 64);
+        assert!(buddy_realloc_in_place(heap, p, 1000));
+        assert_eq!(buddy_usable_size(heap, p), 1024);
+        assert!(!buddy_realloc_in_place(heap, p, HEAP_SIZE));
+        buddy_free(heap, p);
+        buddy_free(heap, core::ptr::null_mut());
+    }
+}
+
+#[test]
+fn test_init_too_small() {
+    let mut buf = [0u8; 8];
+    let heap = unsafe { buddy_init(buf.as_mut_ptr(), buf.len(), 64) };
+    assert!(heap.is_null());
+}
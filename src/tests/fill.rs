@@ -0,0 +1,39 @@
+use {
+    crate::{
+        fill::{FillAlloc, FillPatterns, ALLOC_FILL, FREE_FILL},
+        freelist_alloc::{FreelistAlloc, FreelistAllocParam},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_fill_patterns() {
+    let buf = [0u8; 4096];
+    let inner = unsafe { FreelistAlloc::new(FreelistAllocParam::new(buf.as_ptr(), buf.len())) };
+    let allocator = FillAlloc::new(inner, FillPatterns::default());
+    let layout = Layout::from_size_align(48, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    let bytes = unsafe { core::slice::from_raw_parts(p.as_mut_ptr(), 48) };
+    assert!(bytes.iter().all(|&b| b == ALLOC_FILL));
+    unsafe { allocator.deallocate(p.cast(), layout) };
+    // the free list node lives in the first bytes, the rest is filled
+    let bytes = unsafe { core::slice::from_raw_parts(p.as_mut_ptr().add(16), 32) };
+    assert!(bytes.iter().all(|&b| b == FREE_FILL));
+    let z = allocator.allocate_zeroed(layout).unwrap();
+    assert!(unsafe { z.as_ref() }.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_fill_disabled() {
+    let buf = [7u8; 4096];
+    let inner = unsafe { FreelistAlloc::new(FreelistAllocParam::new(buf.as_ptr(), buf.len())) };
+    let patterns = FillPatterns {
+        on_alloc: None,
+        on_free: None,
+    };
+    let allocator = FillAlloc::new(inner, patterns);
+    let p = allocator
+        .allocate(Layout::from_size_align(64, 1).unwrap())
+        .unwrap();
+    assert_eq!(unsafe { *p.as_mut_ptr().add(20) }, 7);
+}
@@ -0,0 +1,51 @@
+use {
+    crate::{bump_alloc::BumpAllocParam, frame_arena::FrameArena},
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_swap() {
+    let front = [0u8; 1024];
+    let back = [0u8; 1024];
+    let arena = unsafe {
+        FrameArena::new(
+            BumpAllocParam::new(front.as_ptr(), front.len()),
+            BumpAllocParam::new(back.as_ptr(), back.len()),
+        )
+    };
+    let layout = Layout::from_size_align(512, 1).unwrap();
+    let p = arena.allocate(layout).unwrap();
+    let q = arena.allocate(layout).unwrap();
+    assert!(arena.allocate(layout).is_err());
+    // the next frame gets the other arena, p and q stay valid
+    unsafe { arena.swap() };
+    assert!(arena.allocate(layout).is_ok());
+    assert_eq!(arena.previous().live_allocations(), 2);
+    unsafe {
+        arena.deallocate(p.cast(), layout);
+        arena.deallocate(q.cast(), layout);
+    }
+    unsafe { arena.swap() };
+    assert_eq!(arena.current().used_bytes(), 0);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "retired frame still has live allocations")]
+fn test_swap_with_live_allocations() {
+    let front = [0u8; 1024];
+    let back = [0u8; 1024];
+    let arena = unsafe {
+        FrameArena::new(
+            BumpAllocParam::new(front.as_ptr(), front.len()),
+            BumpAllocParam::new(back.as_ptr(), back.len()),
+        )
+    };
+    arena
+        .allocate(Layout::from_size_align(8, 1).unwrap())
+        .unwrap();
+    unsafe {
+        arena.swap();
+        arena.swap();
+    }
+}
@@ -1,5 +1,9 @@
 use {
-    crate::freelist_alloc::{FreelistAlloc, FreelistAllocParam, BLOCK_SIZE},
+    crate::{
+        error::Error,
+        freelist_alloc::{FreelistAlloc, FreelistAllocParam, BLOCK_SIZE, GUARD_SIZE},
+        SliceSize,
+    },
     core::alloc::{Allocator, Layout},
 };
 
@@ -140,3 +144,232 @@ fn test_free_bug() {
         &buf,
     );
 }
+
+#[test]
+fn test_guard_word() {
+    let buf = [0u8; 4096];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len()).with_guard(true);
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    assert_eq!(allocator.max_alloc_size(), BLOCK_SIZE - GUARD_SIZE);
+    assert!(allocator
+        .allocate(Layout::from_size_align(BLOCK_SIZE, 1).unwrap())
+        .is_err());
+    let layout = Layout::from_size_align(BLOCK_SIZE - GUARD_SIZE, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe {
+        p.as_mut_ptr().write_bytes(1, layout.size());
+        allocator.deallocate(p.cast(), layout);
+    }
+}
+
+#[test]
+#[should_panic(expected = "guard word overwritten")]
+fn test_guard_word_overrun() {
+    let buf = [0u8; 4096];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len()).with_guard(true);
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    let layout = Layout::from_size_align(16, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe {
+        // overrun into the guard word
+        p.as_mut_ptr().write_bytes(1, BLOCK_SIZE);
+        allocator.deallocate(p.cast(), layout);
+    }
+}
+
+#[test]
+fn test_slice_size() {
+    let buf = [0u8; 4096];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len()).with_slice_size(SliceSize::Block);
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    let p = allocator
+        .allocate(Layout::from_size_align(10, 1).unwrap())
+        .unwrap();
+    assert_eq!(p.len(), BLOCK_SIZE);
+}
+
+#[test]
+fn test_scrub_free() {
+    let buf = [0x5au8; 4096];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len());
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    let live = allocator
+        .allocate(Layout::from_size_align(BLOCK_SIZE, 1).unwrap())
+        .unwrap();
+    assert_eq!(allocator.scrub_free(), 63 * (BLOCK_SIZE - 16));
+    let live = unsafe { live.as_ref() };
+    // past the node it held while free
+    assert!(live[16..].iter().all(|&b| b == 0x5a));
+}
+
+#[test]
+fn test_no_instrumentation_overhead() {
+    // the bookkeeping fields, plus only what the enabled features add
+    struct Bare {
+        _words: [usize; 2],
+        _guard: bool,
+        _slice_size: SliceSize,
+        _free: core::cell::RefCell<*mut u8>,
+        #[cfg(feature = "free-fill")]
+        _poison: bool,
+        #[cfg(feature = "stats")]
+        _counters: crate::inspect::Counters,
+        #[cfg(feature = "double-free")]
+        _double_free: crate::inspect::DoubleFreeHandler,
+    }
+    assert_eq!(
+        core::mem::size_of::<FreelistAlloc>(),
+        core::mem::size_of::<Bare>()
+    );
+}
+
+#[test]
+fn test_inherent_malloc_free() {
+    let buf = [0u8; 4096];
+    with_allocator(
+        |allocator| {
+            let p = allocator.malloc(allocator.max_alloc_size());
+            assert!(!p.is_null());
+            assert!(allocator.malloc(allocator.max_alloc_size() + 1).is_null());
+            unsafe {
+                allocator.free(p);
+                allocator.free(core::ptr::null_mut());
+            }
+            assert_eq!(allocator.malloc(16), p);
+        },
+        &buf,
+    );
+}
+
+#[test]
+fn test_alignment() {
+    #[repr(align(64))]
+    struct Aligned([u8; 4096]);
+    let buf = Aligned([0u8; 4096]);
+    with_allocator(
+        |allocator| {
+            let p = allocator
+                .allocate(Layout::from_size_align(64, 64).unwrap())
+                .unwrap();
+            assert_eq!(p.as_mut_ptr().align_offset(64), 0);
+            assert!(allocator
+                .allocate(Layout::from_size_align(8, 128).unwrap())
+                .is_err());
+        },
+        &buf.0,
+    );
+    // an unaligned region has no block to offer
+    with_allocator(
+        |allocator| {
+            let layout = Layout::from_size_align(8, 64).unwrap();
+            assert!(allocator.allocate(layout).is_err());
+            let p = allocator
+                .allocate(Layout::from_size_align(8, 8).unwrap())
+                .unwrap();
+            assert_eq!(p.as_mut_ptr().align_offset(8), 0);
+        },
+        &buf.0[8..4096 - 56],
+    );
+}
+
+#[test]
+#[cfg(feature = "double-free")]
+fn test_double_free() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+    let buf = [0u8; 4 * BLOCK_SIZE];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len())
+        .with_double_free_handler(|p| REPORTED.store(p as usize, Ordering::Relaxed));
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    let layout = Layout::from_size_align(16, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_non_null_ptr();
+    unsafe { allocator.deallocate(p, layout) };
+    unsafe { allocator.deallocate(p, layout) };
+    assert_eq!(REPORTED.load(Ordering::Relaxed), p.as_ptr() as usize);
+    // the free list still holds every block once
+    let ptrs: Vec<_> = (0..4)
+        .map(|_| allocator.allocate(layout).unwrap().as_mut_ptr())
+        .collect();
+    assert!(allocator.allocate(layout).is_err());
+    assert!(ptrs
+        .iter()
+        .enumerate()
+        .all(|(i, a)| ptrs[..i].iter().all(|b| a != b)));
+}
+
+#[test]
+#[cfg(feature = "free-fill")]
+fn test_poison_on_free() {
+    use crate::fill::FREE_FILL;
+    let buf = [0u8; 4 * BLOCK_SIZE];
+    let param = FreelistAllocParam::new(buf.as_ptr(), buf.len())
+        .with_poison_on_free(true)
+        .with_guard(true);
+    let allocator = unsafe { FreelistAlloc::new(param) };
+    let layout = Layout::from_size_align(32, 1).unwrap();
+    let ptrs: Vec<_> = (0..4)
+        .map(|_| allocator.allocate(layout).unwrap().as_mut_ptr())
+        .collect();
+    for &p in &ptrs {
+        unsafe {
+            p.write_bytes(1, 32);
+            allocator.free(p);
+        }
+    }
+    let p = allocator.malloc(32);
+    let block = unsafe { core::slice::from_raw_parts(p, BLOCK_SIZE - GUARD_SIZE) };
+    assert!(block[16..].iter().all(|&b| b == FREE_FILL));
+
+    // every free block written after its free
+    unsafe { allocator.free(p) };
+    for &p in &ptrs {
+        unsafe { p.add(20).write(0) };
+    }
+    let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| allocator.malloc(32)));
+    let msg = caught.unwrap_err();
+    let msg = msg.downcast_ref::<String>().unwrap();
+    assert!(ptrs
+        .iter()
+        .any(|&p| msg.ends_with(&format!("at {:#x}", p as usize + 20))));
+}
+
+#[test]
+fn test_from_static() {
+    let buf = Box::leak(vec![core::mem::MaybeUninit::uninit(); BLOCK_SIZE * 8].into_boxed_slice());
+    let allocator = FreelistAlloc::from_static(buf);
+    let layout = Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+
+    // a misaligned buffer loses the partial blocks at both ends
+    let buf = Box::leak(vec![core::mem::MaybeUninit::uninit(); BLOCK_SIZE * 9].into_boxed_slice());
+    let pad = buf.as_ptr().align_offset(BLOCK_SIZE);
+    let allocator = FreelistAlloc::from_static(&mut buf[pad + 1..pad + 1 + BLOCK_SIZE * 8]);
+    let layout = Layout::from_size_align(BLOCK_SIZE, BLOCK_SIZE).unwrap();
+    let mut blocks = 0;
+    while let Ok(p) = allocator.allocate(layout) {
+        assert!(p.as_mut_ptr().addr().is_multiple_of(BLOCK_SIZE));
+        blocks += 1;
+    }
+    assert_eq!(blocks, 7);
+}
+
+#[test]
+fn test_try_allocate() {
+    let buf = vec![0u8; BLOCK_SIZE * 2];
+    with_allocator(
+        |allocator| {
+            let too_big = Layout::from_size_align(BLOCK_SIZE + 1, 1).unwrap();
+            assert_eq!(allocator.try_allocate(too_big), Err(Error::SizeUnsupported));
+            let too_aligned = Layout::from_size_align(8, BLOCK_SIZE * 2).unwrap();
+            assert_eq!(
+                allocator.try_allocate(too_aligned),
+                Err(Error::AlignmentUnsupported)
+            );
+            let layout = Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
+            while allocator.try_allocate(layout).is_ok() {}
+            assert_eq!(allocator.try_allocate(layout), Err(Error::OutOfMemory));
+        },
+        &buf,
+    );
+}
@@ -0,0 +1,25 @@
+use {
+    crate::{
+        freelist_alloc::{FreelistAllocParam, BLOCK_SIZE},
+        freelist_global::FreelistGlobalAlloc,
+        inspect::HeapInspect,
+    },
+    core::alloc::{GlobalAlloc, Layout},
+};
+
+#[test]
+fn test_freelist_global_alloc() {
+    let buf = [0u8; 4 * BLOCK_SIZE];
+    let allocator = FreelistGlobalAlloc::new(FreelistAllocParam::new(buf.as_ptr(), buf.len()));
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let ptrs: Vec<_> = (0..4).map(|_| unsafe { allocator.alloc(small) }).collect();
+    assert!(ptrs.iter().all(|p| !p.is_null()));
+    assert!(unsafe { allocator.alloc(small) }.is_null());
+    for p in ptrs {
+        unsafe { allocator.dealloc(p, small) };
+    }
+    // larger than a block, there's no buddy heap to fall back on
+    let large = Layout::from_size_align(BLOCK_SIZE + 1, 1).unwrap();
+    assert!(unsafe { allocator.alloc(large) }.is_null());
+    assert_eq!(allocator.with(|heap| heap.stats().free_blocks), 4);
+}
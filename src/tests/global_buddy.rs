@@ -0,0 +1,56 @@
+use {
+    crate::{buddy_alloc::BuddyAllocParam, global_buddy::GlobalBuddyAlloc, inspect::HeapInspect},
+    core::alloc::{Allocator, GlobalAlloc, Layout},
+};
+
+const HEAP_SIZE: usize = 256 * 1024;
+
+static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+static ALLOCATOR: GlobalBuddyAlloc = GlobalBuddyAlloc::new(BuddyAllocParam::new(
+    core::ptr::addr_of!(HEAP).cast(),
+    HEAP_SIZE,
+    64,
+));
+
+#[test]
+fn test_global_buddy_threads() {
+    let free = ALLOCATOR.with(|heap| heap.stats().free_bytes);
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            s.spawn(move || {
+                let layout = Layout::from_size_align(64 + t * 48, 16).unwrap();
+                for _ in 0..200 {
+                    unsafe {
+                        let p = ALLOCATOR.alloc(layout);
+                        assert!(!p.is_null());
+                        p.write_bytes(t as u8, layout.size());
+                        let q = ALLOCATOR.realloc(p, layout, layout.size() * 3);
+                        assert!((0..layout.size()).all(|i| q.add(i).read() == t as u8));
+                        ALLOCATOR
+                            .dealloc(q, Layout::from_size_align(layout.size() * 3, 16).unwrap());
+                    }
+                }
+            });
+        }
+    });
+    ALLOCATOR.with(|heap| heap.validate()).unwrap();
+    assert_eq!(ALLOCATOR.with(|heap| heap.stats().free_bytes), free);
+}
+
+crate::static_heap!(SMALL, 16 * 1024);
+
+#[test]
+fn test_static_heap() {
+    let layout = Layout::from_size_align(1024, 256).unwrap();
+    let p = SMALL.allocate(layout).unwrap();
+    assert_eq!(p.as_mut_ptr().align_offset(256), 0);
+    SMALL.with(|heap| heap.validate()).unwrap();
+    unsafe { SMALL.deallocate(p.as_non_null_ptr(), layout) };
+
+    // every byte of the buffer is usable
+    let layout = Layout::from_size_align(16 * 1024, 1).unwrap();
+    let p = SMALL.allocate(layout).unwrap();
+    assert_eq!(SMALL.with(|heap| heap.available_bytes()), 16 * 1024);
+    unsafe { SMALL.deallocate(p.as_non_null_ptr(), layout) };
+}
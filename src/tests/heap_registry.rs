@@ -0,0 +1,151 @@
+use {
+    crate::{
+        buddy_alloc::{
+            atomic::{AtomicBuddyAlloc, AtomicBuddyAllocParam},
+            BuddyAlloc, BuddyAllocParam,
+        },
+        heap_registry::{AllocHint, HeapRange, HeapRegistry, Lifetime, Locality},
+    },
+    core::{
+        alloc::{Allocator, GlobalAlloc, Layout},
+        ptr::NonNull,
+    },
+};
+
+const HEAP_SIZE: usize = 16 * 1024;
+
+fn with_registry<F: FnOnce(HeapRegistry<BuddyAlloc, 2>)>(f: F) {
+    let bank0: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let bank1: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let heaps = [&bank0, &bank1]
+        .map(|buf| unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64)) });
+    f(HeapRegistry::new(heaps));
+}
+
+#[test]
+fn test_spill_to_next_heap() {
+    with_registry(|registry| {
+        let layout = Layout::from_size_align(4096, 1).unwrap();
+        let mut ps = Vec::new();
+        loop {
+            let p = unsafe { registry.alloc(layout) };
+            if p.is_null() {
+                break;
+            }
+            ps.push(p);
+        }
+        let [bank0, bank1] = registry.heaps();
+        assert!(ps
+            .iter()
+            .any(|&p| bank0.heap_range().contains(&(p as usize))));
+        assert!(ps
+            .iter()
+            .any(|&p| bank1.heap_range().contains(&(p as usize))));
+        for p in ps {
+            assert!(registry.owner(p).is_some());
+            unsafe { registry.dealloc(p, layout) };
+        }
+        // everything went back to its own heap
+        assert!(!unsafe { registry.alloc(layout) }.is_null());
+    });
+}
+
+#[test]
+fn test_foreign_pointer() {
+    with_registry(|registry| {
+        let foreign = [0u8; 16];
+        assert!(registry.owner(foreign.as_ptr()).is_none());
+    });
+}
+
+#[test]
+fn test_alloc_hint() {
+    with_registry(|registry| {
+        let layout = Layout::from_size_align(64, 1).unwrap();
+        let [bank0, bank1] = registry.heaps();
+        let in_bank = |bank: &BuddyAlloc, p: *mut u8| bank.heap_range().contains(&(p as usize));
+        registry.set_reclaimable(0, true);
+        let hint = |region, locality, lifetime| AllocHint {
+            region,
+            locality,
+            lifetime,
+        };
+        let alloc = |hint| {
+            registry
+                .allocate_with_hint(layout, hint)
+                .unwrap()
+                .as_mut_ptr()
+        };
+
+        let unknown = alloc(AllocHint::default());
+        assert!(in_bank(bank1, unknown));
+        let short = alloc(hint(None, Locality::Any, Lifetime::Short));
+        assert!(in_bank(bank0, short));
+        let near = alloc(hint(
+            None,
+            Locality::Near(short as usize),
+            Lifetime::Unknown,
+        ));
+        assert!(in_bank(bank0, near));
+        // long lived allocations never go to the reclaimable heap
+        let long = alloc(hint(Some(0), Locality::Any, Lifetime::Long));
+        assert!(in_bank(bank1, long));
+    });
+}
+
+#[test]
+fn test_spill_and_migrate() {
+    with_registry(|registry| {
+        let layout = Layout::from_size_align(4096, 1).unwrap();
+        // fill the fast heap, the next allocation spills
+        let mut fast = Vec::new();
+        let slow = loop {
+            let p = registry.allocate(layout).unwrap().as_mut_ptr();
+            if registry.owner_index(p) == Some(1) {
+                break p;
+            }
+            fast.push(p);
+        };
+        unsafe {
+            slow.write_bytes(0x42, layout.size());
+            assert!(registry
+                .migrate(NonNull::new_unchecked(slow), layout, 0)
+                .is_err());
+            registry.deallocate(NonNull::new_unchecked(fast.pop().unwrap()), layout);
+            let p = registry
+                .migrate(NonNull::new_unchecked(slow), layout, 0)
+                .unwrap()
+                .as_mut_ptr();
+            assert_eq!(registry.owner_index(p), Some(0));
+            assert!((0..layout.size()).all(|i| *p.add(i) == 0x42));
+        }
+    });
+}
+
+#[test]
+fn test_threads() {
+    let banks = [vec![0u8; HEAP_SIZE], vec![0u8; HEAP_SIZE]];
+    let heaps = banks.each_ref().map(|buf| unsafe {
+        AtomicBuddyAlloc::new(AtomicBuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64))
+    });
+    let registry = HeapRegistry::new(heaps);
+    registry.set_reclaimable(1, true);
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                let layout = Layout::from_size_align(1024, 8).unwrap();
+                for _ in 0..100 {
+                    let p = registry.allocate(layout).unwrap();
+                    unsafe { registry.deallocate(p.as_non_null_ptr(), layout) };
+                }
+            });
+        }
+    });
+    let free: usize = registry.heaps().iter().map(|heap| heap.free_bytes()).sum();
+    let available: usize = registry
+        .heaps()
+        .iter()
+        .map(|heap| heap.available_bytes())
+        .sum();
+    assert_eq!(free, available);
+}
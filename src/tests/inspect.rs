@@ -0,0 +1,86 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        freelist_alloc::{FreelistAlloc, FreelistAllocParam, BLOCK_SIZE},
+        inspect::{Corruption, HeapInspect},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+/// written once over any heap
+fn check_heap<H: Allocator + HeapInspect>(heap: &H, size: usize) {
+    let layout = Layout::from_size_align(size, 1).unwrap();
+    let before = heap.stats();
+    assert!(before.free_bytes > 0 && before.free_bytes <= before.total_bytes);
+    heap.validate().unwrap();
+
+    let ptrs: Vec<_> = (0..8).map(|_| heap.allocate(layout).unwrap()).collect();
+    for p in &ptrs {
+        assert!(heap.owns(p.as_mut_ptr()));
+        assert!(unsafe { heap.alloc_size(p.as_mut_ptr()) } >= size);
+    }
+    assert!(heap.stats().free_bytes < before.free_bytes);
+    heap.validate().unwrap();
+
+    for p in ptrs {
+        unsafe { heap.deallocate(p.as_non_null_ptr(), layout) };
+    }
+    assert_eq!(heap.stats().free_bytes, before.free_bytes);
+    heap.validate().unwrap();
+    let outside = 0u8;
+    assert!(!heap.owns(&outside));
+}
+
+#[test]
+fn test_buddy_inspect() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 16)) };
+    check_heap(&heap, 100);
+    let stats = heap.stats();
+    assert_eq!(stats.total_bytes, heap.available_bytes());
+    assert!(stats.largest_free <= stats.free_bytes && stats.free_blocks > 0);
+}
+
+#[test]
+fn test_freelist_inspect() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = unsafe { FreelistAlloc::new(FreelistAllocParam::new(buf.as_ptr(), HEAP_SIZE)) };
+    check_heap(&heap, BLOCK_SIZE);
+    let stats = heap.stats();
+    assert_eq!(stats.free_blocks, HEAP_SIZE / BLOCK_SIZE);
+    assert_eq!(stats.largest_free, BLOCK_SIZE);
+}
+
+#[test]
+fn test_validate_corruption() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 16)) };
+    let layout = Layout::from_size_align(16, 1).unwrap();
+    let p = heap.allocate(layout).unwrap().as_mut_ptr();
+    let _buddy = heap.allocate(layout).unwrap();
+    unsafe { heap.deallocate(core::ptr::NonNull::new_unchecked(p), layout) };
+    heap.validate().unwrap();
+    // a use after free clobbering the free list node
+    unsafe { p.cast::<usize>().add(1).write(1) };
+    assert_eq!(heap.validate(), Err(Corruption { addr: p as usize }));
+}
+
+#[test]
+fn test_freelist_validate_corruption() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let heap = unsafe { FreelistAlloc::new(FreelistAllocParam::new(buf.as_ptr(), HEAP_SIZE)) };
+    let layout = Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
+    let p = heap.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { heap.deallocate(core::ptr::NonNull::new_unchecked(p), layout) };
+    heap.validate().unwrap();
+    // a use after free pointing the node's next outside the heap,
+    // caught before it is followed
+    let outside = buf.as_ptr_range().end as usize + 4096;
+    unsafe { p.cast::<usize>().write(outside) };
+    assert_eq!(heap.validate(), Err(Corruption { addr: p as usize }));
+    // or inside it, off a block boundary
+    unsafe { p.cast::<usize>().write(buf.as_ptr() as usize + 1) };
+    assert_eq!(heap.validate(), Err(Corruption { addr: p as usize }));
+}
@@ -0,0 +1,32 @@
+use {
+    crate::{buddy_alloc::BuddyAllocParam, kernel_alloc::KernelAlloc},
+    core::{alloc::Layout, ptr::NonNull},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_realloc() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = unsafe { KernelAlloc::new(param) };
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let large = Layout::from_size_align(512, 8).unwrap();
+    unsafe {
+        let p = allocator.realloc(None, small, small).unwrap().as_mut_ptr();
+        p.write_bytes(0x5a, small.size());
+        let q = allocator
+            .realloc(Some(NonNull::new_unchecked(p)), large, small)
+            .unwrap();
+        assert_eq!(q.len(), large.size());
+        let q = q.as_mut_ptr();
+        for i in 0..small.size() {
+            assert_eq!(*q.add(i), 0x5a);
+        }
+        let zero = Layout::from_size_align(0, 8).unwrap();
+        let r = allocator
+            .realloc(Some(NonNull::new_unchecked(q)), zero, large)
+            .unwrap();
+        assert_eq!(r.len(), 0);
+    }
+}
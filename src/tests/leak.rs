@@ -0,0 +1,45 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        leak::{LeakTracker, LiveAllocation},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_leak_report() {
+    let buf: Vec<u8> = Vec::with_capacity(64 * 1024);
+    let inner = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), 64 * 1024, 64)) };
+    let tracker: LeakTracker<_, 2> = LeakTracker::new(inner);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let a = tracker.allocate(layout).unwrap();
+    tracker.set_tag(7);
+    let b = tracker.allocate(layout).unwrap();
+    // the table is full
+    let c = tracker.allocate(layout).unwrap();
+    assert_eq!(tracker.untracked(), 1);
+    unsafe { tracker.deallocate(a.as_non_null_ptr(), layout) };
+
+    let mut live = Vec::new();
+    tracker.for_each_live(|record| live.push(record));
+    let b_addr = b.as_mut_ptr() as usize;
+    assert_eq!(
+        live,
+        [LiveAllocation {
+            addr: b_addr,
+            size: 100,
+            tag: 7
+        }]
+    );
+    let mut report = String::new();
+    tracker.report(&mut report).unwrap();
+    assert_eq!(
+        report,
+        format!("{b_addr:#x}: 100 bytes, tag 7\n1 live allocations, 100 bytes, 1 untracked\n")
+    );
+    unsafe {
+        tracker.deallocate(b.as_non_null_ptr(), layout);
+        tracker.deallocate(c.as_non_null_ptr(), layout);
+    }
+    assert_eq!(tracker.untracked(), 0);
+}
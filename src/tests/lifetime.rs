@@ -0,0 +1,53 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        lifetime::LifetimeTracker,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        sync::atomic::{AtomicU64, Ordering},
+    },
+};
+
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+fn clock() -> u64 {
+    NOW.load(Ordering::Relaxed)
+}
+
+#[test]
+fn test_lifetime_histogram() {
+    let buf: Vec<u8> = Vec::with_capacity(64 * 1024);
+    let param = BuddyAllocParam::new(buf.as_ptr(), 64 * 1024, 16);
+    let tracker = LifetimeTracker::new(unsafe { BuddyAlloc::new(param) }, clock);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+
+    let short = tracker.allocate(layout).unwrap();
+    assert_eq!(short.as_mut_ptr() as usize % 8, 0);
+    let long = tracker.allocate(layout).unwrap();
+    NOW.store(3, Ordering::Relaxed);
+    unsafe { tracker.deallocate(short.cast(), layout) };
+    NOW.store(1000, Ordering::Relaxed);
+    unsafe { tracker.deallocate(long.cast(), layout) };
+
+    let histogram = tracker.histogram();
+    assert_eq!(histogram[1], 1);
+    assert_eq!(histogram[9], 1);
+    let mut report = String::new();
+    tracker.report(&mut report).unwrap();
+    assert_eq!(report, "2..4 ticks: 1\n512..1024 ticks: 1\n");
+}
+
+#[test]
+fn test_sampled_lifetimes() {
+    let buf: Vec<u8> = Vec::with_capacity(64 * 1024);
+    let param = BuddyAllocParam::new(buf.as_ptr(), 64 * 1024, 16);
+    let tracker = LifetimeTracker::new(unsafe { BuddyAlloc::new(param) }, clock);
+    tracker.set_sample_period(4);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let blocks: Vec<_> = (0..8).map(|_| tracker.allocate(layout).unwrap()).collect();
+    for p in blocks {
+        unsafe { tracker.deallocate(p.cast(), layout) };
+    }
+    assert_eq!(tracker.histogram().iter().sum::<usize>(), 2);
+}
@@ -0,0 +1,144 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::HeapInspect,
+        locked::{Locked, PreemptibleLocked, RawMutex, RawSpinlock},
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+const HEAP_SIZE: usize = 256 * 1024;
+
+#[test]
+fn test_locked_threads() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator: Locked<BuddyAlloc> = Locked::new(unsafe { BuddyAlloc::new(param) });
+    let free = allocator.with(|heap| heap.free_regions().map(|(_, len)| len).sum::<usize>());
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let allocator = &allocator;
+            s.spawn(move || {
+                let layout = Layout::from_size_align(64 + t * 32, 8).unwrap();
+                for _ in 0..200 {
+                    let p = allocator.allocate(layout).unwrap();
+                    unsafe { p.as_mut_ptr().write_bytes(t as u8, layout.size()) };
+                    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+                }
+            });
+        }
+    });
+    let after = allocator.with(|heap| heap.free_regions().map(|(_, len)| len).sum::<usize>());
+    assert_eq!(after, free);
+}
+
+static LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+/// stands in for an RTOS mutex
+struct CountingMutex(RawSpinlock);
+
+unsafe impl RawMutex for CountingMutex {
+    const INIT: Self = CountingMutex(RawSpinlock::INIT);
+
+    fn lock(&self) {
+        self.0.lock();
+        LOCKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn try_lock(&self) -> bool {
+        self.0.try_lock()
+    }
+
+    unsafe fn unlock(&self) {
+        self.0.unlock();
+    }
+}
+
+#[test]
+fn test_custom_mutex() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator: Locked<_, CountingMutex> = Locked::new(unsafe { BuddyAlloc::new(param) });
+    let layout = Layout::from_size_align(64, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    let available = allocator.with(|heap| heap.available_bytes());
+    assert!(available > 0);
+    assert_eq!(LOCKS.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_preemptible() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64).with_deferred_coalescing(true);
+    let allocator: PreemptibleLocked = PreemptibleLocked::new(unsafe { BuddyAlloc::new(param) }, 4);
+    let leaf = Layout::from_size_align(64, 1).unwrap();
+    let mut ptrs = Vec::new();
+    while let Ok(p) = allocator.allocate(leaf) {
+        ptrs.push(p.as_non_null_ptr());
+    }
+    for p in ptrs {
+        unsafe { allocator.deallocate(p, leaf) };
+    }
+    // takes many rounds of 4 merges
+    let large = Layout::from_size_align(HEAP_SIZE / 4, 1).unwrap();
+    let p = allocator.allocate(large).unwrap();
+    allocator.with(|heap| heap.validate()).unwrap();
+    // merges what the allocation left
+    allocator.coalesce();
+    assert_eq!(allocator.coalesce(), 0);
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), large) };
+    assert!(allocator
+        .allocate(Layout::from_size_align(HEAP_SIZE, 1).unwrap())
+        .is_err());
+}
+
+/// a mutex from outside the crate, only implementing lock_api's trait
+#[cfg(feature = "lock_api")]
+struct LockApiMutex(core::sync::atomic::AtomicBool);
+
+#[cfg(feature = "lock_api")]
+unsafe impl lock_api::RawMutex for LockApiMutex {
+    const INIT: Self = LockApiMutex(core::sync::atomic::AtomicBool::new(false));
+
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock(&self) {
+        while !lock_api::RawMutex::try_lock(self) {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.0
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+#[test]
+#[cfg(feature = "lock_api")]
+fn test_lock_api_mutex() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator: Locked<_, LockApiMutex> = Locked::new(unsafe { BuddyAlloc::new(param) });
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                let layout = Layout::from_size_align(64, 8).unwrap();
+                for _ in 0..100 {
+                    let p = allocator.allocate(layout).unwrap();
+                    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+                }
+            });
+        }
+    });
+    allocator.with(|heap| heap.validate()).unwrap();
+}
@@ -0,0 +1,36 @@
+use {
+    crate::{
+        buddy_alloc::BuddyAllocParam, freelist_alloc::FreelistAllocParam,
+        locked_alloc::ThreadsafeAlloc,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_threads_share_heap() {
+    let freelist_buf = vec![0u8; 64 * 1024];
+    let buddy_buf = vec![0u8; 1024 * 1024];
+    let allocator = ThreadsafeAlloc::new(
+        FreelistAllocParam::new(freelist_buf.as_ptr(), freelist_buf.len()),
+        BuddyAllocParam::new(buddy_buf.as_ptr(), buddy_buf.len(), 16),
+    );
+    let available = allocator.with(|inner| inner.scrub_free());
+    std::thread::scope(|s| {
+        for t in 0..4u8 {
+            let allocator = &allocator;
+            s.spawn(move || {
+                for i in 0..1000 {
+                    let layout = Layout::from_size_align(8 + (i % 200), 8).unwrap();
+                    let p = allocator.allocate(layout).unwrap();
+                    unsafe {
+                        p.as_mut_ptr().write_bytes(t, layout.size());
+                        assert!(p.as_ref().iter().all(|&b| b == t));
+                        allocator.deallocate(p.as_non_null_ptr(), layout);
+                    }
+                }
+            });
+        }
+    });
+    // everything went back
+    assert_eq!(allocator.with(|inner| inner.scrub_free()), available);
+}
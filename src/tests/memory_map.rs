@@ -0,0 +1,78 @@
+use {
+    crate::{error::Error, inspect::HeapInspect, memory_map::MultiRegionAlloc},
+    core::alloc::{Allocator, Layout},
+};
+
+const MAP_SIZE: usize = 256 * 1024;
+
+#[test]
+fn test_memory_map() {
+    let buf = vec![0u8; MAP_SIZE];
+    let base = buf.as_ptr().expose_provenance();
+    let map = [
+        // out of order, overlapping, with a hole in the middle
+        (base + 128 * 1024, 128 * 1024, true),
+        (base, 160 * 1024, true),
+        (base + 64 * 1024, 16 * 1024, false),
+        // leaving 64 bytes between them, too small for a heap
+        (base + 96 * 1024, 64, false),
+        (base + 96 * 1024 + 128, 64, false),
+    ];
+    let alloc: MultiRegionAlloc<4> = unsafe { MultiRegionAlloc::from_memory_map(map, 64) };
+    assert_eq!(alloc.heaps().count(), 3);
+
+    let hole = (base + 64 * 1024)..(base + 80 * 1024);
+    let layout = Layout::from_size_align(1024, 1).unwrap();
+    let mut ps = Vec::new();
+    while let Ok(p) = alloc.allocate(layout) {
+        let addr = p.as_mut_ptr() as usize;
+        assert!(!hole.contains(&addr) && (base..(base + MAP_SIZE)).contains(&addr));
+        ps.push(p);
+    }
+    assert!(ps.len() > 200);
+    let stats = alloc.stats();
+    let sum = alloc.heaps().map(|heap| heap.stats().total_bytes).sum();
+    assert_eq!(stats.total_bytes, sum);
+    assert!(stats.free_bytes < layout.size() * 3);
+    for p in ps {
+        assert!(alloc.owner(p.as_mut_ptr()).is_some());
+        unsafe { alloc.deallocate(p.as_non_null_ptr(), layout) };
+    }
+    assert_eq!(alloc.stats().used_bytes, 0);
+}
+
+#[test]
+fn test_add_region() {
+    let buf = vec![0u8; MAP_SIZE];
+    let (head, tail) = buf.split_at(MAP_SIZE / 2);
+    let alloc: MultiRegionAlloc<2> = MultiRegionAlloc::new();
+    assert_eq!(alloc.heaps().count(), 0);
+    let layout = Layout::from_size_align(64 * 1024, 1).unwrap();
+    assert!(alloc.allocate(layout).is_err());
+
+    unsafe {
+        assert_eq!(
+            alloc.add_region(head.as_ptr(), 64, 64),
+            Err(Error::SizeUnsupported)
+        );
+        alloc.add_region(head.as_ptr(), head.len(), 64).unwrap();
+        let p = alloc.allocate(layout).unwrap();
+        assert!(alloc.allocate(layout).is_err());
+
+        // the second region isn't contiguous with the first
+        alloc
+            .add_region(tail[1024..].as_ptr(), 100 * 1024, 64)
+            .unwrap();
+        let q = alloc.allocate(layout).unwrap();
+        assert!(core::ptr::eq(
+            alloc.owner(q.as_mut_ptr()).unwrap(),
+            alloc.heaps().nth(1).unwrap()
+        ));
+        assert_eq!(
+            alloc.add_region(tail.as_ptr(), 1024, 64),
+            Err(Error::QuotaExceeded)
+        );
+        alloc.deallocate(p.as_non_null_ptr(), layout);
+        alloc.deallocate(q.as_non_null_ptr(), layout);
+    }
+}
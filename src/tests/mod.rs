@@ -1,2 +1,58 @@
+mod atomic_buddy_alloc;
+mod borrowed;
 mod buddy_alloc;
+mod buddy_corpus;
+mod bump_alloc;
+#[cfg(feature = "c-api")]
+mod c_api;
+mod child_heap;
+#[cfg(feature = "cortex-m")]
+mod cortex_m_heap;
+mod descriptors;
+#[cfg(feature = "events")]
+mod events;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fill;
+mod frame_arena;
 mod freelist_alloc;
+mod freelist_global;
+mod global_buddy;
+mod heap_registry;
+mod inspect;
+#[cfg(feature = "kernel")]
+mod kernel_alloc;
+#[cfg(feature = "heavy-debug")]
+mod leak;
+#[cfg(feature = "heavy-debug")]
+mod lifetime;
+mod locked;
+mod locked_alloc;
+mod memory_map;
+#[cfg(feature = "mte")]
+mod mte;
+mod non_threadsafe_alloc;
+mod object_pool;
+mod persist;
+mod pin_table;
+mod rc_alloc;
+mod redzone;
+mod reentry;
+mod region_table;
+mod remote_free;
+mod rt_pool;
+mod sampling;
+mod scratch;
+mod seal;
+mod shared_alloc;
+mod size_header;
+mod slab_alloc;
+mod slab_color;
+mod small_alloc;
+mod snapshot;
+mod task_accounting;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+mod tlsf_alloc;
+#[cfg(any(unix, windows))]
+mod vm_alloc;
@@ -0,0 +1,23 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        mte::{MteAlloc, GRANULE_SIZE},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+// tags are only applied on aarch64, this checks the granule bookkeeping
+#[test]
+#[cfg(not(target_arch = "aarch64"))]
+fn test_granule_rounding() {
+    let buf: Vec<u8> = Vec::with_capacity(64 * 1024);
+    let param = BuddyAllocParam::new(buf.as_ptr(), 64 * 1024, 16);
+    let allocator = MteAlloc::new(unsafe { BuddyAlloc::new(param) });
+    let available_bytes = allocator.inner().available_bytes();
+    let layout = Layout::from_size_align(5, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    assert_eq!(p.len(), 5);
+    assert_eq!(p.as_mut_ptr() as usize % GRANULE_SIZE, 0);
+    unsafe { allocator.deallocate(p.cast(), layout) };
+    assert_eq!(allocator.inner().available_bytes(), available_bytes);
+}
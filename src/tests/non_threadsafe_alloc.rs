@@ -0,0 +1,152 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAllocParam, MIN_LEAF_SIZE_ALIGN},
+        freelist_alloc::{FreelistAllocParam, BLOCK_SIZE},
+        NonThreadsafeAlloc,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+#[test]
+fn test_refill() {
+    let freelist_buf = [0u8; BLOCK_SIZE];
+    let buddy_buf = vec![0u8; 4096];
+    let allocator = NonThreadsafeAlloc::new(
+        FreelistAllocParam::new(freelist_buf.as_ptr(), freelist_buf.len()),
+        BuddyAllocParam::new(buddy_buf.as_ptr(), buddy_buf.len(), 16),
+    );
+    let buddy_range = buddy_buf.as_ptr_range();
+    let layout = Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    assert!(freelist_buf
+        .as_ptr_range()
+        .contains(&p.as_ptr().cast_const().cast()));
+
+    // refilled blocks come from the buddy heap but are handed out by the pool
+    allocator.refill(BLOCK_SIZE, 2).unwrap();
+    allocator.validate().unwrap();
+    let q = allocator.allocate(layout).unwrap();
+    let r = allocator.allocate(layout).unwrap();
+    assert!(buddy_range.contains(&q.as_ptr().cast_const().cast()));
+    assert!(buddy_range.contains(&r.as_ptr().cast_const().cast()));
+    unsafe {
+        allocator.deallocate(q.as_non_null_ptr(), layout);
+        allocator.deallocate(r.as_non_null_ptr(), layout);
+        allocator.deallocate(p.as_non_null_ptr(), layout);
+    }
+
+    // no pool serves larger sizes
+    assert!(allocator.refill(BLOCK_SIZE + 1, 1).is_err());
+    // the buddy heap runs out
+    assert!(allocator.refill(BLOCK_SIZE, 4096 / BLOCK_SIZE).is_err());
+}
+
+#[test]
+fn test_stats() {
+    let freelist_buf = [0u8; BLOCK_SIZE];
+    let buddy_buf = vec![0u8; 4096];
+    let allocator = NonThreadsafeAlloc::new(
+        FreelistAllocParam::new(freelist_buf.as_ptr(), freelist_buf.len()),
+        BuddyAllocParam::new(buddy_buf.as_ptr(), buddy_buf.len(), 16),
+    );
+    let empty = allocator.stats();
+    assert_eq!(empty.used_bytes, empty.total_bytes - empty.free_bytes);
+
+    // one block from the pool, the next from the buddy heap
+    let layout = Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    let q = allocator.allocate(layout).unwrap();
+    assert!(allocator.owns(p.as_mut_ptr()) && allocator.owns(q.as_mut_ptr()));
+    assert!(!allocator.owns(&layout as *const Layout as *const u8));
+    let stats = allocator.stats();
+    assert_eq!(stats.used_bytes, empty.used_bytes + 2 * BLOCK_SIZE);
+    assert_eq!(allocator.pool_stats().free_bytes, 0);
+    assert_eq!(
+        allocator.buddy_stats().used_bytes,
+        stats.used_bytes - BLOCK_SIZE
+    );
+    assert_eq!(
+        allocator.pool_overflows(),
+        if cfg!(feature = "stats") { 1 } else { 0 }
+    );
+    assert!(allocator
+        .allocate(Layout::from_size_align(8192, 1).unwrap())
+        .is_err());
+    unsafe {
+        allocator.deallocate(q.as_non_null_ptr(), layout);
+        allocator.deallocate(p.as_non_null_ptr(), layout);
+    }
+    let stats = allocator.stats();
+    assert_eq!(stats.used_bytes, empty.used_bytes);
+    if cfg!(feature = "stats") {
+        assert_eq!(stats.peak_used, empty.used_bytes + 2 * BLOCK_SIZE);
+        assert_eq!((stats.allocs, stats.failed_allocs, stats.frees), (2, 1, 2));
+    } else {
+        assert_eq!((stats.peak_used, stats.allocs, stats.frees), (0, 0, 0));
+    }
+}
+
+#[test]
+fn test_from_buffer() {
+    let buf = vec![0usize; 64 * 1024 / core::mem::size_of::<usize>()];
+    let range = buf.as_ptr_range();
+    let allocator = NonThreadsafeAlloc::from_buffer(range.start.cast(), 64 * 1024, 25);
+    allocator.init();
+    let stats = allocator.stats();
+    assert!(stats.total_bytes > 60 * 1024);
+    let small = Layout::from_size_align(BLOCK_SIZE, 8).unwrap();
+    let large = Layout::from_size_align(8 * 1024, 8).unwrap();
+    let ps =
+        [small, large, small, large].map(|layout| (allocator.allocate(layout).unwrap(), layout));
+    for (p, layout) in ps {
+        let p = p.as_mut_ptr();
+        assert!(allocator.owns(p));
+        assert!((range.start.cast::<u8>()..range.end.cast()).contains(&p.cast_const()));
+        unsafe { allocator.deallocate(NonNull::new(p).unwrap(), layout) };
+    }
+    assert_eq!(allocator.stats().used_bytes, stats.used_bytes);
+}
+
+#[test]
+fn test_lazy_init() {
+    let freelist_buf = [0u8; BLOCK_SIZE];
+    let buddy_buf = vec![0u8; 4096];
+    let allocator = NonThreadsafeAlloc::new(
+        FreelistAllocParam::new(freelist_buf.as_ptr(), freelist_buf.len()),
+        BuddyAllocParam::new(buddy_buf.as_ptr(), buddy_buf.len(), 16),
+    );
+    // both heaps get built on first use, init after that changes nothing
+    let layout = Layout::from_size_align(8, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    allocator.init();
+    assert!(allocator.owns(p.as_mut_ptr()));
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    allocator.validate().unwrap();
+}
+
+#[test]
+fn test_from_buffer_misaligned() {
+    let buf = vec![0u8; 64 * 1024 + 2 * BLOCK_SIZE];
+    let pad = buf.as_ptr().align_offset(BLOCK_SIZE) + 8;
+    let base = buf[pad..].as_ptr();
+    let allocator = NonThreadsafeAlloc::from_buffer(base, 64 * 1024, 25);
+    allocator.init();
+    let pool_len = 64 * 1024 / 4;
+    // the pool loses the block the padding cuts into
+    assert_eq!(allocator.pool_stats().total_bytes, pool_len - BLOCK_SIZE);
+    let small = Layout::from_size_align(16, BLOCK_SIZE).unwrap();
+    let p = allocator.allocate(small).unwrap().as_mut_ptr();
+    assert!(p.addr().is_multiple_of(BLOCK_SIZE));
+    assert!(p.addr() < base.addr() + pool_len);
+    assert_eq!(allocator.pool_overflows(), 0);
+    let leaf = Layout::from_size_align(BLOCK_SIZE + 1, MIN_LEAF_SIZE_ALIGN).unwrap();
+    let q = allocator.allocate(leaf).unwrap().as_mut_ptr();
+    assert!(q.addr() >= base.addr() + pool_len);
+    unsafe {
+        allocator.deallocate(NonNull::new(q).unwrap(), leaf);
+        allocator.deallocate(NonNull::new(p).unwrap(), small);
+    }
+}
@@ -0,0 +1,62 @@
+use {
+    crate::{
+        freelist_alloc::{FreelistAlloc, FreelistAllocParam, BLOCK_SIZE},
+        inspect::HeapInspect,
+        object_pool::ObjectPool,
+    },
+    std::{cell::Cell, rc::Rc},
+};
+
+#[repr(align(64))]
+struct Region([u8; 8 * BLOCK_SIZE]);
+
+struct Sensor {
+    id: u32,
+    samples: [u16; 8],
+    dropped: Rc<Cell<usize>>,
+}
+
+impl Drop for Sensor {
+    fn drop(&mut self) {
+        self.dropped.set(self.dropped.get() + 1);
+    }
+}
+
+#[test]
+fn test_pool_boxes() {
+    let region = Region([0; 8 * BLOCK_SIZE]);
+    let freelist =
+        unsafe { FreelistAlloc::new(FreelistAllocParam::new(region.0.as_ptr(), region.0.len())) };
+    let pool = ObjectPool::new(&freelist);
+    let dropped = Rc::new(Cell::new(0));
+    let mut sensors: Vec<_> = (0..8)
+        .map(|id| {
+            pool.alloc(Sensor {
+                id,
+                samples: [0; 8],
+                dropped: dropped.clone(),
+            })
+            .ok()
+            .unwrap()
+        })
+        .collect();
+    sensors[3].samples[0] = 7;
+    assert!(sensors.iter().enumerate().all(|(i, s)| s.id == i as u32));
+    assert_eq!(sensors[3].samples[0], 7);
+    // the pool is full, the value comes back
+    let spare = pool
+        .alloc(Sensor {
+            id: 8,
+            samples: [0; 8],
+            dropped: dropped.clone(),
+        })
+        .err()
+        .unwrap();
+    assert_eq!(spare.id, 8);
+    drop(spare);
+    sensors.truncate(2);
+    assert_eq!(dropped.get(), 7);
+    assert_eq!(freelist.stats().free_blocks, 6);
+    drop(sensors);
+    assert_eq!(freelist.stats().free_blocks, 8);
+}
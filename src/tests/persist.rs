@@ -0,0 +1,84 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::HeapInspect,
+        persist::{self, ImageError, IMAGE_VERSION},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+fn free_blocks(heap: &BuddyAlloc) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    heap.for_each_free_block(|addr, size| blocks.push((addr, size)));
+    blocks.sort();
+    blocks
+}
+
+#[test]
+fn test_save_load() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 16);
+    let heap = unsafe { BuddyAlloc::new(param) };
+    let layout = Layout::from_size_align(200, 8).unwrap();
+    let ptrs: Vec<_> = (0..10u8)
+        .map(|i| {
+            let p = heap.allocate(layout).unwrap().as_mut_ptr();
+            unsafe { p.write_bytes(i, layout.size()) };
+            p
+        })
+        .collect();
+    for &p in ptrs.iter().step_by(2) {
+        unsafe { heap.deallocate(core::ptr::NonNull::new_unchecked(p), layout) };
+    }
+    let mut image = vec![0u8; persist::image_size(&heap)];
+    assert_eq!(persist::save(&heap, &mut image), Ok(image.len()));
+    let expected = free_blocks(&heap);
+
+    // a reset later, the heap memory kept its contents
+    let heap = unsafe { persist::load(param, &image) }.unwrap();
+    assert_eq!(free_blocks(&heap), expected);
+    heap.validate().unwrap();
+    for (i, &p) in ptrs.iter().enumerate().skip(1).step_by(2) {
+        let data = unsafe { core::slice::from_raw_parts(p, layout.size()) };
+        assert!(data.iter().all(|&b| b == i as u8));
+        unsafe { heap.deallocate(core::ptr::NonNull::new_unchecked(p), layout) };
+    }
+    assert_eq!(heap.free_regions().count(), 1);
+}
+
+#[test]
+fn test_load_rejects() {
+    let buf = vec![0u8; HEAP_SIZE];
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 16);
+    let heap = unsafe { BuddyAlloc::new(param) };
+    let mut image = vec![0u8; persist::image_size(&heap)];
+    assert_eq!(
+        persist::save(&heap, &mut image[..10]),
+        Err(ImageError::Truncated)
+    );
+    persist::save(&heap, &mut image).unwrap();
+    let load = |image: &[u8], param| unsafe { persist::load(param, image) }.err();
+
+    assert_eq!(
+        load(&image[..image.len() - 1], param),
+        Some(ImageError::Truncated)
+    );
+    let mut bad = image.clone();
+    bad[0] ^= 1;
+    assert_eq!(load(&bad, param), Some(ImageError::BadMagic));
+    let mut bad = image.clone();
+    bad[4..6].copy_from_slice(&(IMAGE_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        load(&bad, param),
+        Some(ImageError::UnsupportedVersion(IMAGE_VERSION + 1))
+    );
+    let mut bad = image.clone();
+    *bad.last_mut().unwrap() ^= 0x80;
+    assert_eq!(load(&bad, param), Some(ImageError::BadChecksum));
+    // another leaf size
+    let other = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 32);
+    assert_eq!(load(&image, other), Some(ImageError::Incompatible));
+    assert_eq!(load(&image, param), None);
+}
@@ -0,0 +1,36 @@
+use crate::pin_table::PinTable;
+
+#[test]
+fn test_pin_and_unpin() {
+    let table: PinTable<2> = PinTable::new();
+    let a = core::ptr::without_provenance::<u8>(0x1000);
+    let b = core::ptr::without_provenance::<u8>(0x2000);
+    table.pin(a).unwrap();
+    table.pin(a).unwrap();
+    table.pin(b).unwrap();
+    assert_eq!(table.pin_count(a), 2);
+    // the table is full
+    assert!(table
+        .pin(core::ptr::without_provenance::<u8>(0x3000))
+        .is_err());
+    table.unpin(a);
+    assert!(table.is_pinned(a));
+    table.unpin(a);
+    assert!(!table.is_pinned(a));
+    table.debug_assert_movable(a);
+    assert_eq!(table.pinned().collect::<Vec<_>>(), vec![0x2000]);
+    // the freed slot is reused
+    table
+        .pin(core::ptr::without_provenance::<u8>(0x3000))
+        .unwrap();
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "pinned block chosen for relocation")]
+fn test_pinned_block_is_not_movable() {
+    let table: PinTable<1> = PinTable::new();
+    let a = core::ptr::without_provenance::<u8>(0x1000);
+    table.pin(a).unwrap();
+    table.debug_assert_movable(a);
+}
@@ -0,0 +1,28 @@
+use crate::{
+    buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    rc_alloc::RcAlloc,
+};
+
+const HEAP_SIZE: usize = 16 * 1024;
+
+#[test]
+fn test_shared_heap() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let heap = RcAlloc::new(unsafe { BuddyAlloc::new(param) });
+    let mut free = 0;
+    heap.for_each_free_block(|_, size| free += size);
+
+    let mut a: Vec<u32, _> = Vec::new_in(heap.clone());
+    let mut b: Vec<u32, _> = Vec::new_in(heap.clone());
+    a.extend(0..100);
+    b.extend(0..200);
+    let mut used = 0;
+    heap.for_each_free_block(|_, size| used += size);
+    assert!(used < free);
+    drop(a);
+    drop(b);
+    let mut after = 0;
+    heap.for_each_free_block(|_, size| after += size);
+    assert_eq!(after, free);
+}
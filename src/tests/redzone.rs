@@ -0,0 +1,51 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::Corruption,
+        redzone::{RedzoneAlloc, CANARY, REDZONE},
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+fn report(corruption: Corruption) {
+    REPORTED.store(corruption.addr, Ordering::Relaxed);
+}
+
+#[test]
+fn test_redzone() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let inner = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64)) };
+    let allocator = RedzoneAlloc::new(inner).with_handler(report);
+    for align in [1, 8, 64, 256] {
+        let layout = Layout::from_size_align(100, align).unwrap();
+        let p = allocator.allocate(layout).unwrap();
+        assert_eq!(p.len(), 100);
+        assert_eq!(p.as_mut_ptr().align_offset(align), 0);
+        let before = unsafe { core::slice::from_raw_parts(p.as_mut_ptr().sub(REDZONE), REDZONE) };
+        assert!(before.iter().all(|&b| b == CANARY));
+        unsafe { p.as_mut_ptr().write_bytes(1, 100) };
+        unsafe { allocator.check(p.as_non_null_ptr(), layout) }.unwrap();
+        unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    }
+    assert_eq!(REPORTED.load(Ordering::Relaxed), 0);
+
+    // one byte past the end
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { p.add(40).write(0) };
+    unsafe { allocator.deallocate(NonNull::new(p).unwrap(), layout) };
+    assert_eq!(REPORTED.load(Ordering::Relaxed), p as usize + 40);
+    // and an underrun
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { p.sub(1).write(0) };
+    let err = unsafe { allocator.check(NonNull::new(p).unwrap(), layout) }.unwrap_err();
+    assert_eq!(err.addr, p as usize - 1);
+}
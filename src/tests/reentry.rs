@@ -0,0 +1,61 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        reentry::{AllocFailure, ReentryGuard},
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        ptr::NonNull,
+    },
+};
+
+const HEAP_SIZE: usize = 16 * 1024;
+
+/// a heap whose hook allocates and frees through the guard around it
+struct Hooked {
+    heap: BuddyAlloc,
+    guard: Cell<*const ReentryGuard<Hooked>>,
+    nested: Cell<Option<Result<NonNull<[u8]>, AllocFailure>>>,
+}
+
+unsafe impl Allocator for Hooked {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let guard = unsafe { &*self.guard.get() };
+        self.nested.set(Some(guard.try_allocate(layout)));
+        self.heap.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let guard = &*self.guard.get();
+        guard.deallocate(ptr, layout);
+        self.heap.deallocate(ptr, layout);
+    }
+}
+
+#[test]
+fn test_reentry() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = ReentryGuard::new(Hooked {
+        heap: unsafe { BuddyAlloc::new(param) },
+        guard: Cell::new(core::ptr::null()),
+        nested: Cell::new(None),
+    });
+    allocator.inner().guard.set(&allocator);
+    let layout = Layout::from_size_align(64, 1).unwrap();
+
+    let p = allocator.allocate(layout).unwrap();
+    assert_eq!(
+        allocator.inner().nested.get(),
+        Some(Err(AllocFailure::Reentered))
+    );
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    assert_eq!(allocator.rejected(), 2);
+    assert_eq!(allocator.leaked(), 1);
+
+    // the flag is cleared once the outer call returns
+    let huge = Layout::from_size_align(HEAP_SIZE, 1).unwrap();
+    assert_eq!(allocator.try_allocate(huge), Err(AllocFailure::OutOfMemory));
+    assert_eq!(allocator.rejected(), 3);
+}
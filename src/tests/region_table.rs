@@ -0,0 +1,25 @@
+use crate::region_table::RegionTable;
+
+#[test]
+fn test_find() {
+    let mut table: RegionTable<4> = RegionTable::new();
+    table.insert(0x3000..0x4000, 2);
+    table.insert(0x1000..0x2000, 0);
+    table.insert(0x2000..0x2800, 1);
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.find(0x0fff), None);
+    assert_eq!(table.find(0x1000), Some(0));
+    assert_eq!(table.find(0x1fff), Some(0));
+    assert_eq!(table.find(0x2000), Some(1));
+    assert_eq!(table.find(0x2800), None);
+    assert_eq!(table.find(0x3abc), Some(2));
+    assert_eq!(table.find(0x4000), None);
+}
+
+#[test]
+#[should_panic(expected = "overlapping regions")]
+fn test_overlap() {
+    let mut table: RegionTable<2> = RegionTable::new();
+    table.insert(0x1000..0x2000, 0);
+    table.insert(0x1800..0x2800, 1);
+}
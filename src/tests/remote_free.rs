@@ -0,0 +1,65 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        remote_free::RemoteFreeAlloc,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+struct Block(NonNull<u8>);
+
+unsafe impl Send for Block {}
+
+#[test]
+fn test_remote_free() {
+    let buf = vec![0u8; 1024 * 1024];
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), buf.len(), 16)) };
+    let available = heap.scrub_free();
+    let allocator = RemoteFreeAlloc::new(&heap);
+    let layout = Layout::from_size_align(3, 1).unwrap();
+    let blocks: Vec<Vec<Block>> = (0..4)
+        .map(|_| {
+            (0..500)
+                .map(|_| Block(allocator.allocate(layout).unwrap().as_non_null_ptr()))
+                .collect()
+        })
+        .collect();
+    let freer = allocator.freer();
+    std::thread::scope(|s| {
+        for blocks in blocks {
+            s.spawn(move || {
+                for Block(p) in blocks {
+                    unsafe { freer.deallocate(p, layout) };
+                }
+            });
+        }
+    });
+    // nothing went back before the owner drains
+    assert!(heap.scrub_free() < available);
+    assert_eq!(allocator.drain(), 2000);
+    assert_eq!(allocator.drain(), 0);
+    assert_eq!(heap.scrub_free(), available);
+}
+
+#[test]
+fn test_allocate_drains() {
+    let buf = vec![0u8; 4096];
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), buf.len(), 16)) };
+    let allocator = RemoteFreeAlloc::new(&heap);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+    let mut blocks = Vec::new();
+    while let Ok(p) = allocator.allocate(layout) {
+        blocks.push(p.as_non_null_ptr());
+    }
+    let freer = allocator.freer();
+    for p in blocks {
+        unsafe { freer.deallocate(p, layout) };
+    }
+    // the owner picks the remote frees up when it runs out
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    assert_eq!(allocator.drain(), 0);
+}
@@ -0,0 +1,53 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        rt_pool::RtPool,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+const LEAF_SIZE: usize = 16;
+
+fn with_allocator<F: FnOnce(BuddyAlloc)>(f: F) {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, LEAF_SIZE);
+    unsafe { f(BuddyAlloc::new(param)) }
+}
+
+#[test]
+fn test_prefill_and_allocate() {
+    with_allocator(|parent| {
+        let available_bytes = parent.available_bytes();
+        {
+            let classes = [
+                Layout::from_size_align(64, 16).unwrap(),
+                Layout::from_size_align(256, 16).unwrap(),
+            ];
+            let pool: RtPool<_, 2, 8> = RtPool::new(&parent, classes);
+            pool.prefill(0, 4).unwrap();
+            pool.prefill(1, 2).unwrap();
+            assert_eq!(pool.available(0), 4);
+            assert_eq!(pool.available(1), 2);
+
+            let layout = Layout::from_size_align(100, 8).unwrap();
+            let a = pool.allocate(layout).unwrap();
+            let b = pool.allocate(layout).unwrap();
+            assert_eq!(a.len(), 256);
+            // the class is drained, no fallback to the parent
+            assert!(pool.allocate(layout).is_err());
+            unsafe { pool.deallocate(a.cast(), layout) };
+            assert_eq!(pool.available(1), 1);
+            unsafe { pool.deallocate(b.cast(), layout) };
+            // requests no class can serve are rejected
+            assert!(pool
+                .allocate(Layout::from_size_align(512, 1).unwrap())
+                .is_err());
+        }
+        // dropping the pool returns everything to the parent
+        assert_eq!(parent.available_bytes(), available_bytes);
+        assert!(parent
+            .allocate(Layout::from_size_align(HEAP_SIZE / 4, 1).unwrap())
+            .is_ok());
+    });
+}
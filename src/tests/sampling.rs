@@ -0,0 +1,10 @@
+use crate::sampling::Sampler;
+
+#[test]
+fn test_sampler() {
+    let sampler = Sampler::new(3);
+    let picks: Vec<bool> = (0..7).map(|_| sampler.sample()).collect();
+    assert_eq!(picks, [true, false, false, true, false, false, true]);
+    sampler.set_period(1);
+    assert!((0..5).all(|_| sampler.sample()));
+}
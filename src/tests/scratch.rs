@@ -0,0 +1,43 @@
+use crate::scratch::{scratch, SCRATCH_SIZE};
+
+#[test]
+fn test_scratch_is_reclaimed() {
+    let used = scratch(|alloc| {
+        let mut v = Vec::with_capacity_in(1024, alloc);
+        v.extend(0..1024u32);
+        assert_eq!(v.iter().sum::<u32>(), 1023 * 512);
+        alloc.used_bytes()
+    });
+    assert!(used >= 4096);
+    scratch(|alloc| assert_eq!(alloc.used_bytes(), 0));
+}
+
+#[test]
+#[should_panic(expected = "scratch called from within scratch")]
+fn test_nested_scratch_refused() {
+    scratch(|outer| {
+        let _v: Vec<u8, _> = Vec::with_capacity_in(100, outer);
+        scratch(|inner| {
+            let _w: Vec<u8, _> = Vec::with_capacity_in(SCRATCH_SIZE / 2, inner);
+        });
+    });
+}
+
+#[test]
+fn test_scratch_usable_after_refused_nesting() {
+    let result = std::panic::catch_unwind(|| scratch(|_| scratch(|_| ())));
+    assert!(result.is_err());
+    scratch(|alloc| assert_eq!(alloc.used_bytes(), 0));
+}
+
+#[test]
+fn test_scratch_rewinds_on_panic() {
+    let result = std::panic::catch_unwind(|| {
+        scratch(|alloc| {
+            let _v: Vec<u8, _> = Vec::with_capacity_in(1024, alloc);
+            panic!("boom");
+        })
+    });
+    assert!(result.is_err());
+    scratch(|alloc| assert_eq!(alloc.used_bytes(), 0));
+}
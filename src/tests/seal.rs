@@ -0,0 +1,26 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        seal::SealAlloc,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 16 * 1024;
+
+#[test]
+fn test_seal() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let allocator = SealAlloc::new(unsafe { BuddyAlloc::new(param) });
+    let layout = Layout::from_size_align(64, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    allocator.seal();
+    assert!(allocator.is_sealed());
+    assert!(allocator.allocate(layout).is_err());
+    assert!(allocator.allocate(layout).is_err());
+    assert_eq!(allocator.rejected(), 2);
+    // frees still work
+    unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+    assert_eq!(allocator.rejected(), 2);
+}
@@ -0,0 +1,150 @@
+use {
+    crate::{
+        error::Error,
+        shared_alloc::{SharedAlloc, Yield},
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+const LEAF_SIZE: usize = 16;
+
+fn region() -> Vec<u128> {
+    vec![0u128; HEAP_SIZE / 16]
+}
+
+fn base(buf: &mut [u128]) -> NonNull<u8> {
+    NonNull::new(buf.as_mut_ptr().cast()).unwrap()
+}
+
+#[test]
+fn test_basic_malloc() {
+    let mut buf = region();
+    let allocator = unsafe { SharedAlloc::init(base(&mut buf), HEAP_SIZE, LEAF_SIZE) };
+    let available_bytes = allocator.available_bytes();
+    assert!(available_bytes > HEAP_SIZE / 2);
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe {
+        p.as_mut_ptr().write_bytes(42, layout.size());
+        allocator.deallocate(p.cast(), layout);
+    }
+    // drain and refill to check everything merged back
+    let layout = Layout::from_size_align(LEAF_SIZE, 1).unwrap();
+    let mut count = 0;
+    while allocator.allocate(layout).is_ok() {
+        count += 1;
+    }
+    assert_eq!(count * LEAF_SIZE, available_bytes);
+}
+
+#[test]
+fn test_attach_at_other_address() {
+    let mut buf = region();
+    let allocator = unsafe { SharedAlloc::init(base(&mut buf), HEAP_SIZE, LEAF_SIZE) };
+    let layout = Layout::from_size_align(256, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { p.write(7) };
+    let offset = p as usize - allocator.base().as_ptr() as usize;
+
+    // "map" the region somewhere else
+    let mut moved = buf.clone();
+    drop(buf);
+    let allocator = unsafe { SharedAlloc::attach(base(&mut moved)) }.unwrap();
+    let p = unsafe { allocator.base().as_ptr().add(offset) };
+    assert_eq!(unsafe { *p }, 7);
+    let q = allocator.allocate(layout).unwrap();
+    assert_ne!(q.as_mut_ptr(), p);
+    unsafe {
+        allocator.deallocate(NonNull::new_unchecked(p), layout);
+        allocator.deallocate(q.cast(), layout);
+    }
+}
+
+#[test]
+fn test_attach_rejects_unformatted() {
+    let mut buf = region();
+    assert!(unsafe { SharedAlloc::attach(base(&mut buf)) }.is_none());
+}
+
+#[test]
+fn test_offsets_survive_remapping() {
+    let mut buf = region();
+    let allocator = unsafe { SharedAlloc::init(base(&mut buf), HEAP_SIZE, LEAF_SIZE) };
+    let layout = Layout::from_size_align(64, 1).unwrap();
+    let offset = allocator.allocate_offset(layout).unwrap();
+    unsafe { allocator.resolve(offset).as_ptr().write(9) };
+    assert_eq!(allocator.offset_of(allocator.resolve(offset)), Some(offset));
+
+    let mut moved = buf.clone();
+    drop(buf);
+    let allocator = unsafe { SharedAlloc::attach(base(&mut moved)) }.unwrap();
+    assert_eq!(unsafe { *allocator.resolve(offset).as_ptr() }, 9);
+    let available_bytes = allocator.available_bytes();
+    unsafe { allocator.deallocate_offset(offset) };
+    let big = Layout::from_size_align(HEAP_SIZE / 4, 1).unwrap();
+    assert!(allocator.allocate_offset(big).is_ok());
+    assert_eq!(allocator.available_bytes(), available_bytes);
+}
+
+#[test]
+fn test_concurrent_handles() {
+    let mut buf = region();
+    let allocator = unsafe { SharedAlloc::init(base(&mut buf), HEAP_SIZE, LEAF_SIZE) };
+    let available_bytes = allocator.available_bytes();
+    let addr = allocator.base().as_ptr().expose_provenance();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            std::thread::spawn(move || {
+                let base = NonNull::new(core::ptr::with_exposed_provenance_mut(addr)).unwrap();
+                let allocator = unsafe { SharedAlloc::attach(base) }
+                    .unwrap()
+                    .with_wait_strategy(Yield);
+                let layout = Layout::from_size_align(LEAF_SIZE * 3, 1).unwrap();
+                for _ in 0..1000 {
+                    let offset = allocator.allocate_offset(layout).unwrap();
+                    unsafe { allocator.deallocate_offset(offset) };
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(allocator.available_bytes(), available_bytes);
+    let big = Layout::from_size_align(HEAP_SIZE / 4, 1).unwrap();
+    assert!(allocator.allocate(big).is_ok());
+}
+
+#[test]
+fn test_alignment() {
+    let mut buf = vec![0u128; (HEAP_SIZE + 4096) / 16];
+    let start = base(&mut buf).as_ptr();
+    let start = start.wrapping_add(start.align_offset(4096));
+    let mut aligned = 0;
+    // only some region placements put the heap start on a 1 KiB boundary
+    for shift in (0..4096).step_by(LEAF_SIZE) {
+        let base = NonNull::new(start.wrapping_add(shift)).unwrap();
+        let allocator = unsafe { SharedAlloc::init(base, HEAP_SIZE - 4096, LEAF_SIZE) };
+        let layout = Layout::from_size_align(LEAF_SIZE, 1024).unwrap();
+        match allocator.allocate_offset(layout) {
+            Ok(offset) => {
+                assert!(allocator.resolve(offset).addr().get().is_multiple_of(1024));
+                // the whole block is the allocation's
+                let p = allocator.allocate(layout).unwrap().as_non_null_ptr();
+                assert!(p.addr().get().is_multiple_of(1024));
+                let next = allocator.offset_of(p).unwrap();
+                assert!(next.get().abs_diff(offset.get()) >= 1024);
+                aligned += 1;
+            }
+            Err(err) => {
+                assert_eq!(err, Error::AlignmentUnsupported);
+                assert!(allocator.allocate(layout).is_err());
+            }
+        }
+    }
+    assert_eq!(aligned, 4);
+}
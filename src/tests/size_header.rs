@@ -0,0 +1,42 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::HeapInspect,
+        size_header::SizeHeaderAlloc,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_size_header() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let inner = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64)) };
+    let allocator = SizeHeaderAlloc::new(inner);
+    let free = allocator.inner().stats().free_bytes;
+    for align in [1, 8, 64, 256] {
+        let layout = Layout::from_size_align(3000, align).unwrap();
+        let p = allocator.allocate(layout).unwrap();
+        assert_eq!(p.as_mut_ptr().align_offset(align), 0);
+        unsafe {
+            assert_eq!(allocator.layout_of(p.as_non_null_ptr()), layout);
+            // the wrong layout does no harm
+            allocator.deallocate(p.as_non_null_ptr(), Layout::new::<u8>());
+        }
+    }
+    assert_eq!(allocator.inner().stats().free_bytes, free);
+
+    let layout = Layout::from_size_align(100, 16).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_non_null_ptr();
+    unsafe {
+        p.write_bytes(5, 100);
+        let q = allocator.realloc(p, 5000).unwrap().as_non_null_ptr();
+        assert_eq!(q.align_offset(16), 0);
+        assert!((0..100).all(|i| q.add(i).read() == 5));
+        assert_eq!(allocator.layout_of(q).size(), 5000);
+        assert!(allocator.realloc(q, HEAP_SIZE).is_err());
+        allocator.free(q);
+    }
+    assert_eq!(allocator.inner().stats().free_bytes, free);
+}
@@ -0,0 +1,51 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::HeapInspect,
+        slab_alloc::SlabAlloc,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_size_classes() {
+    let buf = vec![0u8; 1024 * 1024];
+    let parent = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), buf.len(), 16)) };
+    let parent_free = parent.stats().free_bytes;
+    {
+        let slab = SlabAlloc::new(&parent, [16, 32, 64, 128, 256], 4096);
+        let mut blocks = Vec::new();
+        for i in 0..2000 {
+            let layout = Layout::from_size_align(1 + i % 256, 1 << (i % 4)).unwrap();
+            let p = slab.allocate(layout).unwrap();
+            assert!(p.as_mut_ptr().addr().is_multiple_of(layout.align()));
+            assert!(p.len() >= layout.size());
+            unsafe { p.as_mut_ptr().write_bytes(i as u8, layout.size()) };
+            blocks.push((p, layout, i as u8));
+        }
+        // each object got its own memory
+        for (p, layout, byte) in &blocks {
+            assert!(unsafe { &p.as_ref()[..layout.size()] }
+                .iter()
+                .all(|b| b == byte));
+        }
+        let slabs = slab.slabs(0);
+        let free = slab.free_objects(0);
+        for (p, layout, _) in blocks {
+            unsafe { slab.deallocate(p.as_non_null_ptr(), layout) };
+        }
+        assert!(slab.free_objects(0) > free);
+        // freed objects are reused before asking the parent again
+        let p = slab
+            .allocate(Layout::from_size_align(16, 8).unwrap())
+            .unwrap();
+        assert_eq!(slab.slabs(0), slabs);
+        unsafe { slab.deallocate(p.as_non_null_ptr(), Layout::from_size_align(16, 8).unwrap()) };
+        // larger requests bypass the classes
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let p = slab.allocate(layout).unwrap();
+        unsafe { slab.deallocate(p.as_non_null_ptr(), layout) };
+    }
+    // slabs went back to the parent on drop
+    assert_eq!(parent.stats().free_bytes, parent_free);
+}
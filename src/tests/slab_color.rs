@@ -0,0 +1,19 @@
+use crate::slab_color::{SlabColorer, CACHE_LINE_SIZE};
+
+#[test]
+fn test_colors_cycle() {
+    // 4096 / 96 = 42 objects, 64 bytes of slack: colors 0 and 64
+    let colorer = SlabColorer::new(4096, 96, CACHE_LINE_SIZE);
+    assert_eq!(colorer.colors(), 2);
+    let offsets: Vec<usize> = (0..4).map(|_| colorer.next_offset()).collect();
+    assert_eq!(offsets, vec![0, 64, 0, 64]);
+    assert_eq!(SlabColorer::objects_per_slab(4096, 96, 64), 42);
+}
+
+#[test]
+fn test_no_slack_single_color() {
+    let colorer = SlabColorer::new(4096, 64, CACHE_LINE_SIZE);
+    assert_eq!(colorer.colors(), 1);
+    assert_eq!(colorer.next_offset(), 0);
+    assert_eq!(colorer.next_offset(), 0);
+}
@@ -0,0 +1,46 @@
+use {
+    crate::{
+        bump_alloc::{BumpAlloc, BumpAllocParam},
+        small_alloc::SmallAlloc,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+#[test]
+fn test_inline_then_backing() {
+    let buf = [0u8; 4096];
+    let backing = unsafe { BumpAlloc::new(BumpAllocParam::new(buf.as_ptr(), buf.len())) };
+    let small = SmallAlloc::<_, 64>::new(&backing);
+    let mut v: Vec<u32, _> = Vec::with_capacity_in(4, &small);
+    v.extend([1, 2, 3, 4]);
+    assert!(small.is_inline(v.as_ptr().cast()));
+    assert_eq!(backing.used_bytes(), 0);
+    // outgrowing the inline storage moves to the backing allocator
+    v.extend(5..64);
+    assert!(!small.is_inline(v.as_ptr().cast()));
+    assert!(backing.used_bytes() > 0);
+    assert_eq!(v.iter().sum::<u32>(), 63 * 32);
+    drop(v);
+    assert_eq!(small.inline_used(), 0);
+}
+
+#[test]
+fn test_inline_reclaim() {
+    let backing = std::alloc::Global;
+    let small = SmallAlloc::<_, 64>::new(&backing);
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let p = small.allocate(layout).unwrap();
+    let q = small.allocate(layout).unwrap();
+    assert_eq!(p.as_mut_ptr().align_offset(8), 0);
+    assert_eq!(small.inline_used(), 32);
+    // the most recent one is reclaimed right away
+    unsafe { small.deallocate(q.as_non_null_ptr(), layout) };
+    assert_eq!(small.inline_used(), 16);
+    let q = small.allocate(layout).unwrap();
+    unsafe {
+        small.deallocate(p.as_non_null_ptr(), layout);
+        assert_eq!(small.inline_used(), 32);
+        small.deallocate(q.as_non_null_ptr(), layout);
+    }
+    assert_eq!(small.inline_used(), 0);
+}
@@ -0,0 +1,65 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::{HeapInspect, HeapStats},
+        locked::Locked,
+        snapshot::Snapshot,
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+#[test]
+fn test_snapshot_matches_heap() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let heap = Locked::<_>::new(unsafe {
+        BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64))
+    });
+    let layout = Layout::from_size_align(100, 1).unwrap();
+    let ptrs: Vec<_> = (0..5).map(|_| heap.allocate(layout).unwrap()).collect();
+
+    let mut bits = vec![0u8; heap.with(Snapshot::size)];
+    let snapshot = heap.with(|h| Snapshot::take(h, &mut bits)).unwrap();
+    let stats = heap.with(|h| h.stats());
+    // a snapshot keeps no counters
+    let without_counters = HeapStats {
+        peak_used: 0,
+        allocs: 0,
+        failed_allocs: 0,
+        frees: 0,
+        ..stats
+    };
+    assert_eq!(snapshot.stats(), without_counters);
+    let mut blocks = Vec::new();
+    heap.with(|h| h.for_each_free_block(|addr, size| blocks.push((addr, size))));
+    let mut copied = Vec::new();
+    snapshot.for_each_free_block(|addr, size| copied.push((addr, size)));
+    blocks.sort_unstable();
+    copied.sort_unstable();
+    assert_eq!(copied, blocks);
+    assert_eq!(snapshot.free_blocks(1), heap.with(|h| h.free_blocks(1)));
+
+    // the copy stays as it was while the heap moves on
+    for p in ptrs {
+        unsafe { heap.deallocate(p.as_non_null_ptr(), layout) };
+    }
+    // a snapshot keeps no counters
+    let without_counters = HeapStats {
+        peak_used: 0,
+        allocs: 0,
+        failed_allocs: 0,
+        frees: 0,
+        ..stats
+    };
+    assert_eq!(snapshot.stats(), without_counters);
+    assert_ne!(heap.with(|h| h.stats()), stats);
+}
+
+#[test]
+fn test_snapshot_buffer_too_small() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let heap = unsafe { BuddyAlloc::new(BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64)) };
+    let mut bits = vec![0u8; Snapshot::size(&heap) - 1];
+    assert!(Snapshot::take(&heap, &mut bits).is_none());
+}
@@ -0,0 +1,53 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        task_accounting::TaskAccounting,
+    },
+    core::{
+        alloc::{Allocator, Layout},
+        cell::Cell,
+    },
+};
+
+std::thread_local! {
+    static TASK: Cell<usize> = const { Cell::new(0) };
+}
+
+fn current_task_id() -> usize {
+    TASK.with(|task| task.get())
+}
+
+#[test]
+fn test_per_task_usage_and_limits() {
+    let buf: Vec<u8> = Vec::with_capacity(64 * 1024);
+    let param = BuddyAllocParam::new(buf.as_ptr(), 64 * 1024, 16);
+    let heap = TaskAccounting::<_, 3>::new(unsafe { BuddyAlloc::new(param) }, current_task_id);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+
+    TASK.with(|task| task.set(1));
+    heap.set_limit(1, 250);
+    let a = heap.allocate(layout).unwrap();
+    assert_eq!(a.as_mut_ptr().align_offset(8), 0);
+    let b = heap.allocate(layout).unwrap();
+    assert!(heap.allocate(layout).is_err());
+    let usage = heap.usage(1);
+    assert_eq!((usage.used, usage.peak, usage.rejected), (200, 200, 1));
+    assert_eq!(heap.usage(0).used, 0);
+
+    // a block freed by another task still credits its owner
+    TASK.with(|task| task.set(0));
+    unsafe { heap.deallocate(a.as_non_null_ptr(), layout) };
+    assert_eq!(heap.usage(1).used, 100);
+
+    // ids past the last account share it
+    TASK.with(|task| task.set(7));
+    let c = heap.allocate(layout).unwrap();
+    assert_eq!(heap.usage(2).used, 100);
+    unsafe {
+        heap.deallocate(b.as_non_null_ptr(), layout);
+        heap.deallocate(c.as_non_null_ptr(), layout);
+    }
+    assert_eq!(heap.usage(1).used, 0);
+    assert_eq!(heap.usage(1).peak, 200);
+    assert_eq!(heap.usage(2).used, 0);
+}
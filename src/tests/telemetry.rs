@@ -0,0 +1,43 @@
+use crate::{
+    buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+    telemetry::{Reporter, Snapshot, TelemetrySink},
+};
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+impl TelemetrySink for Vec<u8> {
+    fn write(&mut self, frame: &[u8]) {
+        self.extend_from_slice(frame);
+    }
+}
+
+#[test]
+fn test_roundtrip() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let heap = unsafe { BuddyAlloc::new(param) };
+    let snapshot = Snapshot::capture(&heap, 42);
+    assert!(snapshot.free_bytes > 0);
+    assert!(snapshot.largest_free <= snapshot.free_bytes);
+    let mut sink = Vec::new();
+    Reporter::new(10).report(&heap, &mut sink, 42);
+    let (decoded, len) = Snapshot::decode(&sink).unwrap();
+    assert_eq!(len, sink.len());
+    assert_eq!(decoded, snapshot);
+    assert!(Snapshot::decode(&sink[..(len - 1)]).is_none());
+}
+
+#[test]
+fn test_poll_interval() {
+    let buf: Vec<u8> = Vec::with_capacity(HEAP_SIZE);
+    let param = BuddyAllocParam::new(buf.as_ptr(), HEAP_SIZE, 64);
+    let heap = unsafe { BuddyAlloc::new(param) };
+    let reporter = Reporter::new(10);
+    let mut sink = Vec::new();
+    assert!(reporter.poll(&heap, &mut sink, 0));
+    assert!(!reporter.poll(&heap, &mut sink, 9));
+    assert!(reporter.poll(&heap, &mut sink, 10));
+    let (_, len) = Snapshot::decode(&sink).unwrap();
+    let (second, _) = Snapshot::decode(&sink[len..]).unwrap();
+    assert_eq!(second.timestamp, 10);
+}
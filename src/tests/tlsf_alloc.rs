@@ -0,0 +1,115 @@
+use {
+    crate::{
+        buddy_alloc::{BuddyAlloc, BuddyAllocParam},
+        inspect::HeapInspect,
+        tlsf_alloc::{TlsfAlloc, TlsfAllocParam, ALIGN},
+    },
+    core::alloc::{Allocator, Layout},
+};
+
+const HEAP_SIZE: usize = 1024 * 1024;
+
+fn with_allocator<F: FnOnce(TlsfAlloc)>(heap_size: usize, f: F) {
+    let buf: Vec<u8> = Vec::with_capacity(heap_size);
+    let allocator = unsafe { TlsfAlloc::new(TlsfAllocParam::new(buf.as_ptr(), heap_size)) };
+    f(allocator);
+}
+
+#[test]
+fn test_random_alloc_free() {
+    with_allocator(HEAP_SIZE, |allocator| {
+        let free = allocator.stats().free_bytes;
+        assert_eq!(allocator.stats().free_blocks, 1);
+        let mut seed: u32 = 7;
+        let mut next = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as usize
+        };
+        let mut live = Vec::new();
+        for i in 0..20000 {
+            if live.len() < 200 && next() % 3 != 0 {
+                let size = 1 + next() % 3000;
+                let align = 1 << (next() % 7);
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let p = allocator.allocate(layout).unwrap();
+                assert!(p.as_mut_ptr().addr().is_multiple_of(align));
+                assert!(unsafe { allocator.alloc_size(p.as_mut_ptr()) } >= size);
+                unsafe { p.as_mut_ptr().write_bytes(i as u8, size) };
+                live.push((p, layout, i as u8));
+            } else if !live.is_empty() {
+                let (p, layout, byte) = live.swap_remove(next() % live.len());
+                assert!(unsafe { p.as_ref() }.iter().all(|&b| b == byte));
+                unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+            }
+            if i % 1000 == 0 {
+                allocator.validate().unwrap();
+            }
+        }
+        for (p, layout, _) in live {
+            unsafe { allocator.deallocate(p.as_non_null_ptr(), layout) };
+        }
+        allocator.validate().unwrap();
+        // everything merged back into one block
+        assert_eq!(allocator.stats().free_bytes, free);
+        assert_eq!(allocator.stats().free_blocks, 1);
+    });
+}
+
+#[test]
+fn test_odd_sizes_fit_tighter_than_buddy() {
+    const SIZE: usize = 64 * 1024;
+    let buf: Vec<u8> = Vec::with_capacity(2 * SIZE);
+    let tlsf = unsafe { TlsfAlloc::new(TlsfAllocParam::new(buf.as_ptr(), SIZE)) };
+    let buddy = unsafe {
+        BuddyAlloc::new(BuddyAllocParam::new(
+            buf.as_ptr().wrapping_add(SIZE),
+            SIZE,
+            16,
+        ))
+    };
+    // just past a power of two, buddy rounds each one up to 1 KiB
+    let layout = Layout::from_size_align(520, 8).unwrap();
+    let count = |alloc: &dyn Allocator| {
+        let mut n = 0;
+        while alloc.allocate(layout).is_ok() {
+            n += 1;
+        }
+        n
+    };
+    let (tlsf_count, buddy_count) = (count(&tlsf), count(&buddy));
+    assert!(tlsf_count * 10 > buddy_count * 18);
+    tlsf.validate().unwrap();
+}
+
+#[test]
+fn test_large_alignment() {
+    with_allocator(HEAP_SIZE, |allocator| {
+        let free = allocator.stats().free_bytes;
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let s = allocator.allocate(small).unwrap();
+        let layout = Layout::from_size_align(4096, 4096).unwrap();
+        let p = allocator.allocate(layout).unwrap();
+        assert!(p.as_mut_ptr().addr().is_multiple_of(4096));
+        allocator.validate().unwrap();
+        unsafe {
+            allocator.deallocate(p.as_non_null_ptr(), layout);
+            allocator.deallocate(s.as_non_null_ptr(), small);
+        }
+        allocator.validate().unwrap();
+        assert_eq!(allocator.stats().free_bytes, free);
+    });
+}
+
+#[test]
+fn test_out_of_memory() {
+    with_allocator(4096, |allocator| {
+        let p = allocator.malloc(1);
+        assert!(p.addr().is_multiple_of(ALIGN));
+        assert!(allocator.malloc(8192).is_null());
+        assert!(allocator
+            .allocate(Layout::from_size_align(usize::MAX / 2, 1).unwrap())
+            .is_err());
+        unsafe { allocator.free(p) };
+        allocator.validate().unwrap();
+    });
+}
@@ -0,0 +1,81 @@
+use {
+    crate::vm_alloc::{OsBacking, PageBacking, VmAlloc},
+    core::{
+        alloc::{Allocator, Layout},
+        ptr::NonNull,
+    },
+};
+
+const HEAP_SIZE: usize = 64 * 1024 * 1024;
+const LEAF_SIZE: usize = 16;
+
+#[test]
+fn test_basic_malloc() {
+    let allocator = VmAlloc::new(HEAP_SIZE, LEAF_SIZE).unwrap();
+    assert_eq!(allocator.reserved_bytes(), HEAP_SIZE);
+    let layout = Layout::from_size_align(1024 * 1024, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe {
+        p.write_bytes(42, layout.size());
+        assert_eq!(*p.add(layout.size() - 1), 42);
+        allocator.deallocate(NonNull::new_unchecked(p), layout);
+    }
+}
+
+#[test]
+fn test_commit_on_growth() {
+    let allocator = VmAlloc::new(HEAP_SIZE, LEAF_SIZE).unwrap();
+    // just the pages holding free list nodes
+    let initial = allocator.committed_bytes();
+    assert!(initial < 1024 * 1024);
+    let layout = Layout::from_size_align(4 * 1024 * 1024, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { p.write_bytes(7, layout.size()) };
+    assert!(allocator.committed_bytes() >= initial + layout.size());
+    assert!(allocator.committed_bytes() < HEAP_SIZE / 8);
+    // aligned past the reservation, committing the whole block it comes from
+    let layout = Layout::from_size_align(4096, 8 * 1024 * 1024).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    assert!(p.addr().is_multiple_of(layout.align()));
+    unsafe { p.write_bytes(7, layout.size()) };
+}
+
+#[test]
+fn test_trim() {
+    let allocator = VmAlloc::new(HEAP_SIZE, LEAF_SIZE).unwrap();
+    let available_bytes = allocator.available_bytes();
+    let layout = Layout::from_size_align(4 * 1024 * 1024, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap();
+    unsafe { p.as_mut_ptr().write_bytes(7, layout.size()) };
+    let committed = allocator.committed_bytes();
+    unsafe { allocator.deallocate(p.cast(), layout) };
+    let trimmed = allocator.trim();
+    assert!(trimmed >= layout.size() - OsBacking.page_size());
+    assert_eq!(allocator.committed_bytes(), committed - trimmed);
+    // trimmed memory is committed again, touching decommitted pages would fault
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe { p.write_bytes(7, layout.size()) };
+    assert_eq!(allocator.committed_bytes(), committed);
+    assert_eq!(allocator.available_bytes(), available_bytes);
+}
+
+#[test]
+fn test_decommit_threshold() {
+    let allocator = VmAlloc::new(HEAP_SIZE, LEAF_SIZE).unwrap();
+    allocator.set_decommit_threshold(1024 * 1024);
+    let layout = Layout::from_size_align(2 * 1024 * 1024, 1).unwrap();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    unsafe {
+        p.write_bytes(42, layout.size());
+        allocator.deallocate(NonNull::new_unchecked(p), layout);
+    }
+    // freed pages were already handed back, the block is still usable
+    let committed = allocator.committed_bytes();
+    let p = allocator.allocate(layout).unwrap().as_mut_ptr();
+    assert!(allocator.committed_bytes() >= committed + layout.size() - OsBacking.page_size());
+    unsafe {
+        p.write_bytes(1, layout.size());
+        allocator.deallocate(NonNull::new_unchecked(p), layout);
+    }
+    assert_eq!(allocator.committed_bytes(), committed);
+}
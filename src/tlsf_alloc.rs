@@ -0,0 +1,471 @@
+//! TLSF alloc
+//! Two-Level Segregated Fit, see http://www.gii.upv.es/tlsf/
+//!
+//! Free blocks are kept in lists by size: the first level splits sizes by
+//! powers of two, the second one splits each power of two linearly. Two
+//! bitmaps find a list holding a large enough block, so allocate and free
+//! take a bounded number of steps. Blocks are split to the requested size
+//! and merged with free neighbours on free, wasting far less than rounding
+//! to powers of two. Use `Locked` to share it or install it as global allocator.
+
+use {
+    crate::{
+        heap_registry::HeapRange,
+        inspect::{Corruption, HeapInspect, HeapStats},
+    },
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        mem::size_of,
+        ops::Range,
+        ptr::{null_mut, NonNull},
+    },
+};
+
+const OOM_MSG: &str = "requires more memory space to initialize TlsfAlloc";
+
+/// log2 of the second level lists per power of two
+const SL_LOG2: usize = 4;
+const SL_COUNT: usize = 1 << SL_LOG2;
+/// Block header size, blocks and payloads are aligned to it
+pub const ALIGN: usize = 2 * size_of::<usize>();
+const ALIGN_LOG2: usize = ALIGN.trailing_zeros() as usize;
+const HEADER_SIZE: usize = ALIGN;
+/// a free block holds its header and the free list links
+const MIN_BLOCK_SIZE: usize = 2 * ALIGN;
+/// sizes below are split linearly into the lists of level 0
+const SMALL_BLOCK_SIZE: usize = 1 << (SL_LOG2 + ALIGN_LOG2);
+const FREE: usize = 1;
+
+#[repr(C)]
+struct Block {
+    /// previous block in memory, null for the first one
+    prev_phys: *mut Block,
+    /// block size including this header, FREE in the low bit
+    size: usize,
+    /// free list links, in the payload of free blocks only
+    next_free: *mut Block,
+    prev_free: *mut Block,
+}
+
+impl Block {
+    unsafe fn size(b: *mut Block) -> usize {
+        (*b).size & !FREE
+    }
+
+    unsafe fn is_free(b: *mut Block) -> bool {
+        (*b).size & FREE != 0
+    }
+
+    unsafe fn next_phys(b: *mut Block) -> *mut Block {
+        b.byte_add(Self::size(b))
+    }
+
+    unsafe fn payload(b: *mut Block) -> *mut u8 {
+        b.cast::<u8>().add(HEADER_SIZE)
+    }
+
+    unsafe fn from_payload(p: *mut u8) -> *mut Block {
+        p.sub(HEADER_SIZE).cast()
+    }
+}
+
+/// the list a block of `size` bytes belongs to
+const fn mapping_insert(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK_SIZE {
+        (0, size >> ALIGN_LOG2)
+    } else {
+        let f = usize::BITS as usize - 1 - size.leading_zeros() as usize;
+        (
+            f - (SL_LOG2 + ALIGN_LOG2) + 1,
+            (size >> (f - SL_LOG2)) ^ SL_COUNT,
+        )
+    }
+}
+
+/// the first list whose blocks all hold `size` bytes
+const fn mapping_search(size: usize) -> Option<(usize, usize)> {
+    if size < SMALL_BLOCK_SIZE {
+        return Some(mapping_insert(size));
+    }
+    let f = usize::BITS as usize - 1 - size.leading_zeros() as usize;
+    match size.checked_add((1 << (f - SL_LOG2)) - 1) {
+        Some(size) => Some(mapping_insert(size)),
+        None => None,
+    }
+}
+
+/// block size for a payload of `size` bytes
+const fn adjust_size(size: usize) -> Option<usize> {
+    match size.checked_add(HEADER_SIZE + ALIGN - 1) {
+        Some(n) => {
+            let n = n & !(ALIGN - 1);
+            Some(if n < MIN_BLOCK_SIZE {
+                MIN_BLOCK_SIZE
+            } else {
+                n
+            })
+        }
+        None => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct TlsfAllocParam {
+    /// Base addr: the start address
+    base_addr: *const u8,
+    /// Len: available bytes from the start address
+    len: usize,
+}
+
+impl TlsfAllocParam {
+    /// Base addr: the start address
+    /// Len: available bytes from the start address
+    pub const fn new(base_addr: *const u8, len: usize) -> Self {
+        TlsfAllocParam { base_addr, len }
+    }
+}
+
+pub struct TlsfAlloc {
+    /// first block, past the lists kept in front of the heap
+    first: *mut Block,
+    /// zero sized block ending the heap, never free
+    sentinel: *mut Block,
+    fl_count: usize,
+    /// a set bit marks a first level with a non empty list
+    fl_bitmap: Cell<usize>,
+    /// per first level, a set bit marks a non empty second level list
+    sl_bitmaps: *mut u32,
+    /// list heads, `SL_COUNT` per first level
+    heads: *mut *mut Block,
+}
+
+// the heap owns its memory, moving it moves every pointer into it
+unsafe impl Send for TlsfAlloc {}
+
+impl TlsfAlloc {
+    /// # Safety
+    ///
+    /// The `base_addr..(base_addr + len)` must be allocated before use,
+    /// and must guarantee no others write to the memory range, otherwise behavior is undefined.
+    /// Panics if the range can't hold the lists and one block.
+    pub unsafe fn new(param: TlsfAllocParam) -> Self {
+        let region = param.base_addr.cast_mut();
+        let start = region.addr();
+        let end = start + param.len;
+        let fl_count = mapping_insert(param.len).0 + 1;
+        assert!(fl_count <= usize::BITS as usize, "{}", OOM_MSG);
+
+        let heads_addr = start.next_multiple_of(size_of::<usize>());
+        let sl_addr = heads_addr + fl_count * SL_COUNT * size_of::<*mut Block>();
+        let first_addr = (sl_addr + fl_count * size_of::<u32>()).next_multiple_of(ALIGN);
+        let sentinel_addr = (end & !(ALIGN - 1)).saturating_sub(HEADER_SIZE);
+        assert!(sentinel_addr >= first_addr + MIN_BLOCK_SIZE, "{}", OOM_MSG);
+
+        let heads = region.with_addr(heads_addr).cast::<*mut Block>();
+        for i in 0..fl_count * SL_COUNT {
+            heads.add(i).write(null_mut());
+        }
+        let sl_bitmaps = region.with_addr(sl_addr).cast::<u32>();
+        for i in 0..fl_count {
+            sl_bitmaps.add(i).write(0);
+        }
+        let first = region.with_addr(first_addr).cast::<Block>();
+        let sentinel = region.with_addr(sentinel_addr).cast::<Block>();
+        first.write(Block {
+            prev_phys: null_mut(),
+            size: (sentinel_addr - first_addr) | FREE,
+            next_free: null_mut(),
+            prev_free: null_mut(),
+        });
+        (*sentinel).prev_phys = first;
+        (*sentinel).size = 0;
+
+        let alloc = TlsfAlloc {
+            first,
+            sentinel,
+            fl_count,
+            fl_bitmap: Cell::new(0),
+            sl_bitmaps,
+            heads,
+        };
+        alloc.insert(first);
+        alloc
+    }
+
+    /// available bytes, block headers included
+    pub fn available_bytes(&self) -> usize {
+        self.sentinel.addr() - self.first.addr()
+    }
+
+    /// Allocate `size` bytes aligned to ALIGN,
+    /// returns null if out of memory. For C-style callers, without Layout.
+    pub fn malloc(&self, size: usize) -> *mut u8 {
+        match Layout::from_size_align(size, 1) {
+            Ok(layout) => self
+                .allocate(layout)
+                .map_or(core::ptr::null_mut(), |p| p.as_mut_ptr()),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    /// Free an allocation from malloc, or any other one of this heap, null is ignored.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a live allocation of this heap.
+    pub unsafe fn free(&self, ptr: *mut u8) {
+        if !ptr.is_null() {
+            self.free_block(Block::from_payload(ptr));
+        }
+    }
+
+    /// usable size of the live allocation at p
+    ///
+    /// # Safety
+    ///
+    /// `p` must have been returned by this allocator and not freed yet.
+    pub unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        Block::size(Block::from_payload(p.cast_mut())) - HEADER_SIZE
+    }
+
+    fn head(&self, fl: usize, sl: usize) -> *mut *mut Block {
+        unsafe { self.heads.add(fl * SL_COUNT + sl) }
+    }
+
+    fn sl_bitmap(&self, fl: usize) -> *mut u32 {
+        unsafe { self.sl_bitmaps.add(fl) }
+    }
+
+    /// push a free block onto its list
+    unsafe fn insert(&self, b: *mut Block) {
+        let (fl, sl) = mapping_insert(Block::size(b));
+        let head = self.head(fl, sl);
+        (*b).next_free = *head;
+        (*b).prev_free = null_mut();
+        if let Some(next) = NonNull::new(*head) {
+            (*next.as_ptr()).prev_free = b;
+        }
+        *head = b;
+        *self.sl_bitmap(fl) |= 1 << sl;
+        self.fl_bitmap.set(self.fl_bitmap.get() | 1 << fl);
+    }
+
+    /// unlink a free block from its list
+    unsafe fn remove(&self, b: *mut Block) {
+        let (fl, sl) = mapping_insert(Block::size(b));
+        let (next, prev) = ((*b).next_free, (*b).prev_free);
+        if !next.is_null() {
+            (*next).prev_free = prev;
+        }
+        if !prev.is_null() {
+            (*prev).next_free = next;
+        } else {
+            *self.head(fl, sl) = next;
+            if next.is_null() {
+                *self.sl_bitmap(fl) &= !(1 << sl);
+                if *self.sl_bitmap(fl) == 0 {
+                    self.fl_bitmap.set(self.fl_bitmap.get() & !(1 << fl));
+                }
+            }
+        }
+    }
+
+    /// a non empty list at or above `fl, sl`
+    fn find_suitable(&self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        if fl >= self.fl_count {
+            return None;
+        }
+        let mut fl = fl;
+        let mut sl_map = unsafe { *self.sl_bitmap(fl) } & (!0u32 << sl);
+        if sl_map == 0 {
+            let fl_map = self.fl_bitmap.get() & (!0usize).checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+            fl = fl_map.trailing_zeros() as usize;
+            sl_map = unsafe { *self.sl_bitmap(fl) };
+        }
+        Some((fl, sl_map.trailing_zeros() as usize))
+    }
+
+    /// Cut `b` at `size` bytes, the rest becomes a free block.
+    unsafe fn split(&self, b: *mut Block, size: usize) {
+        let rest_size = Block::size(b) - size;
+        if rest_size < MIN_BLOCK_SIZE {
+            return;
+        }
+        let rest = b.byte_add(size);
+        (*rest).prev_phys = b;
+        (*rest).size = rest_size | FREE;
+        (*Block::next_phys(rest)).prev_phys = rest;
+        (*b).size = size | ((*b).size & FREE);
+        self.insert(rest);
+    }
+
+    /// Take a free block holding `size` bytes whose payload is aligned to `align`.
+    unsafe fn take(&self, size: usize, align: usize) -> Option<*mut Block> {
+        // room to move the payload up to the alignment, leaving a whole
+        // free block in front
+        let search = if align > ALIGN {
+            size.checked_add(align + MIN_BLOCK_SIZE)?
+        } else {
+            size
+        };
+        let (fl, sl) = mapping_search(search)?;
+        let (fl, sl) = self.find_suitable(fl, sl)?;
+        let mut b = *self.head(fl, sl);
+        self.remove(b);
+
+        let payload = Block::payload(b).addr();
+        let mut target = payload.next_multiple_of(align);
+        if target != payload && target - payload < MIN_BLOCK_SIZE {
+            target = (payload + MIN_BLOCK_SIZE).next_multiple_of(align);
+        }
+        if target != payload {
+            // the front gap goes back as a free block
+            let gap = target - payload;
+            let nb = b.byte_add(gap);
+            (*nb).prev_phys = b;
+            (*nb).size = Block::size(b) - gap;
+            (*Block::next_phys(nb)).prev_phys = nb;
+            (*b).size = gap | FREE;
+            self.insert(b);
+            b = nb;
+        }
+        (*b).size &= !FREE;
+        self.split(b, size);
+        Some(b)
+    }
+
+    /// free a block, merging it with free neighbours
+    unsafe fn free_block(&self, mut b: *mut Block) {
+        (*b).size |= FREE;
+        let next = Block::next_phys(b);
+        if Block::is_free(next) {
+            self.remove(next);
+            (*b).size += Block::size(next);
+            (*Block::next_phys(b)).prev_phys = b;
+        }
+        let prev = (*b).prev_phys;
+        if !prev.is_null() && Block::is_free(prev) {
+            self.remove(prev);
+            (*prev).size += Block::size(b);
+            b = prev;
+            (*Block::next_phys(b)).prev_phys = b;
+        }
+        self.insert(b);
+    }
+
+    /// call `f` with every block in address order
+    fn for_each_block<F: FnMut(*mut Block)>(&self, mut f: F) {
+        let mut b = self.first;
+        while b != self.sentinel {
+            f(b);
+            b = unsafe { Block::next_phys(b) };
+        }
+    }
+}
+
+impl HeapRange for TlsfAlloc {
+    fn heap_range(&self) -> Range<usize> {
+        self.first.addr()..self.sentinel.addr()
+    }
+}
+
+impl HeapInspect for TlsfAlloc {
+    unsafe fn alloc_size(&self, p: *const u8) -> usize {
+        TlsfAlloc::alloc_size(self, p)
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            total_bytes: self.available_bytes(),
+            ..HeapStats::default()
+        };
+        self.for_each_block(|b| unsafe {
+            if Block::is_free(b) {
+                let size = Block::size(b);
+                stats.free_bytes += size;
+                stats.largest_free = stats.largest_free.max(size);
+                stats.free_blocks += 1;
+            }
+        });
+        stats.used_bytes = stats.total_bytes.saturating_sub(stats.free_bytes);
+        stats
+    }
+
+    fn validate(&self) -> Result<(), Corruption> {
+        // the blocks tile the heap, each linked back to the one before,
+        // and no two free blocks touch
+        let (mut prev, mut b) = (null_mut::<Block>(), self.first);
+        let mut free_blocks = 0;
+        while b != self.sentinel {
+            let addr = b.addr();
+            let (size, free) = unsafe { (Block::size(b), Block::is_free(b)) };
+            let prev_free = !prev.is_null() && unsafe { Block::is_free(prev) };
+            if unsafe { (*b).prev_phys } != prev
+                || size < MIN_BLOCK_SIZE
+                || !size.is_multiple_of(ALIGN)
+                || addr + size > self.sentinel.addr()
+                || (free && prev_free)
+            {
+                return Err(Corruption { addr });
+            }
+            free_blocks += free as usize;
+            prev = b;
+            b = unsafe { Block::next_phys(b) };
+        }
+        if unsafe { (*self.sentinel).prev_phys } != prev {
+            return Err(Corruption {
+                addr: self.sentinel.addr(),
+            });
+        }
+        // every listed block is free, in its own list and counted once
+        let mut listed = 0;
+        for fl in 0..self.fl_count {
+            for sl in 0..SL_COUNT {
+                let mut node = unsafe { *self.head(fl, sl) };
+                let bit = unsafe { *self.sl_bitmap(fl) } & (1 << sl) != 0;
+                if bit == node.is_null() {
+                    return Err(Corruption {
+                        addr: self.head(fl, sl).addr(),
+                    });
+                }
+                while let Some(b) = NonNull::new(node) {
+                    let b = b.as_ptr();
+                    let in_heap = b >= self.first && b < self.sentinel;
+                    if !in_heap
+                        || !unsafe { Block::is_free(b) }
+                        || mapping_insert(unsafe { Block::size(b) }) != (fl, sl)
+                    {
+                        return Err(Corruption { addr: b.addr() });
+                    }
+                    listed += 1;
+                    node = unsafe { (*b).next_free };
+                }
+            }
+        }
+        if listed != free_blocks {
+            return Err(Corruption {
+                addr: self.first.addr(),
+            });
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Allocator for TlsfAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let size = adjust_size(layout.size()).ok_or(AllocError)?;
+        let b = unsafe { self.take(size, layout.align()) }.ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(
+            unsafe { NonNull::new_unchecked(Block::payload(b)) },
+            layout.size(),
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.free_block(Block::from_payload(ptr.as_ptr()));
+    }
+}
@@ -0,0 +1,360 @@
+//! VmAlloc
+//! A std-only buddy heap over a reserved range of virtual memory.
+//!
+//! The whole range is reserved up front but pages are only committed as
+//! allocations reach them, the metadata lives outside the range.
+//! `trim` decommits the pages of free blocks while the range stays reserved,
+//! and `set_decommit_threshold` does the same for large blocks as they are
+//! freed, allocations commit them again.
+
+use {
+    crate::buddy_alloc::{metadata_size, BuddyAlloc, BuddyAllocParam},
+    core::{
+        alloc::{AllocError, Allocator, Layout},
+        cell::Cell,
+        ops::Range,
+        ptr::NonNull,
+    },
+    std::io,
+};
+
+/// Page level virtual memory operations used by `VmAlloc`.
+///
+/// # Safety
+///
+/// `commit` must leave its range readable and writable, keeping the contents
+/// of pages committed already. After `decommit` the contents of the range
+/// become unspecified, until committed again it needn't be accessible.
+pub unsafe trait PageBacking {
+    /// Size of a page, must be a power of two.
+    fn page_size(&self) -> usize;
+
+    /// Reserve `len` bytes of address space, committing none of it.
+    fn reserve(&self, len: usize) -> io::Result<NonNull<u8>>;
+
+    /// Commit the pages of `ptr..(ptr + len)` before they are touched.
+    ///
+    /// # Safety
+    ///
+    /// The range must be page aligned and lie within a reserved range.
+    unsafe fn commit(&self, ptr: NonNull<u8>, len: usize) -> io::Result<()>;
+
+    /// Return the pages of `ptr..(ptr + len)` to the OS.
+    ///
+    /// # Safety
+    ///
+    /// The range must be page aligned and lie within a reserved range.
+    unsafe fn decommit(&self, ptr: NonNull<u8>, len: usize);
+
+    /// Release a range returned by `reserve`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `len` must match a previous `reserve` call.
+    unsafe fn release(&self, ptr: NonNull<u8>, len: usize);
+}
+
+/// The host's page backing,
+/// mmap on unix and VirtualAlloc on windows
+#[derive(Clone, Copy, Default)]
+pub struct OsBacking;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_NORESERVE: libc::c_int = libc::MAP_NORESERVE;
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+const MAP_NORESERVE: libc::c_int = 0;
+
+#[cfg(unix)]
+unsafe impl PageBacking for OsBacking {
+    fn page_size(&self) -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    fn reserve(&self, len: usize) -> io::Result<NonNull<u8>> {
+        let p = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if p == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { NonNull::new_unchecked(p.cast()) })
+    }
+
+    unsafe fn commit(&self, ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+        // physical pages still only come on first touch
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        if libc::mprotect(ptr.as_ptr().cast(), len, prot) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    unsafe fn decommit(&self, ptr: NonNull<u8>, len: usize) {
+        libc::madvise(ptr.as_ptr().cast(), len, libc::MADV_DONTNEED);
+        libc::mprotect(ptr.as_ptr().cast(), len, libc::PROT_NONE);
+    }
+
+    unsafe fn release(&self, ptr: NonNull<u8>, len: usize) {
+        libc::munmap(ptr.as_ptr().cast(), len);
+    }
+}
+
+#[cfg(windows)]
+unsafe impl PageBacking for OsBacking {
+    fn page_size(&self) -> usize {
+        use windows_sys::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+        unsafe {
+            let mut info: SYSTEM_INFO = core::mem::zeroed();
+            GetSystemInfo(&mut info);
+            info.dwPageSize as usize
+        }
+    }
+
+    fn reserve(&self, len: usize) -> io::Result<NonNull<u8>> {
+        use windows_sys::Win32::System::Memory::{VirtualAlloc, MEM_RESERVE, PAGE_NOACCESS};
+        let p = unsafe { VirtualAlloc(core::ptr::null(), len, MEM_RESERVE, PAGE_NOACCESS) };
+        NonNull::new(p.cast()).ok_or_else(io::Error::last_os_error)
+    }
+
+    unsafe fn commit(&self, ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+        use windows_sys::Win32::System::Memory::{VirtualAlloc, MEM_COMMIT, PAGE_READWRITE};
+        // charged against the commit limit from here, physical pages come once touched
+        let p = VirtualAlloc(ptr.as_ptr().cast(), len, MEM_COMMIT, PAGE_READWRITE);
+        if p.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    unsafe fn decommit(&self, ptr: NonNull<u8>, len: usize) {
+        use windows_sys::Win32::System::Memory::{VirtualFree, MEM_DECOMMIT};
+        VirtualFree(ptr.as_ptr().cast(), len, MEM_DECOMMIT);
+    }
+
+    unsafe fn release(&self, ptr: NonNull<u8>, _len: usize) {
+        use windows_sys::Win32::System::Memory::{VirtualFree, MEM_RELEASE};
+        VirtualFree(ptr.as_ptr().cast(), 0, MEM_RELEASE);
+    }
+}
+
+/// VmAlloc
+/// a BuddyAlloc owning its reserved virtual memory range
+pub struct VmAlloc<B: PageBacking = OsBacking> {
+    backing: B,
+    base: NonNull<u8>,
+    len: usize,
+    /// one bit per page of the range, set while committed
+    committed: Box<[Cell<u64>]>,
+    /// freed blocks of at least this size get decommitted
+    decommit_threshold: Cell<usize>,
+    inner: BuddyAlloc,
+    /// the heap's bitmaps and free list heads, always committed
+    _metadata: Box<[usize]>,
+}
+
+// the heap owns its reservation, moving it moves every pointer into it
+unsafe impl<B: PageBacking + Send> Send for VmAlloc<B> {}
+
+impl VmAlloc<OsBacking> {
+    /// Reserve `len` bytes from the OS and build a heap over them.
+    /// see BuddyAllocParam::new for `leaf_size`
+    pub fn new(len: usize, leaf_size: usize) -> io::Result<Self> {
+        Self::with_backing(OsBacking, len, leaf_size)
+    }
+}
+
+impl<B: PageBacking> VmAlloc<B> {
+    /// Reserve `len` bytes through `backing` and build a heap over them.
+    pub fn with_backing(backing: B, len: usize, leaf_size: usize) -> io::Result<Self> {
+        let base = backing.reserve(len)?;
+        let pages = len.div_ceil(backing.page_size());
+        let committed: Box<[Cell<u64>]> = (0..pages.div_ceil(64)).map(|_| Cell::new(0)).collect();
+        let words = metadata_size(len, leaf_size).div_ceil(core::mem::size_of::<usize>());
+        let mut metadata = vec![0usize; words].into_boxed_slice();
+        // the free blocks of a fresh heap follow the bits of its length in
+        // leaves, BuddyAlloc::new writes the free list node of each
+        let leaf2base = leaf_size.ilog2();
+        let leaves = len >> leaf2base;
+        let mut offset = 0;
+        for bit in (0..usize::BITS)
+            .rev()
+            .filter(|bit| leaves & (1 << bit) != 0)
+        {
+            commit_pages(&backing, base, &committed, offset, 1)?;
+            offset += 1 << (bit + leaf2base);
+        }
+        let param = BuddyAllocParam::new_with_metadata(
+            base.as_ptr(),
+            len,
+            leaf_size,
+            metadata.as_mut_ptr().cast(),
+            words * core::mem::size_of::<usize>(),
+        );
+        let inner = unsafe { BuddyAlloc::new(param) };
+        Ok(VmAlloc {
+            backing,
+            base,
+            len,
+            committed,
+            decommit_threshold: Cell::new(usize::MAX),
+            inner,
+            _metadata: metadata,
+        })
+    }
+
+    /// reserved bytes
+    pub fn reserved_bytes(&self) -> usize {
+        self.len
+    }
+
+    /// bytes of the range committed now
+    pub fn committed_bytes(&self) -> usize {
+        let pages: u32 = self
+            .committed
+            .iter()
+            .map(|word| word.get().count_ones())
+            .sum();
+        pages as usize * self.backing.page_size()
+    }
+
+    /// available bytes
+    pub fn available_bytes(&self) -> usize {
+        self.inner.available_bytes()
+    }
+
+    /// Decommit the interior pages of freed blocks of at least `bytes`,
+    /// after merging with their buddies. Disabled by default,
+    /// pass `usize::MAX` to disable it again.
+    pub fn set_decommit_threshold(&self, bytes: usize) {
+        self.decommit_threshold.set(bytes);
+    }
+
+    /// Decommit the interior pages of every free block,
+    /// returns the number of committed bytes handed back to the OS.
+    pub fn trim(&self) -> usize {
+        let mut trimmed = 0;
+        self.inner.for_each_free_block(|addr, size| {
+            trimmed += unsafe { self.decommit_interior(addr, size) };
+        });
+        trimmed
+    }
+
+    /// Decommit the committed whole pages of `addr..(addr + size)`, leaving
+    /// the page that holds the free list node in place.
+    unsafe fn decommit_interior(&self, addr: usize, size: usize) -> usize {
+        let page_size = self.backing.page_size();
+        let offset = addr - self.base.as_ptr().addr();
+        let first = (offset + 1).div_ceil(page_size);
+        let last = (offset + size) / page_size;
+        let mut decommitted = 0;
+        for pages in runs(&self.committed, first..last, true) {
+            self.backing
+                .decommit(self.page_ptr(pages.start), pages.len() * page_size);
+            mark(&self.committed, pages.clone(), false);
+            decommitted += pages.len() * page_size;
+        }
+        decommitted
+    }
+
+    /// Commit the pages of `addr..(addr + len)` not committed yet.
+    fn commit(&self, addr: usize, len: usize) -> Result<(), AllocError> {
+        let offset = addr - self.base.as_ptr().addr();
+        commit_pages(&self.backing, self.base, &self.committed, offset, len).map_err(|_| AllocError)
+    }
+
+    fn page_ptr(&self, page: usize) -> NonNull<u8> {
+        let p = self
+            .base
+            .as_ptr()
+            .wrapping_add(page * self.backing.page_size());
+        unsafe { NonNull::new_unchecked(p) }
+    }
+}
+
+/// Commit the pages of `offset..(offset + len)` from `base` that `committed`
+/// doesn't have yet, and note them there
+fn commit_pages<B: PageBacking>(
+    backing: &B,
+    base: NonNull<u8>,
+    committed: &[Cell<u64>],
+    offset: usize,
+    len: usize,
+) -> io::Result<()> {
+    let page_size = backing.page_size();
+    let pages = offset / page_size..(offset + len).div_ceil(page_size);
+    for pages in runs(committed, pages, false) {
+        let p = base.as_ptr().wrapping_add(pages.start * page_size);
+        unsafe { backing.commit(NonNull::new_unchecked(p), pages.len() * page_size)? };
+        mark(committed, pages, true);
+    }
+    Ok(())
+}
+
+/// the runs of consecutive pages in `pages` whose bit is `set`
+fn runs(
+    bits: &[Cell<u64>],
+    pages: Range<usize>,
+    set: bool,
+) -> impl Iterator<Item = Range<usize>> + '_ {
+    let end = pages.end;
+    let is = move |page: usize| (bits[page / 64].get() >> (page % 64) & 1 == 1) == set;
+    let mut page = pages.start;
+    core::iter::from_fn(move || {
+        while page < end && !is(page) {
+            page += 1;
+        }
+        let start = page;
+        while page < end && is(page) {
+            page += 1;
+        }
+        (start < page).then_some(start..page)
+    })
+}
+
+fn mark(bits: &[Cell<u64>], pages: Range<usize>, set: bool) {
+    for page in pages {
+        let word = &bits[page / 64];
+        let bit = 1 << (page % 64);
+        word.set(if set {
+            word.get() | bit
+        } else {
+            word.get() & !bit
+        });
+    }
+}
+
+impl<B: PageBacking> Drop for VmAlloc<B> {
+    fn drop(&mut self) {
+        unsafe { self.backing.release(self.base, self.len) };
+    }
+}
+
+/// Allocations commit the pages they write first: the block handed out and
+/// the free list nodes of the halves split off it.
+unsafe impl<B: PageBacking> Allocator for VmAlloc<B> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if let Some((addr, size, block)) = self.inner.next_carve(layout) {
+            let mut half = block;
+            while half < size {
+                self.commit(addr + half, 1)?;
+                half *= 2;
+            }
+            self.commit(addr, block)?;
+        }
+        self.inner.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        let (addr, size) = self.inner.free_block(ptr.as_ptr());
+        if size >= self.decommit_threshold.get() {
+            self.decommit_interior(addr, size);
+        }
+    }
+}